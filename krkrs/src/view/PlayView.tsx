@@ -1,18 +1,53 @@
-import { useEffect, useState } from 'react'
+import { useCallback, useEffect, useState } from 'react'
 import * as krkrs from 'krkrs';
 import './playView.css'
-import TextDisplay from './component/TextDisplay';
+import TextDisplay, { Line } from './component/TextDisplay';
 import ImageDisplay from './component/ImageDisplay';
+import BacklogView from './component/BacklogView';
+
+type Mode = 'normal' | 'auto' | 'skipRead' | 'skipAll';
+
+// archives served next to the scenario, mounted in order if present.
+const archives = ['data.xp3', 'patch.xp3'];
+
+async function loadAssets(): Promise<krkrs.Assets> {
+    const assets = new krkrs.Assets();
+    for (const name of archives) {
+        const resp = await fetch(`/${name}`);
+        if (!resp.ok) {
+            continue;
+        }
+        try {
+            assets.mount(new Uint8Array(await resp.arrayBuffer()));
+        } catch (err) {
+            // the dev server answers missing files with the index page.
+            console.log(`${name}: ${err}`);
+        }
+    }
+    return assets;
+}
 
 type RenderContext = {
-    'text': string[];
+    'text': Line[];
     'scene': string[];
+    'mode': Mode;
 }
 
+// keys that switch auto mode and skip mode.
+const modeKeys: Record<string, string> = {
+    'a': 'Auto',
+    's': 'Skip',
+    'S': 'SkipAll',
+};
+
 function PlayView() {
     const [krkri, setKrkrs] = useState<krkrs.App>();
-    const [text, setText] = useState(['unloaded']);
-    const [image, setImage] = useState('unloaded');
+    const [text, setText] = useState<Line[]>([]);
+    const [image, setImage] = useState<string>();
+    const [assets, setAssets] = useState<krkrs.Assets>();
+    const [renders, setRenders] = useState(0);
+    const [mode, setMode] = useState<Mode>('normal');
+    const [showBacklog, setShowBacklog] = useState(false);
 
     async function initKrkrs() {
         if (krkri) {
@@ -22,17 +57,50 @@ function PlayView() {
             console.log('rendering');
             console.log(ctx);
             setText(ctx['text']);
+            setRenders((n) => n + 1);
             setImage(ctx['scene'][0]);
+            setMode(ctx['mode']);
         });
         setKrkrs(k);
     }
 
     initKrkrs();
 
+    useEffect(() => {
+        loadAssets().then(setAssets).catch((err) => console.log(err));
+    }, []);
+
+    const handleRevealed = useCallback(() => {
+        krkri?.handle_web_input("TextRevealed")
+    }, [krkri]);
+
+    // drives the interpreter's clock in real time.
+    useEffect(() => {
+        let last = performance.now();
+        let frame = requestAnimationFrame(function step(now) {
+            krkri?.tick(Math.round(now - last));
+            last = now;
+            frame = requestAnimationFrame(step);
+        });
+        return () => cancelAnimationFrame(frame);
+    }, [krkri]);
+
     useEffect(
         () => {
             const handleKey = (e: KeyboardEvent) => {
-                krkri?.handle_web_input(e.key)
+                if (e.key === 'b') {
+                    setShowBacklog((shown) => !shown);
+                    return;
+                }
+                if (e.key === 'r') {
+                    try {
+                        krkri?.rollback(1);
+                    } catch (err) {
+                        console.log(err);
+                    }
+                    return;
+                }
+                krkri?.handle_web_input(modeKeys[e.key] ?? e.key)
             }
             document.addEventListener('keyup', handleKey);
 
@@ -47,8 +115,10 @@ function PlayView() {
                     e.preventDefault();
                     krkri?.handle_web_input("MouseClick")
                 }}>
-                <ImageDisplay imageSrc={image} />
-                <TextDisplay text={text} />
+                <ImageDisplay storage={image} assets={assets} />
+                {mode !== 'normal' && <div className="absolute top-4 right-4 text-white">{mode}</div>}
+                <TextDisplay key={renders} text={text} onRevealed={handleRevealed} />
+                {showBacklog && krkri && <BacklogView app={krkri} onClose={() => setShowBacklog(false)} />}
             </div>
         </>
     )
@@ -0,0 +1,49 @@
+// This component shows the text the player has gone through, a page at a time.
+import { useState } from 'react';
+import * as krkrs from 'krkrs';
+import type { Line } from './TextDisplay';
+import './BacklogView.css'
+
+export type BacklogEntry = {
+    'speaker': string | null;
+    'lines': Line[];
+    'voice': string | null;
+    // where the entry starts in the script
+    'position': { 'storage': string, 'label': string, 'index': number };
+}
+
+const PER_PAGE = 8;
+
+const BacklogView = ({ app, onClose }: { app: krkrs.App, onClose: () => void }) => {
+    // page 0 is the newest
+    const [page, setPage] = useState(0);
+    const pages = app.backlog_pages(PER_PAGE);
+    const entries: BacklogEntry[] = app.backlog_page(page, PER_PAGE);
+
+    const items = entries.map((entry, i) => (
+        <div key={i} className="pb-4">
+            {entry.speaker && <div className="backlog-speaker">{entry.speaker}</div>}
+            {entry.lines.map((line, j) => (
+                <p key={j}>{line.runs.map((run) => run.text).join('')}</p>
+            ))}
+            {entry.voice && (
+                <button onClick={() => new Audio(`/voice/${entry.voice}.ogg`).play()}>
+                    ▶
+                </button>
+            )}
+        </div>
+    ));
+
+    return (
+        <div className="backlog-view p-12" onMouseDown={(e) => e.stopPropagation()}>
+            <nav className="flex flex-row justify-between pb-4">
+                <button disabled={page + 1 >= pages} onClick={() => setPage(page + 1)}>older</button>
+                <button onClick={onClose}>close</button>
+                <button disabled={page === 0} onClick={() => setPage(page - 1)}>newer</button>
+            </nav>
+            {items}
+        </div>
+    )
+}
+
+export default BacklogView;
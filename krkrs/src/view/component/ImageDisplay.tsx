@@ -1,10 +1,33 @@
 // This component displays image in a stack manner
+import { useEffect, useState } from 'react';
+import * as krkrs from 'krkrs';
 import './image-displayer.css'
 
-const ImageDisplay = ({ imageSrc }: { imageSrc: string }) => {
+// `storage` is a name from the script. Mounted archives are asked first,
+// then the server's `bgimage` directory.
+const ImageDisplay = ({ storage, assets }: { storage?: string, assets?: krkrs.Assets }) => {
+    const [imageSrc, setImageSrc] = useState<string>();
+
+    useEffect(() => {
+        if (!storage) {
+            setImageSrc(undefined);
+            return;
+        }
+        if (!assets?.exists(storage)) {
+            setImageSrc(`/bgimage/${storage}.png`);
+            return;
+        }
+        const url = assets.object_url(storage);
+        setImageSrc(url);
+        return () => URL.revokeObjectURL(url);
+    }, [storage, assets]);
+
+    if (!imageSrc) {
+        return null;
+    }
     return (
         <img className="image-displayer" src={imageSrc} alt="background" />
     )
 }
 
-export default ImageDisplay;
\ No newline at end of file
+export default ImageDisplay;
@@ -1,11 +1,110 @@
 // This component displays the text in a grey box.
+import { useEffect, useState, type CSSProperties } from 'react';
 import './TextDisplay.css'
 
-const TextDisplay = ({ text }: { text: string[] }) => {
-    const paragraphs = text.map((p, i) => {
+export type Font = {
+    'face': string | null;
+    'size': number | null;
+    'color': string | null;
+    'bold': boolean | null;
+    'italic': boolean | null;
+    'shadow': boolean | null;
+    'shadowColor': string | null;
+    'edge': boolean | null;
+    'edgeColor': string | null;
+}
+
+export type TextRun = {
+    'text': string;
+    'ruby': string | null;
+    'font': Font;
+    // milliseconds between two characters
+    'delay': number;
+    // characters already on screen
+    'revealed': number;
+    // seen in an earlier play
+    'read': boolean;
+}
+
+export type Line = {
+    'align': 'left' | 'center' | 'right';
+    'runs': TextRun[];
+}
+
+// unset font fields fall back to the stylesheet.
+const fontStyle = (font: Font): CSSProperties => {
+    const style: CSSProperties = {};
+    if (font.face !== null) style.fontFamily = font.face;
+    if (font.size !== null) style.fontSize = `${font.size}px`;
+    if (font.color !== null) style.color = font.color;
+    if (font.bold !== null) style.fontWeight = font.bold ? 'bold' : 'normal';
+    if (font.italic !== null) style.fontStyle = font.italic ? 'italic' : 'normal';
+    if (font.shadow === false) style.textShadow = 'none';
+    if (font.shadow && font.shadowColor !== null) style.textShadow = `2px 2px 2px ${font.shadowColor}`;
+    if (font.edge) style.WebkitTextStroke = `1px ${font.edgeColor ?? 'black'}`;
+    return style;
+}
+
+// how many characters of each run are visible after `elapsed` milliseconds.
+// runs are typed one after another; returns the time the last one finishes.
+const schedule = (text: Line[], elapsed: number): [number[][], number] => {
+    let t = 0;
+    const visible = text.map((line) => line.runs.map((run) => {
+        const length = Array.from(run.text).length;
+        const pending = length - run.revealed;
+        if (pending <= 0 || run.delay === 0) {
+            return length;
+        }
+        const typed = Math.max(0, Math.floor((elapsed - t) / run.delay));
+        t += pending * run.delay;
+        return run.revealed + Math.min(pending, typed);
+    }));
+    return [visible, t];
+}
+
+// the parent remounts this component for every render, so typing starts over.
+const TextDisplay = ({ text, onRevealed }: { text: Line[], onRevealed: () => void }) => {
+    const [elapsed, setElapsed] = useState(0);
+    const [visible, total] = schedule(text, elapsed);
+
+    // the frames stop once the last run is typed.
+    useEffect(() => {
+        if (total === 0) {
+            return;
+        }
+        const start = performance.now();
+        let frame = requestAnimationFrame(function step(now) {
+            setElapsed(now - start);
+            if (now - start < total) {
+                frame = requestAnimationFrame(step);
+            }
+        });
+        return () => cancelAnimationFrame(frame);
+    }, [total]);
+
+    // nothing to report when everything was already on screen.
+    const done = total > 0 && elapsed >= total;
+    useEffect(() => {
+        if (done) {
+            onRevealed();
+        }
+    }, [done, onRevealed]);
+
+    const paragraphs = text.map((line, i) => {
+        const runs = line.runs.map((run, j) => {
+            const shown = Array.from(run.text).slice(0, visible[i][j]).join('');
+            if (run.ruby) {
+                return (
+                    <ruby key={j} style={fontStyle(run.font)}>
+                        {shown}<rt>{shown ? run.ruby : ''}</rt>
+                    </ruby>
+                )
+            }
+            return <span key={j} className={run.read ? 'read' : undefined} style={fontStyle(run.font)}>{shown}</span>
+        });
         return (
-            <p key={i} className="pb-4">
-                {p}
+            <p key={i} className="pb-4" style={{ textAlign: line.align }}>
+                {runs}
             </p>
         )
     });
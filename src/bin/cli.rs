@@ -1,7 +1,368 @@
 //! cli interface
-use krkrs::interface::cli::App;
+use std::{error::Error, fs, path::PathBuf, process};
+
+use encoding_rs::Encoding;
+use krkrs::{
+    interface::cli::{
+        check, extract_strings, flow_graph, format_files, inject_strings, lint, open_game,
+        print_tokens, translate, App,
+    },
+    vfs::{extract::Extract, xp3::Xp3},
+};
+
+const USAGE: &str = "usage: krkrs-cli <command> [options]
+
+commands:
+    play <game dir|xp3|ks>     play a game in the terminal
+        --start-label <label>  start from this label
+        --storage <ks>         start with this scenario instead of first.ks
+        --encoding <name>      encoding of scenarios without a BOM
+    tokens <ks>                print the tokens of a scenario
+        --encoding <name>
+    check <game dir|xp3|ks>    parse every scenario of a game
+        --encoding <name>
+    lint <game dir|xp3|ks>     find unknown tags, broken jumps and missing assets
+        --tag <name>           a tag defined outside scenarios, may be repeated
+        --encoding <name>
+    graph <game dir|xp3|ks>    print the flow graph of labels, unreachable ones in red
+        --format <dot|json>    dot by default
+        --storage <ks>         start with this scenario instead of first.ks
+        --encoding <name>
+    strings <game dir|xp3|ks>  extract the text of scenarios for translation
+        --format <po|csv>      po by default
+        --output, -o <file>    write there instead of to the standard output
+        --encoding <name>
+    inject <game dir|xp3|ks> <po|csv> <out dir>
+                               write the scenarios with translations into <out dir>
+        --encoding <name>
+    fmt <dir|ks>...            format scenarios in place
+        --check                only list the ones not formatted, failing if any
+        --encoding <name>
+    extract <xp3> [out dir]    unpack an archive, into <xp3> without extension
+        --list, -l             only list the files
+        --glob, -g <pattern>   only the files matching, may be repeated
+        --png                  convert TLG images to PNG
+        --utf8                 convert scenarios to UTF-8";
 
 fn main() {
-    let mut app = App::new_cli_from_ks("public/lorerei.ks");
-    app.run();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.split_first() {
+        Some((command, args)) => match command.as_str() {
+            "play" => play(args),
+            "tokens" => tokens(args),
+            "check" => check_game(args),
+            "lint" => lint_game(args),
+            "graph" => graph(args),
+            "fmt" => fmt(args),
+            "strings" => strings(args),
+            "inject" => inject(args),
+            "extract" => extract(args),
+            "help" | "--help" | "-h" => {
+                println!("{}", USAGE);
+                Ok(())
+            }
+            _ => Err(format!("unknown command {}\n\n{}", command, USAGE).into()),
+        },
+        None => Err(USAGE.into()),
+    };
+    if let Err(e) = result {
+        eprintln!("krkrs-cli: {}", e);
+        process::exit(1);
+    }
+}
+
+/// an option of a command: its long name, its short name and whether it
+/// takes a value.
+type Spec = (&'static str, Option<&'static str>, bool);
+
+const ENCODING: Spec = ("--encoding", None, true);
+
+/// the positional arguments and the options given, options by long name.
+#[derive(Debug, Default)]
+struct Args {
+    positional: Vec<String>,
+    options: Vec<(&'static str, Option<String>)>,
+}
+
+impl Args {
+    fn parse(args: &[String], specs: &[Spec]) -> Result<Args, Box<dyn Error>> {
+        let mut parsed = Args::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with('-') || arg == "-" {
+                parsed.positional.push(arg.clone());
+                continue;
+            }
+            let (name, inline) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (arg.as_str(), None),
+            };
+            let &(long, _, takes_value) = specs
+                .iter()
+                .find(|(long, short, _)| *long == name || *short == Some(name))
+                .ok_or_else(|| format!("unknown option {}", name))?;
+            let value = match (takes_value, inline) {
+                (true, Some(value)) => Some(value),
+                (true, None) => Some(
+                    args.next()
+                        .ok_or_else(|| format!("{} needs a value", long))?
+                        .clone(),
+                ),
+                (false, Some(_)) => return Err(format!("{} takes no value", long).into()),
+                (false, None) => None,
+            };
+            parsed.options.push((long, value));
+        }
+        Ok(parsed)
+    }
+
+    fn flag(&self, long: &str) -> bool {
+        self.options.iter().any(|(name, _)| *name == long)
+    }
+
+    /// the last value given for the option.
+    fn value(&self, long: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .rev()
+            .find(|(name, _)| *name == long)
+            .and_then(|(_, value)| value.as_deref())
+    }
+
+    fn values(&self, long: &str) -> Vec<String> {
+        self.options
+            .iter()
+            .filter(|(name, _)| *name == long)
+            .filter_map(|(_, value)| value.clone())
+            .collect()
+    }
+
+    fn encoding(&self) -> Result<Option<&'static Encoding>, Box<dyn Error>> {
+        self.value(ENCODING.0)
+            .map(|label| {
+                Encoding::for_label(label.as_bytes())
+                    .ok_or_else(|| format!("unknown encoding {}", label).into())
+            })
+            .transpose()
+    }
+
+    /// the only positional argument.
+    fn single(&self, what: &str) -> Result<PathBuf, Box<dyn Error>> {
+        match self.positional.as_slice() {
+            [path] => Ok(PathBuf::from(path)),
+            _ => Err(format!("expected one {}\n\n{}", what, USAGE).into()),
+        }
+    }
+}
+
+fn play(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let args = Args::parse(
+        args,
+        &[
+            ("--start-label", None, true),
+            ("--storage", None, true),
+            ENCODING,
+        ],
+    )?;
+    let (storage, first) = open_game(&args.single("game")?)?;
+    let start = args.value("--storage").unwrap_or(&first).to_string();
+    let mut app = App::new_cli(
+        storage,
+        &start,
+        args.value("--start-label"),
+        args.encoding()?,
+    )?;
+    app.run()
+}
+
+fn tokens(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let args = Args::parse(args, &[ENCODING])?;
+    print_tokens(&args.single("scenario")?, args.encoding()?)
+}
+
+fn check_game(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let args = Args::parse(args, &[ENCODING])?;
+    let (storage, _) = open_game(&args.single("game")?)?;
+    let errors = check(&storage, args.encoding()?);
+    for (storage, e) in &errors {
+        eprintln!("{}: {}", storage, e);
+    }
+    if !errors.is_empty() {
+        return Err(format!("{} scenarios do not parse", errors.len()).into());
+    }
+    Ok(())
+}
+
+fn lint_game(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let args = Args::parse(args, &[("--tag", None, true), ENCODING])?;
+    let (storage, _) = open_game(&args.single("game")?)?;
+    let diagnostics = lint(&storage, args.encoding()?, &args.values("--tag"));
+    for d in &diagnostics {
+        println!("{}", d);
+    }
+    if !diagnostics.is_empty() {
+        return Err(format!("{} problems found", diagnostics.len()).into());
+    }
+    Ok(())
+}
+
+fn graph(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let args = Args::parse(
+        args,
+        &[
+            ("--format", None, true),
+            ("--storage", None, true),
+            ENCODING,
+        ],
+    )?;
+    let (storage, first) = open_game(&args.single("game")?)?;
+    let start = args.value("--storage").unwrap_or(&first);
+    let graph = flow_graph(&storage, start, args.encoding()?)?;
+    match args.value("--format").unwrap_or("dot") {
+        "dot" => print!("{}", graph.to_dot()),
+        "json" => print!("{}", graph.to_json()),
+        format => return Err(format!("unknown format {}", format).into()),
+    }
+    Ok(())
+}
+
+fn fmt(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let args = Args::parse(args, &[("--check", None, false), ENCODING])?;
+    if args.positional.is_empty() {
+        return Err(format!("expected scenarios\n\n{}", USAGE).into());
+    }
+    let check = args.flag("--check");
+    let mut unformatted = 0;
+    for path in &args.positional {
+        for file in format_files(&PathBuf::from(path), check, args.encoding()?)? {
+            if check {
+                println!("{}", file.display());
+            }
+            unformatted += 1;
+        }
+    }
+    if check && unformatted > 0 {
+        return Err(format!("{} scenarios are not formatted", unformatted).into());
+    }
+    Ok(())
+}
+
+fn strings(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let args = Args::parse(
+        args,
+        &[
+            ("--format", None, true),
+            ("--output", Some("-o"), true),
+            ENCODING,
+        ],
+    )?;
+    let (storage, _) = open_game(&args.single("game")?)?;
+    let entries = extract_strings(&storage, args.encoding()?)?;
+    let out = match args.value("--format").unwrap_or("po") {
+        "po" => translate::to_po(&entries),
+        "csv" => translate::to_csv(&entries),
+        format => return Err(format!("unknown format {}", format).into()),
+    };
+    match args.value("--output") {
+        Some(path) => fs::write(path, out)?,
+        None => print!("{}", out),
+    }
+    Ok(())
+}
+
+fn inject(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let args = Args::parse(args, &[ENCODING])?;
+    let (game, file, out) = match args.positional.as_slice() {
+        [game, file, out] => (game, PathBuf::from(file), PathBuf::from(out)),
+        _ => {
+            return Err(
+                format!("expected a game, translations and a directory\n\n{}", USAGE).into(),
+            )
+        }
+    };
+    let (storage, _) = open_game(&PathBuf::from(game))?;
+    let text = fs::read_to_string(&file)?;
+    let is_csv = file
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("csv"));
+    let translations = if is_csv {
+        translate::read_csv(&text)
+    } else {
+        translate::read_po(&text)
+    }
+    .map_err(|e| format!("{}: {}", file.display(), e))?;
+    let injected = inject_strings(&storage, args.encoding()?, &translations, &out)?;
+    for location in &injected.stale {
+        eprintln!(
+            "{}: text has changed since it was translated, left as is",
+            location
+        );
+    }
+    for location in &injected.refused {
+        eprintln!(
+            "{}: translation would start a label or comment, or go on to the next line, left as is",
+            location
+        );
+    }
+    println!(
+        "{} translations written to {}",
+        injected.applied,
+        out.display()
+    );
+    Ok(())
+}
+
+/// lists or unpacks the files of an archive that match the globs.
+fn extract(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let args = Args::parse(
+        args,
+        &[
+            ("--list", Some("-l"), false),
+            ("--glob", Some("-g"), true),
+            ("--png", None, false),
+            ("--utf8", None, false),
+        ],
+    )?;
+    let options = Extract {
+        globs: args.values("--glob"),
+        png: args.flag("--png"),
+        utf8: args.flag("--utf8"),
+    };
+    let (archive, out) = match args.positional.as_slice() {
+        [archive] => (
+            PathBuf::from(archive),
+            PathBuf::from(archive).with_extension(""),
+        ),
+        [archive, out] => (PathBuf::from(archive), PathBuf::from(out)),
+        _ => return Err(format!("expected an archive\n\n{}", USAGE).into()),
+    };
+
+    let xp3 = Xp3::open(&archive)?;
+    let entries = xp3.entries().iter().filter(|e| options.matches(&e.name));
+    if args.flag("--list") {
+        for e in entries {
+            let protected = if e.protected { " protected" } else { "" };
+            println!("{:>10} {:>10}{} {}", e.size, e.packed, protected, e.name);
+        }
+        return Ok(());
+    }
+    let mut count = 0;
+    for e in entries {
+        let (path, data) = match options.convert(&e.name, xp3.read(e)?) {
+            Ok(converted) => converted,
+            Err(err) => {
+                // keep the original bytes of what does not convert.
+                eprintln!("krkrs-cli: {}: {}, extracted as is", e.name, err);
+                Extract::default().convert(&e.name, xp3.read(e)?)?
+            }
+        };
+        let path = out.join(path);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, data)?;
+        count += 1;
+    }
+    println!("{} files extracted to {}", count, out.display());
+    Ok(())
 }
@@ -0,0 +1,26 @@
+//! language server
+use std::{
+    io::{self, BufReader},
+    process,
+};
+
+use krkrs::interface::lsp::{parse_error, read_message, write_message, Server};
+
+fn main() -> io::Result<()> {
+    let mut input = BufReader::new(io::stdin());
+    let mut output = io::stdout();
+    let mut server = Server::new();
+    while let Some(message) = read_message(&mut input)? {
+        let replies = match message {
+            Ok(message) => server.handle(&message),
+            Err(e) => vec![parse_error(&e)],
+        };
+        for reply in replies {
+            write_message(&mut output, &reply)?;
+        }
+        if let Some(code) = server.exit_code() {
+            process::exit(code);
+        }
+    }
+    Ok(())
+}
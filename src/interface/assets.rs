@@ -0,0 +1,61 @@
+use js_sys::{Array, Uint8Array};
+use wasm_bindgen::{prelude::wasm_bindgen, Clamped, JsValue};
+use web_sys::{Blob, BlobPropertyBag, ImageData, Url};
+
+use crate::vfs::{
+    storage::{Storage, IMAGE_EXTS},
+    tlg,
+    xp3::Xp3,
+};
+
+/// archives the front end fetched, served to `<img>` and `<canvas>` as the
+/// browser needs them.
+#[wasm_bindgen]
+#[derive(Debug, Default)]
+pub struct Assets {
+    storage: Storage,
+}
+
+fn js_error(e: impl ToString) -> JsValue {
+    JsValue::from(e.to_string())
+}
+
+#[wasm_bindgen]
+impl Assets {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Assets {
+        Assets::default()
+    }
+
+    /// mounts the bytes of an `.xp3`, later archives shadow earlier ones.
+    pub fn mount(&mut self, xp3: Vec<u8>) -> Result<(), JsValue> {
+        self.storage.mount_archive(Xp3::new(xp3).map_err(js_error)?);
+        Ok(())
+    }
+
+    pub fn exists(&self, path: &str) -> bool {
+        self.storage.exists(path, IMAGE_EXTS)
+    }
+
+    /// an object URL of the asset, TLG images turned into PNG on the way.
+    /// The caller revokes it with `URL.revokeObjectURL`.
+    pub fn object_url(&self, path: &str) -> Result<String, JsValue> {
+        let (mime, data) = self.storage.transcode(path, IMAGE_EXTS).map_err(js_error)?;
+        let parts = Array::of1(&Uint8Array::from(&data[..]));
+        let options = BlobPropertyBag::new();
+        options.set_type(&mime);
+        let blob = Blob::new_with_u8_array_sequence_and_options(&parts, &options)?;
+        Url::create_object_url_with_blob(&blob)
+    }
+
+    /// the pixels of a TLG image, ready for `putImageData`.
+    pub fn image_data(&self, path: &str) -> Result<ImageData, JsValue> {
+        let data = self.storage.read(path, &["tlg"]).map_err(js_error)?;
+        let image = tlg::decode(&data).map_err(js_error)?;
+        ImageData::new_with_u8_clamped_array_and_sh(
+            Clamped(&image.pixels),
+            image.width,
+            image.height,
+        )
+    }
+}
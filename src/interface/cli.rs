@@ -1,13 +1,229 @@
 //! Command line interface for the application.
-pub use crate::{interface::App, interpreter::interpreter::State, presentation::cli::KrkrsCli};
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use encoding_rs::Encoding;
+
+pub use crate::{
+    interface::App,
+    interpreter::{
+        cst,
+        format::format,
+        graph::Graph,
+        interpreter::State,
+        lint::lint,
+        translate::{self, Entry, Translation},
+    },
+    presentation::cli::KrkrsCli,
+};
+use crate::{
+    interpreter::{lint, scenario::Scenario},
+    vfs::{storage::Storage, text, xp3::Xp3},
+};
+
+/// the scenario krkr starts a game with.
+const FIRST_SCENARIO: &str = "first.ks";
+
+/// the storage of a game directory, of an archive or of a scenario alone,
+/// and the scenario to start with. The directory of a scenario is not
+/// walked, it may be any directory, the home one as well.
+pub fn open_game(path: &Path) -> Result<(Storage, String), Box<dyn Error>> {
+    let mut storage = Storage::new();
+    if path.is_dir() {
+        // loose files override the archives next to them.
+        let mut archives: Vec<_> = fs::read_dir(path)?
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|e| e.eq_ignore_ascii_case("xp3")))
+            .collect();
+        archives.sort();
+        for archive in archives {
+            storage.mount_archive(Xp3::open(archive)?);
+        }
+        storage.mount_dir(path)?;
+        return Ok((storage, FIRST_SCENARIO.to_string()));
+    }
+    if path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("xp3"))
+    {
+        storage.mount_archive(Xp3::open(path)?);
+        return Ok((storage, FIRST_SCENARIO.to_string()));
+    }
+    storage.mount_file(path)?;
+    let start = storage.files().remove(0);
+    Ok((storage, start))
+}
+
+/// prints the tokens of a scenario file with their line numbers.
+pub fn print_tokens(
+    path: &Path,
+    encoding: Option<&'static Encoding>,
+) -> Result<(), Box<dyn Error>> {
+    let source = text::decode(&fs::read(path)?, encoding)?;
+    let scenario = Scenario::parse(&path.to_string_lossy(), &source)?;
+    let mut pc = 0;
+    while let Some(token) = scenario.get(pc) {
+        println!("{:>5} {:?}", scenario.line(pc).unwrap_or_default(), token);
+        pc += 1;
+    }
+    Ok(())
+}
+
+/// parses every scenario of the storage, returning the ones that fail.
+pub fn check(
+    storage: &Storage,
+    encoding: Option<&'static Encoding>,
+) -> Vec<(String, Box<dyn Error>)> {
+    storage
+        .files()
+        .into_iter()
+        .filter(|f| f.to_lowercase().ends_with(".ks"))
+        .filter_map(|f| {
+            let parsed = storage
+                .read(&f, &[])
+                .and_then(|data| Ok(text::decode(&data, encoding)?))
+                .and_then(|source| Scenario::parse(&f, &source).map(|_| ()));
+            parsed.err().map(|e| (f, e))
+        })
+        .collect()
+}
+
+/// the flow graph of every scenario of the storage, reached from `start`.
+pub fn flow_graph(
+    storage: &Storage,
+    start: &str,
+    encoding: Option<&'static Encoding>,
+) -> Result<Graph, Box<dyn Error>> {
+    let (scenarios, diagnostics) = lint::load(storage, encoding);
+    if let Some(d) = diagnostics.first() {
+        return Err(d.to_string().into());
+    }
+    Ok(Graph::build(
+        &scenarios,
+        |name| storage.resolve(name, &["ks"]),
+        start,
+    ))
+}
+
+/// the text of every scenario of the storage.
+pub fn extract_strings(
+    storage: &Storage,
+    encoding: Option<&'static Encoding>,
+) -> Result<Vec<Entry>, Box<dyn Error>> {
+    let mut entries = Vec::new();
+    for file in storage.files() {
+        if file.to_lowercase().ends_with(".ks") {
+            let source = text::decode(&storage.read(&file, &[])?, encoding)?;
+            entries.extend(translate::extract(&file, &source)?);
+        }
+    }
+    Ok(entries)
+}
+
+/// what `inject_strings` did.
+#[derive(Debug, Default)]
+pub struct Injected {
+    /// translations put in.
+    pub applied: usize,
+    /// locations whose text has changed since it was translated.
+    pub stale: Vec<String>,
+    /// locations whose translation cannot be written as text.
+    pub refused: Vec<String>,
+}
+
+/// writes the scenarios of the storage with translations into `out`, in the
+/// encoding they come in.
+pub fn inject_strings(
+    storage: &Storage,
+    encoding: Option<&'static Encoding>,
+    translations: &[Translation],
+    out: &Path,
+) -> Result<Injected, Box<dyn Error>> {
+    let mut injected = Injected::default();
+    for file in storage.files() {
+        let prefix = format!("{}:", file);
+        if !translations.iter().any(|t| t.location.starts_with(&prefix)) {
+            continue;
+        }
+        let data = storage.read(&file, &[])?;
+        let source = text::decode(&data, encoding)?;
+        let reinjection = translate::reinject(&file, &source, translations)?;
+        injected.stale.extend(reinjection.stale);
+        injected.refused.extend(reinjection.refused);
+        if reinjection.applied > 0 {
+            injected.applied += reinjection.applied;
+            let path = out.join(&file);
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(path, text::encode(&reinjection.text, &data, encoding))?;
+        }
+    }
+    Ok(injected)
+}
+
+/// formats the scenarios under `path`, or only checks them. Returns the
+/// files that are not formatted, and stops at one that cannot be.
+pub fn format_files(
+    path: &Path,
+    check: bool,
+    encoding: Option<&'static Encoding>,
+) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let files = if path.is_dir() {
+        let mut storage = Storage::new();
+        storage.mount_dir(path)?;
+        storage
+            .files()
+            .into_iter()
+            .filter(|f| f.to_lowercase().ends_with(".ks"))
+            .map(|f| path.join(f))
+            .collect()
+    } else {
+        vec![path.to_path_buf()]
+    };
+    let mut unformatted = Vec::new();
+    for file in files {
+        let data = fs::read(&file)?;
+        let source = text::decode(&data, encoding)?;
+        let formatted = format(&source).map_err(|e| format!("{}: {}", file.display(), e))?;
+        if formatted == source {
+            continue;
+        }
+        if !check {
+            fs::write(&file, text::encode(&formatted, &data, encoding))?;
+        }
+        unformatted.push(file);
+    }
+    Ok(unformatted)
+}
 
 impl App {
-    pub fn new_cli_from_ks(filename: &str) -> App {
-        let state = State::new_from_ks(filename);
-        App {
+    /// milliseconds the clock jumps between two inputs.
+    const FAST_FORWARD: u64 = 60 * 60 * 1000;
+    /// backlog entries shown by `b`.
+    const BACKLOG_PAGE: usize = 10;
+
+    pub fn new_cli_from_ks(filename: &str) -> Result<App, Box<dyn Error>> {
+        Ok(App {
             ui: Box::new(KrkrsCli {}),
-            state,
-        }
+            state: State::new_from_ks(filename)?,
+        })
+    }
+
+    /// plays `start` of the storage from `label` on.
+    pub fn new_cli(
+        storage: Storage,
+        start: &str,
+        label: Option<&str>,
+        encoding: Option<&'static Encoding>,
+    ) -> Result<App, Box<dyn Error>> {
+        Ok(App {
+            ui: Box::new(KrkrsCli {}),
+            state: State::new_from_storage(storage, start, label, encoding)?,
+        })
     }
 
     pub async fn new_cli_from_url(url: &str) -> App {
@@ -18,32 +234,68 @@ impl App {
         }
     }
 
-    pub fn run(&mut self) {
+    pub fn run(&mut self) -> Result<(), Box<dyn Error>> {
         Self::greeting();
         loop {
-            self.ui.render(&self.state).unwrap();
-            if self.handle_input() {
-                break;
+            // the terminal has no frames, timed waits pass at once.
+            self.state.tick(Self::FAST_FORWARD);
+            self.render()?;
+            // the terminal prints the whole text at once.
+            self.state.eval_cmd("TextRevealed");
+            if self.handle_input()? {
+                return Ok(());
             }
         }
     }
 
-    fn handle_input(&mut self) -> bool {
+    /// whether the player quit.
+    fn handle_input(&mut self) -> Result<bool, Box<dyn Error>> {
         use std::io::{stdin, stdout, Write};
 
         let mut input = String::new();
         print!("> ");
-        let _ = stdout().flush();
-        stdin()
-            .read_line(&mut input)
-            .expect("Did not enter a correct string");
+        stdout().flush()?;
+        if stdin().read_line(&mut input)? == 0 {
+            // end of input
+            return Ok(true);
+        }
         let input = input.trim();
-        match input {
+        Ok(match input {
             "q" => true,
+            "b" => {
+                self.print_backlog();
+                false
+            }
+            "r" => {
+                if let Err(e) = self.state.rollback(1) {
+                    println!("{}", e);
+                }
+                false
+            }
             _ => {
                 self.state.eval_cmd(input);
                 false
             }
+        })
+    }
+
+    fn print_backlog(&self) {
+        for entry in self.state.backlog().page(0, Self::BACKLOG_PAGE) {
+            let voice = entry
+                .voice
+                .as_ref()
+                .map(|v| format!(" ({})", v))
+                .unwrap_or_default();
+            println!(
+                "[{}:{}]{}",
+                entry.position.label, entry.position.index, voice
+            );
+            if let Some(speaker) = &entry.speaker {
+                print!("{}: ", speaker);
+            }
+            for line in &entry.lines {
+                println!("{}", line);
+            }
         }
     }
 
@@ -0,0 +1,970 @@
+//! Language server for `.ks` files, spoken over stdio by `krkrs-lsp`.
+//!
+//! Scenarios under the workspace root are loaded once; open documents take
+//! their place while they are edited. The server publishes the diagnostics
+//! of the parser and the linter, goes to the labels of `target=` and the
+//! definitions of macros, shows what KAG tags do, completes tags, their
+//! attributes and labels, and lists the labels of a document.
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    interpreter::{
+        cst::{self, CstTag, Node},
+        lint::{self, KAG_TAGS},
+        parser::{parse_ks_spans, Token},
+        scenario::Scenario,
+    },
+    parsec::{Location, ParsecError},
+    vfs::{storage::Storage, text},
+};
+
+/// what the built-in tags do and the attributes they take.
+#[rustfmt::skip]
+const TAG_DOCS: &[(&str, &str, &[&str])] = &[
+    ("bg", "Shows a background image.", &["file", "storage", "time", "method"]),
+    ("button", "Shows a graphical button that jumps or calls when clicked.", &["graphic", "storage", "target", "exp", "hint", "cond"]),
+    ("call", "Calls a subroutine, `[return]` comes back.", &["storage", "target", "cond"]),
+    ("cm", "Clears all message layers.", &[]),
+    ("ct", "Clears the message layers and resets the current one.", &[]),
+    ("delay", "Sets the speed text is shown at.", &["speed"]),
+    ("else", "Starts the branch taken when no condition before holds.", &[]),
+    ("elsif", "Starts a branch taken when `exp` holds and no condition before does.", &["exp"]),
+    ("emb", "Shows the value of an expression as text.", &["exp", "cond"]),
+    ("endif", "Ends an `[if]`.", &[]),
+    ("endlink", "Ends a `[link]`.", &[]),
+    ("endmacro", "Ends a `[macro]`.", &[]),
+    ("endscript", "Ends an `[iscript]`.", &[]),
+    ("er", "Clears the current message layer.", &[]),
+    ("eval", "Evaluates a TJS expression.", &["exp", "cond"]),
+    ("fadebgm", "Fades the background music to a volume.", &["volume", "time"]),
+    ("fadeinbgm", "Plays background music, fading it in.", &["storage", "loop", "time"]),
+    ("fadeoutbgm", "Fades the background music out.", &["time"]),
+    ("font", "Sets the font of the text that follows.", &["size", "face", "color", "bold", "italic", "shadow", "edge"]),
+    ("glyph", "Sets the glyph shown while waiting for a click.", &["line", "page", "fix", "left", "top"]),
+    ("hidemessage", "Hides the message layers until a click.", &[]),
+    ("history", "Sets what the message history records.", &["output", "enabled"]),
+    ("if", "Starts a block run only when `exp` holds.", &["exp"]),
+    ("image", "Loads an image into a layer.", &["storage", "layer", "page", "visible", "left", "top", "opacity"]),
+    ("iscript", "Starts a block of TJS script.", &[]),
+    ("jump", "Jumps to a label, of this scenario or of `storage`.", &["storage", "target", "cond"]),
+    ("l", "Waits for a click at the end of a line.", &[]),
+    ("link", "Starts a choice, the text up to `[endlink]` jumps when clicked.", &["storage", "target", "exp", "hint", "cond"]),
+    ("locate", "Moves where the next text is shown.", &["x", "y"]),
+    ("lr", "Waits for a click and breaks the line.", &[]),
+    ("macro", "Defines a tag as the tags up to `[endmacro]`.", &["name"]),
+    ("move", "Moves a layer along a path.", &["layer", "page", "path", "time", "accel", "spline"]),
+    ("name", "Sets the name of who speaks.", &["chara"]),
+    ("nowait", "Shows the text that follows at once.", &[]),
+    ("p", "Waits for a click at the end of a page.", &[]),
+    ("pg", "Waits for a click, then starts a new page.", &[]),
+    ("playbgm", "Plays background music.", &["storage", "loop"]),
+    ("playse", "Plays a sound effect.", &["storage", "buf", "loop"]),
+    ("position", "Sets the place and look of a message layer.", &["layer", "page", "left", "top", "width", "height", "frame", "visible"]),
+    ("quake", "Shakes the screen.", &["time", "hmax", "vmax"]),
+    ("r", "Breaks the line.", &[]),
+    ("return", "Comes back from a `[call]`.", &["storage", "target"]),
+    ("ruby", "Puts ruby text over the next character.", &["text"]),
+    ("s", "Stops the scenario, e.g. to wait for a choice.", &[]),
+    ("say", "Plays a voice for the text that follows.", &["storage"]),
+    ("stopbgm", "Stops the background music.", &[]),
+    ("stopse", "Stops a sound effect.", &["buf"]),
+    ("trans", "Runs a transition from the back page to the front page.", &["layer", "method", "time", "rule", "vague", "from", "stay"]),
+    ("voice", "Plays a voice for the text that follows.", &["storage"]),
+    ("wait", "Waits for some time.", &["time", "mode", "canskip"]),
+    ("wm", "Waits for a `[move]` to end.", &["canskip"]),
+    ("wq", "Waits for a `[quake]` to end.", &["canskip"]),
+    ("ws", "Waits for a sound effect to end.", &["buf", "canskip"]),
+    ("wt", "Waits for a `[trans]` to end.", &["canskip"]),
+];
+
+/// completion item kinds, symbol kinds and error codes of the protocol.
+const KIND_FUNCTION: u32 = 3;
+const KIND_PROPERTY: u32 = 10;
+const KIND_KEYWORD: u32 = 14;
+const KIND_REFERENCE: u32 = 18;
+const SYMBOL_FUNCTION: u32 = 12;
+const SYMBOL_KEY: u32 = 20;
+const METHOD_NOT_FOUND: i64 = -32601;
+const PARSE_ERROR: i64 = -32700;
+
+/// the longest body read, longer ones are skipped.
+const MAX_LENGTH: usize = 64 << 20;
+
+/// reads a message, `None` at the end of the input. A body that is no
+/// message is an inner `Err`, to answer with [`parse_error`] and go on.
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<Result<Value, String>>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+    let length = match length {
+        Some(length) if length > MAX_LENGTH => {
+            io::copy(
+                &mut io::Read::take(&mut *input, length as u64),
+                &mut io::sink(),
+            )?;
+            return Ok(Some(Err(format!("a body of {} bytes is too long", length))));
+        }
+        Some(length) => length,
+        None => return Ok(Some(Err("no Content-Length".to_string()))),
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(
+        serde_json::from_slice(&body).map_err(|e| e.to_string()),
+    ))
+}
+
+/// the answer to a message that could not be read.
+pub fn parse_error(message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": null,
+        "error": { "code": PARSE_ERROR, "message": message },
+    })
+}
+
+pub fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut i = 0;
+    while i < path.len() {
+        let hex = path
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok());
+        match (path[i], hex.and_then(|h| u8::from_str_radix(h, 16).ok())) {
+            (b'%', Some(byte)) => {
+                bytes.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                bytes.push(byte);
+                i += 1;
+            }
+        }
+    }
+    Some(PathBuf::from(String::from_utf8(bytes).ok()?))
+}
+
+fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for &byte in path.to_string_lossy().as_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+/// the byte offset of a character counted in UTF-16, as the protocol does.
+fn byte_offset(line: &str, character: usize) -> usize {
+    let mut units = 0;
+    for (i, c) in line.char_indices() {
+        if units >= character {
+            return i;
+        }
+        units += c.len_utf16();
+    }
+    line.len()
+}
+
+fn utf16_len(s: &str) -> usize {
+    s.encode_utf16().count()
+}
+
+fn line_of(text: &str, line: usize) -> &str {
+    let line = text.split('\n').nth(line).unwrap_or_default();
+    line.strip_suffix('\r').unwrap_or(line)
+}
+
+/// the range of a whole line, counted from 0.
+fn line_range(text: &str, line: usize) -> Value {
+    json!({
+        "start": { "line": line, "character": 0 },
+        "end": { "line": line, "character": utf16_len(line_of(text, line)) },
+    })
+}
+
+/// the range from a parse error to the end of its line.
+fn error_range(text: &str, location: Location) -> Value {
+    let line = location.line.saturating_sub(1);
+    let text = line_of(text, line);
+    let start: String = text
+        .chars()
+        .take(location.column.saturating_sub(1))
+        .collect();
+    json!({
+        "start": { "line": line, "character": utf16_len(&start) },
+        "end": { "line": line, "character": utf16_len(text) },
+    })
+}
+
+/// what of a tag a position is on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Part {
+    Name,
+    Key(usize),
+    Value(usize),
+    Between,
+}
+
+/// the tag at byte offset `col` of `source` and the part of it there.
+fn cursor(source: &str, col: usize) -> Option<(CstTag, Part)> {
+    let mut offset = 0;
+    for node in cst::parse(source).nodes {
+        let len = node.to_string().len();
+        let tag = match node {
+            Node::Tag(tag) if offset < col && col <= offset + len => tag,
+            _ => {
+                offset += len;
+                continue;
+            }
+        };
+        let mut o = offset + 1 + tag.name.len();
+        if col <= o {
+            return Some((tag, Part::Name));
+        }
+        let mut part = Part::Between;
+        for (i, attr) in tag.attributes.iter().enumerate() {
+            o += attr.space.len();
+            if col < o {
+                break;
+            }
+            o += attr.key.len();
+            if col <= o {
+                part = Part::Key(i);
+                break;
+            }
+            if let Some(v) = &attr.value {
+                let quote = v.quote.map_or(0, char::len_utf8);
+                o += v.prefix.map_or(0, char::len_utf8);
+                o += v.before.len() + 1 + v.after.len() + quote;
+                if col >= o && col <= o + v.raw.len() {
+                    part = Part::Value(i);
+                    break;
+                }
+                o += v.raw.len() + quote;
+            }
+        }
+        return Some((tag, part));
+    }
+    None
+}
+
+/// what is being typed before the cursor inside a tag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Typing {
+    Name,
+    Key(String),
+    /// the tag and its attributes so far, and the key whose value is typed.
+    Value(String, HashMap<String, String>, String),
+}
+
+fn typing(prefix: &str) -> Option<Typing> {
+    let body = match prefix.trim_start().strip_prefix('@') {
+        Some(body) => body,
+        None => {
+            let open = prefix.rfind('[')?;
+            if prefix[open..].contains(']') {
+                return None;
+            }
+            &prefix[open + 1..]
+        }
+    };
+    let mut words = body.split([' ', '\t']);
+    let name = words.next()?.to_string();
+    let words: Vec<&str> = words.collect();
+    let (last, before) = match words.split_last() {
+        Some(split) => split,
+        None => return Some(Typing::Name),
+    };
+    let attributes = before
+        .iter()
+        .filter_map(|w| w.split_once('='))
+        .map(|(k, v)| (k.to_string(), v.trim_matches(['"', '\'']).to_string()))
+        .collect();
+    match last.split_once('=') {
+        Some((key, _)) => Some(Typing::Value(name, attributes, key.to_string())),
+        None => Some(Typing::Key(name)),
+    }
+}
+
+struct Document {
+    storage: String,
+    text: String,
+    /// the text parsed, or why and where it does not parse.
+    scenario: Result<Scenario, (String, Option<Location>)>,
+    /// where the parser found tags, empty if the text does not parse.
+    tags: Vec<Range<usize>>,
+}
+
+impl Document {
+    fn new(storage: String, text: String) -> Document {
+        let scenario = Scenario::parse(&storage, &text).map_err(|e| {
+            let location = e.downcast_ref::<ParsecError>().map(ParsecError::location);
+            (e.to_string(), location)
+        });
+        let tags = parse_ks_spans(&text)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(_, token)| matches!(token, Token::Tag(_)))
+            .map(|(range, _)| {
+                // the span leaves out the closing `]`.
+                if text[range.clone()].starts_with('[') && text[range.end..].starts_with(']') {
+                    range.start..range.end + 1
+                } else {
+                    range
+                }
+            })
+            .collect();
+        Document {
+            storage,
+            text,
+            scenario,
+            tags,
+        }
+    }
+
+    /// the tag at a position and the part of it there. The parser decides
+    /// where tags are; only a text that does not parse falls back to the
+    /// line alone.
+    fn cursor(&self, line: usize, col: usize) -> Option<(CstTag, Part)> {
+        if self.scenario.is_err() {
+            return cursor(line_of(&self.text, line), col);
+        }
+        let at = line_start(&self.text, line) + col;
+        let range = self.tags.iter().find(|r| r.start < at && at <= r.end)?;
+        cursor(&self.text[range.clone()], at - range.start)
+    }
+}
+
+/// the byte offset where a line starts, counted from 0.
+fn line_start(text: &str, line: usize) -> usize {
+    text.split_inclusive('\n').take(line).map(str::len).sum()
+}
+
+pub struct Server {
+    root: Option<PathBuf>,
+    storage: Storage,
+    /// the scenarios on disk, by storage.
+    workspace: HashMap<String, Scenario>,
+    /// open documents by URI.
+    documents: HashMap<String, Document>,
+    shutdown: bool,
+    exit: Option<i32>,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Server {
+    pub fn new() -> Server {
+        Server {
+            root: None,
+            storage: Storage::new(),
+            workspace: HashMap::new(),
+            documents: HashMap::new(),
+            shutdown: false,
+            exit: None,
+        }
+    }
+
+    /// the code to exit with once the client has said `exit`.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit
+    }
+
+    /// the responses and notifications a message gives.
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let method = match message["method"].as_str() {
+            Some(method) => method,
+            // a response, the server asks nothing.
+            None => return Vec::new(),
+        };
+        let params = &message["params"];
+        let id = match message.get("id") {
+            Some(id) => id.clone(),
+            None => return self.notify(method, params),
+        };
+        let result = match method {
+            "initialize" => Ok(self.initialize(params)),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/definition" => Ok(self.definition(params)),
+            "textDocument/hover" => Ok(self.hover(params)),
+            "textDocument/completion" => Ok(self.completion(params)),
+            "textDocument/documentSymbol" => Ok(self.symbols(params)),
+            _ => Err(format!("{} is not supported", method)),
+        };
+        vec![match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(message) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": METHOD_NOT_FOUND, "message": message },
+            }),
+        }]
+    }
+
+    fn notify(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                let document = Document::new(self.storage_of(uri), text.to_string());
+                self.documents.insert(uri.to_string(), document);
+                self.publish(uri)
+            }
+            "textDocument/didChange" => {
+                // the whole text is synced.
+                let changes = params["contentChanges"].as_array();
+                let text = changes
+                    .and_then(|c| c.last())
+                    .and_then(|c| c["text"].as_str());
+                match (self.documents.get_mut(uri), text) {
+                    (Some(document), Some(text)) => {
+                        let storage = std::mem::take(&mut document.storage);
+                        *document = Document::new(storage, text.to_string());
+                        self.publish(uri)
+                    }
+                    _ => Vec::new(),
+                }
+            }
+            "textDocument/didClose" => {
+                if let Some(document) = self.documents.remove(uri) {
+                    self.reload(&document.storage);
+                }
+                vec![json!({
+                    "jsonrpc": "2.0",
+                    "method": "textDocument/publishDiagnostics",
+                    "params": { "uri": uri, "diagnostics": [] },
+                })]
+            }
+            "exit" => {
+                self.exit = Some(if self.shutdown { 0 } else { 1 });
+                Vec::new()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn initialize(&mut self, params: &Value) -> Value {
+        let root = params["rootUri"]
+            .as_str()
+            .or_else(|| params["workspaceFolders"][0]["uri"].as_str())
+            .and_then(uri_to_path)
+            .or_else(|| params["rootPath"].as_str().map(PathBuf::from));
+        if let Some(root) = root {
+            // a root that does not mount is no workspace.
+            if self.storage.mount_dir(&root).is_ok() {
+                let (scenarios, _) = lint::load(&self.storage, None);
+                self.workspace = scenarios
+                    .into_iter()
+                    .map(|s| (s.storage.clone(), s))
+                    .collect();
+                self.root = Some(root);
+            }
+        }
+        json!({
+            "capabilities": {
+                "textDocumentSync": 1,
+                "definitionProvider": true,
+                "hoverProvider": true,
+                "completionProvider": { "triggerCharacters": ["[", "@", " ", "*"] },
+                "documentSymbolProvider": true,
+            },
+            "serverInfo": { "name": "krkrs-lsp", "version": env!("CARGO_PKG_VERSION") },
+        })
+    }
+
+    /// the storage a document is, relative to the root when it is under it.
+    fn storage_of(&self, uri: &str) -> String {
+        let path = uri_to_path(uri);
+        if let (Some(root), Some(path)) = (&self.root, &path) {
+            if let Ok(relative) = path.strip_prefix(root) {
+                let parts: Vec<_> = relative.iter().map(|p| p.to_string_lossy()).collect();
+                return parts.join("/");
+            }
+        }
+        uri.rsplit('/').next().unwrap_or(uri).to_string()
+    }
+
+    fn uri_of(&self, storage: &str) -> String {
+        let open = self.documents.iter().find(|(_, d)| d.storage == storage);
+        match (open, &self.root) {
+            (Some((uri, _)), _) => uri.clone(),
+            (None, Some(root)) => path_to_uri(&root.join(storage)),
+            (None, None) => storage.to_string(),
+        }
+    }
+
+    /// reads a scenario from the disk again, after it is edited.
+    fn reload(&mut self, storage: &str) {
+        let data = self.storage.read(storage, &[]);
+        let scenario = data
+            .and_then(|data| Ok(text::decode(&data, None)?))
+            .and_then(|source| Scenario::parse(storage, &source));
+        if let Ok(scenario) = scenario {
+            self.workspace.insert(storage.to_string(), scenario);
+        }
+    }
+
+    /// the workspace with the open documents that parse in place of what is
+    /// on disk.
+    fn scenarios(&self) -> Vec<&Scenario> {
+        let open: HashMap<&str, &Scenario> = self
+            .documents
+            .values()
+            .filter_map(|d| Some((d.storage.as_str(), d.scenario.as_ref().ok()?)))
+            .collect();
+        let mut scenarios: Vec<&Scenario> = self
+            .workspace
+            .values()
+            .filter(|s| !open.contains_key(s.storage.as_str()))
+            .collect();
+        scenarios.extend(open.into_values());
+        scenarios
+    }
+
+    /// the diagnostics of an open document, after it is opened or changed.
+    fn publish(&self, uri: &str) -> Vec<Value> {
+        let document = match self.documents.get(uri) {
+            Some(document) => document,
+            None => return Vec::new(),
+        };
+        let diagnostics = match &document.scenario {
+            Ok(scenario) => lint::check_some(&self.storage, &self.scenarios(), &[scenario], &[])
+                .into_iter()
+                .map(|d| {
+                    (
+                        line_range(&document.text, d.line.saturating_sub(1)),
+                        d.message,
+                    )
+                })
+                .collect(),
+            Err((message, location)) => {
+                let range = match location {
+                    Some(location) => error_range(&document.text, *location),
+                    None => line_range(&document.text, 0),
+                };
+                vec![(range, message.clone())]
+            }
+        };
+        let items: Vec<Value> = diagnostics
+            .into_iter()
+            .map(|(range, message)| {
+                json!({
+                    "range": range,
+                    "severity": 1,
+                    "source": "krkrs",
+                    "message": message,
+                })
+            })
+            .collect();
+        vec![json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": items },
+        })]
+    }
+
+    /// the document, the line and the byte offset in it of a position.
+    fn position<'a>(&'a self, params: &Value) -> Option<(&'a Document, usize, usize)> {
+        let uri = params["textDocument"]["uri"].as_str()?;
+        let document = self.documents.get(uri)?;
+        let line = params["position"]["line"].as_u64()? as usize;
+        let character = params["position"]["character"].as_u64()? as usize;
+        Some((
+            document,
+            line,
+            byte_offset(line_of(&document.text, line), character),
+        ))
+    }
+
+    /// finds a scenario by a `storage=` value, or by its own storage.
+    fn scenario<'a>(&self, name: &str, scenarios: &[&'a Scenario]) -> Option<&'a Scenario> {
+        let name = name.to_lowercase();
+        let matches = |storage: &str| {
+            let storage = storage.to_lowercase();
+            let file = storage.rsplit('/').next().unwrap_or(&storage).to_string();
+            [storage, file]
+                .iter()
+                .any(|s| *s == name || *s == format!("{}.ks", name))
+        };
+        scenarios.iter().find(|s| matches(&s.storage)).copied()
+    }
+
+    /// the macros of all scenarios by name, with where they are defined.
+    fn macros(scenarios: &[&Scenario]) -> HashMap<String, (String, usize)> {
+        let mut macros = HashMap::new();
+        for scenario in scenarios {
+            for (line, token) in scenario.tokens() {
+                if let Token::Tag(tag) = token {
+                    if let (true, Some(name)) = (tag.name == "macro", tag.attributes.get("name")) {
+                        macros.insert(name.clone(), (scenario.storage.clone(), line));
+                    }
+                }
+            }
+        }
+        macros
+    }
+
+    fn location(&self, storage: &str, line: usize) -> Value {
+        let text = self
+            .documents
+            .values()
+            .find(|d| d.storage == storage)
+            .map(|d| d.text.clone())
+            .unwrap_or_default();
+        json!({ "uri": self.uri_of(storage), "range": line_range(&text, line.saturating_sub(1)) })
+    }
+
+    fn definition(&self, params: &Value) -> Value {
+        self.find_definition(params).unwrap_or(Value::Null)
+    }
+
+    fn find_definition(&self, params: &Value) -> Option<Value> {
+        let (document, line, col) = self.position(params)?;
+        let (tag, part) = document.cursor(line, col)?;
+        let scenarios = self.scenarios();
+        match part {
+            Part::Name => {
+                let (storage, line) = Self::macros(&scenarios).remove(&tag.name)?;
+                Some(self.location(&storage, line))
+            }
+            Part::Value(i) => {
+                let attr = &tag.attributes[i];
+                let to = match tag.attribute("storage") {
+                    Some(name) => self.scenario(name, &scenarios)?,
+                    None => self.scenario(&document.storage, &scenarios)?,
+                };
+                match attr.key.as_str() {
+                    "target" => {
+                        let pc = to.label(&attr.value.as_ref()?.raw)?;
+                        Some(self.location(&to.storage, to.line(pc)?))
+                    }
+                    "storage" => Some(self.location(&to.storage, 1)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn hover(&self, params: &Value) -> Value {
+        let (document, line, col) = match self.position(params) {
+            Some(position) => position,
+            None => return Value::Null,
+        };
+        let tag = match document.cursor(line, col) {
+            Some((tag, Part::Name)) => tag,
+            _ => return Value::Null,
+        };
+        let doc = match TAG_DOCS.iter().find(|(name, ..)| *name == tag.name) {
+            Some((_, doc, [])) => doc.to_string(),
+            Some((_, doc, attributes)) => {
+                format!("{}\n\nattributes: {}", doc, attributes.join(", "))
+            }
+            None if KAG_TAGS.contains(&tag.name.as_str()) => "A KAG tag.".to_string(),
+            None => match Self::macros(&self.scenarios()).get(&tag.name) {
+                Some((storage, line)) => format!("A macro defined at {}:{}.", storage, line),
+                None => return Value::Null,
+            },
+        };
+        json!({ "contents": { "kind": "markdown", "value": format!("**[{}]**\n\n{}", tag.name, doc) } })
+    }
+
+    fn completion(&self, params: &Value) -> Value {
+        let (document, line, col) = match self.position(params) {
+            Some(position) => position,
+            None => return json!([]),
+        };
+        let scenarios = self.scenarios();
+        let items: Vec<Value> = match typing(&line_of(&document.text, line)[..col]) {
+            Some(Typing::Name) => {
+                let mut items: Vec<Value> = KAG_TAGS
+                    .iter()
+                    .map(|name| {
+                        let doc = TAG_DOCS.iter().find(|(n, ..)| n == name).map(|(_, doc, _)| *doc);
+                        json!({ "label": name, "kind": KIND_KEYWORD, "documentation": doc })
+                    })
+                    .collect();
+                items.extend(Self::macros(&scenarios).into_iter().map(|(name, (storage, line))| {
+                    json!({ "label": name, "kind": KIND_FUNCTION, "detail": format!("macro at {}:{}", storage, line) })
+                }));
+                items
+            }
+            Some(Typing::Key(name)) => TAG_DOCS
+                .iter()
+                .filter(|(n, ..)| *n == name)
+                .flat_map(|(_, _, attributes)| attributes.iter())
+                .map(|a| json!({ "label": a, "kind": KIND_PROPERTY, "insertText": format!("{}=", a) }))
+                .collect(),
+            Some(Typing::Value(_, attributes, key)) if key == "target" => {
+                let storage = attributes.get("storage").unwrap_or(&document.storage);
+                let to = self.scenario(storage, &scenarios);
+                to.iter()
+                    .flat_map(|s| s.tokens())
+                    .filter_map(|(_, token)| match token {
+                        Token::Label(label) => Some(json!({
+                            "label": format!("*{}", label.label),
+                            "kind": KIND_REFERENCE,
+                            "detail": label.heading.as_ref().map(|h| h.to_string()),
+                        })),
+                        _ => None,
+                    })
+                    .collect()
+            }
+            Some(Typing::Value(_, _, key)) if key == "storage" => scenarios
+                .iter()
+                .map(|s| {
+                    let file = s.storage.rsplit('/').next().unwrap_or(&s.storage);
+                    json!({ "label": file, "kind": KIND_REFERENCE, "detail": s.storage })
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        json!(items)
+    }
+
+    fn symbols(&self, params: &Value) -> Value {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let document = match self.documents.get(uri) {
+            Some(document) => document,
+            None => return json!([]),
+        };
+        let scenario = match &document.scenario {
+            Ok(scenario) => scenario,
+            Err(_) => return json!([]),
+        };
+        let symbols: Vec<Value> = scenario
+            .tokens()
+            .filter_map(|(line, token)| {
+                let (name, detail, kind) = match token {
+                    Token::Label(label) => (
+                        format!("*{}", label.label),
+                        label
+                            .heading
+                            .as_ref()
+                            .map_or_else(String::new, |h| h.to_string()),
+                        SYMBOL_KEY,
+                    ),
+                    Token::Tag(tag) if tag.name == "macro" => (
+                        tag.attributes.get("name")?.clone(),
+                        "macro".to_string(),
+                        SYMBOL_FUNCTION,
+                    ),
+                    _ => return None,
+                };
+                let range = line_range(&document.text, line - 1);
+                Some(json!({
+                    "name": name,
+                    "detail": detail,
+                    "kind": kind,
+                    "range": range,
+                    "selectionRange": range,
+                }))
+            })
+            .collect();
+        json!(symbols)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(id: u64, method: &str, params: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+    }
+
+    fn open(server: &mut Server, uri: &str, text: &str) -> Vec<Value> {
+        server.handle(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": uri, "languageId": "kag", "version": 1, "text": text } },
+        }))
+    }
+
+    fn at(uri: &str, line: usize, character: usize) -> Value {
+        json!({ "textDocument": { "uri": uri }, "position": { "line": line, "character": character } })
+    }
+
+    const FIRST: &str = "*start|開始\n[macro name=chara][endmacro]\n@chara\n\
+[jump storage=second.ks target=*end]\n[jump target=*nowhere]\n@bg file=";
+    const SECOND: &str = "*end|\n[s]";
+
+    fn server() -> (Server, Vec<Value>) {
+        let mut server = Server::new();
+        server.handle(&request(1, "initialize", json!({ "rootUri": null })));
+        open(&mut server, "file:///game/second.ks", SECOND);
+        let published = open(&mut server, "file:///game/first.ks", FIRST);
+        (server, published)
+    }
+
+    #[test]
+    fn test_transport() {
+        let mut out = Vec::new();
+        let message = json!({ "jsonrpc": "2.0", "method": "exit" });
+        write_message(&mut out, &message).unwrap();
+        write_message(&mut out, &message).unwrap();
+        let mut input = io::Cursor::new(out);
+        assert_eq!(read_message(&mut input).unwrap(), Some(Ok(message.clone())));
+        assert_eq!(read_message(&mut input).unwrap(), Some(Ok(message)));
+        assert_eq!(read_message(&mut input).unwrap(), None);
+    }
+
+    #[test]
+    fn test_bad_message() {
+        let mut input = b"Content-Length: 5\r\n\r\n{oops".to_vec();
+        input.extend(format!("Content-Length: {}\r\n\r\n", MAX_LENGTH + 1).bytes());
+        input.resize(input.len() + MAX_LENGTH + 1, b' ');
+        input.extend(b"Content-Length: 2\r\n\r\n{}");
+        let mut input = io::Cursor::new(input);
+        assert!(matches!(read_message(&mut input), Ok(Some(Err(_)))));
+        assert!(matches!(read_message(&mut input), Ok(Some(Err(_)))));
+        assert_eq!(read_message(&mut input).unwrap(), Some(Ok(json!({}))));
+        assert_eq!(parse_error("bad")["error"]["code"], PARSE_ERROR);
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let (_, published) = server();
+        // only the document opened is linted again.
+        assert_eq!(published.len(), 1);
+        let first = published
+            .iter()
+            .find(|p| p["params"]["uri"] == "file:///game/first.ks")
+            .unwrap();
+        let diagnostics = first["params"]["diagnostics"].as_array().unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0]["range"]["start"]["line"], 4);
+        assert!(diagnostics[0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("*nowhere"));
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let (mut server, _) = server();
+        let published = open(&mut server, "file:///game/bad.ks", "one\n[jump target=*end");
+        let diagnostics = published[0]["params"]["diagnostics"].as_array().unwrap();
+        assert_eq!(
+            diagnostics[0]["range"],
+            json!({ "start": { "line": 1, "character": 17 }, "end": { "line": 1, "character": 17 } })
+        );
+    }
+
+    #[test]
+    fn test_definition() {
+        let (mut server, _) = server();
+        let uri = "file:///game/first.ks";
+        let response = server.handle(&request(2, "textDocument/definition", at(uri, 3, 32)));
+        assert_eq!(
+            response[0]["result"],
+            json!({
+                "uri": "file:///game/second.ks",
+                "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 5 } },
+            })
+        );
+        let response = server.handle(&request(3, "textDocument/definition", at(uri, 2, 2)));
+        assert_eq!(response[0]["result"]["range"]["start"]["line"], 1);
+        let response = server.handle(&request(4, "textDocument/definition", at(uri, 4, 15)));
+        assert_eq!(response[0]["result"], Value::Null);
+
+        // the parser reads a line starting with `;` as text, tags included.
+        let uri = "file:///game/third.ks";
+        open(&mut server, uri, "; see [jump storage=second.ks]\n");
+        let response = server.handle(&request(5, "textDocument/definition", at(uri, 0, 24)));
+        assert_eq!(response[0]["result"]["uri"], "file:///game/second.ks");
+    }
+
+    #[test]
+    fn test_hover_and_symbols() {
+        let (mut server, _) = server();
+        let uri = "file:///game/first.ks";
+        let hover = server.handle(&request(2, "textDocument/hover", at(uri, 3, 2)));
+        let value = hover[0]["result"]["contents"]["value"].as_str().unwrap();
+        assert!(value.starts_with("**[jump]**\n\nJumps to a label"));
+        let hover = server.handle(&request(3, "textDocument/hover", at(uri, 2, 3)));
+        assert!(hover[0]["result"]["contents"]["value"]
+            .as_str()
+            .unwrap()
+            .contains("first.ks:2"));
+
+        let symbols = server.handle(&request(
+            4,
+            "textDocument/documentSymbol",
+            json!({ "textDocument": { "uri": uri } }),
+        ));
+        let names: Vec<&str> = symbols[0]["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| s["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["*start", "chara"]);
+        assert_eq!(symbols[0]["result"][0]["detail"], "開始");
+    }
+
+    #[test]
+    fn test_completion() {
+        let (mut server, _) = server();
+        let uri = "file:///game/first.ks";
+        let labels = |response: Vec<Value>| -> Vec<String> {
+            response[0]["result"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|i| i["label"].as_str().unwrap().to_string())
+                .collect()
+        };
+        let tags = labels(server.handle(&request(2, "textDocument/completion", at(uri, 2, 1))));
+        assert!(tags.contains(&"jump".to_string()) && tags.contains(&"chara".to_string()));
+        let attributes =
+            labels(server.handle(&request(3, "textDocument/completion", at(uri, 5, 4))));
+        assert_eq!(attributes, vec!["file", "storage", "time", "method"]);
+        let targets = labels(server.handle(&request(4, "textDocument/completion", at(uri, 3, 31))));
+        assert_eq!(targets, vec!["*end"]);
+
+        let response = server.handle(&request(5, "textDocument/formatting", json!({})));
+        assert_eq!(response[0]["error"]["code"], METHOD_NOT_FOUND);
+        server.handle(&request(6, "shutdown", Value::Null));
+        server.handle(&json!({ "jsonrpc": "2.0", "method": "exit" }));
+        assert_eq!(server.exit_code(), Some(0));
+    }
+}
@@ -2,8 +2,13 @@ use wasm_bindgen::prelude::wasm_bindgen;
 
 use crate::{interpreter::interpreter::State, presentation::UI};
 
+pub mod assets;
+
 pub mod cli;
 
+#[cfg(not(target_arch = "wasm32"))]
+pub mod lsp;
+
 pub mod wasm;
 
 #[wasm_bindgen]
@@ -11,3 +16,12 @@ pub struct App {
     ui: Box<dyn UI>,
     state: State,
 }
+
+impl App {
+    /// shows the state, then forgets the warnings shown with it.
+    fn render(&mut self) -> Result<(), String> {
+        self.ui.render(&self.state)?;
+        self.state.clear_warnings();
+        Ok(())
+    }
+}
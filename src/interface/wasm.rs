@@ -1,6 +1,6 @@
 use crate::{presentation::wasm::WebUI, utils};
 use js_sys::Function;
-use wasm_bindgen::prelude::wasm_bindgen;
+use wasm_bindgen::{prelude::wasm_bindgen, JsValue};
 
 use super::*;
 
@@ -19,10 +19,62 @@ impl App {
 
     pub fn handle_web_input(&mut self, input: &str) {
         self.state.eval_cmd(input);
-        self.ui.render(&self.state).unwrap();
+        self.render().unwrap();
+    }
+
+    /// the front end calls this every frame with the milliseconds since the
+    /// last call. Renders only if the script went on.
+    pub fn tick(&mut self, ms: u32) {
+        if self.state.tick(ms as u64) {
+            self.render().unwrap();
+        }
+    }
+
+    /// the number of backlog pages with `per_page` entries each.
+    pub fn backlog_pages(&self, per_page: usize) -> usize {
+        self.state.backlog().pages(per_page)
+    }
+
+    /// a page of the backlog, page 0 holds the newest entries.
+    pub fn backlog_page(&self, page: usize, per_page: usize) -> js_sys::Array {
+        self.state
+            .backlog()
+            .page(page, per_page)
+            .into_iter()
+            .cloned()
+            .map(JsValue::from)
+            .collect()
+    }
+
+    /// the last save point passed with its title, for the save slot, `null`
+    /// before the first one.
+    pub fn save_point(&self) -> JsValue {
+        self.state
+            .save_point()
+            .cloned()
+            .map_or(JsValue::NULL, JsValue::from)
+    }
+
+    /// steps back `n` pages.
+    pub fn rollback(&mut self, n: usize) -> Result<(), JsValue> {
+        self.state
+            .rollback(n)
+            .map_err(|e| JsValue::from(e.to_string()))?;
+        self.render().unwrap();
+        Ok(())
+    }
+
+    /// pages `rollback` can step back now.
+    pub fn rollback_available(&self) -> usize {
+        self.state.rollback_available()
+    }
+
+    /// milliseconds per character chosen by the player.
+    pub fn set_text_speed(&mut self, ms: u32) {
+        self.state.set_text_speed(ms);
     }
 
     pub(self) fn app_init(&mut self) {
-        self.ui.render(&self.state).unwrap();
+        self.render().unwrap();
     }
 }
@@ -0,0 +1,151 @@
+//! # Backlog
+//!
+//! The backlog keeps the text the player has gone through, so the front end
+//! can scroll back and replay voices. An entry is the text between two
+//! clicks, which is where the speaker and the voice change.
+
+use std::collections::VecDeque;
+
+use crate::interpreter::message::Line;
+
+/// where an entry starts in the script, for jumping back to it.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Position {
+    pub storage: String,
+    pub label: String,
+    /// the index of the token after the label.
+    pub index: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BacklogEntry {
+    pub speaker: Option<String>,
+    pub lines: Vec<Line>,
+    pub voice: Option<String>,
+    pub position: Position,
+}
+
+/// entries the backlog keeps before it drops the oldest.
+pub const BACKLOG_CAPACITY: usize = 200;
+
+#[derive(Debug, Clone)]
+pub struct Backlog {
+    entries: VecDeque<BacklogEntry>,
+    capacity: usize,
+    /// entries pushed since the start, dropped ones included.
+    pushed: usize,
+}
+
+impl Default for Backlog {
+    fn default() -> Self {
+        Backlog::with_capacity(BACKLOG_CAPACITY)
+    }
+}
+
+impl Backlog {
+    pub fn new() -> Backlog {
+        Backlog::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Backlog {
+        Backlog {
+            entries: VecDeque::new(),
+            capacity,
+            pushed: 0,
+        }
+    }
+
+    pub fn push(&mut self, entry: BacklogEntry) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+        self.pushed += 1;
+    }
+
+    /// entries pushed so far, a mark for `rewind`. Unlike `len` it still
+    /// grows once the oldest entries are dropped.
+    pub fn pushed(&self) -> usize {
+        self.pushed
+    }
+
+    /// drops the entries pushed after `pushed` returned `mark`.
+    pub fn rewind(&mut self, mark: usize) {
+        let n = self.pushed.saturating_sub(mark);
+        let len = self.entries.len().saturating_sub(n);
+        self.entries.truncate(len);
+        self.pushed = self.pushed.min(mark);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// the `page`th group of `per_page` entries, counted from the newest.
+    /// Entries in a page are oldest first.
+    pub fn page(&self, page: usize, per_page: usize) -> Vec<&BacklogEntry> {
+        let end = self.len().saturating_sub(page * per_page);
+        let start = end.saturating_sub(per_page);
+        self.entries.range(start..end).collect()
+    }
+
+    pub fn pages(&self, per_page: usize) -> usize {
+        self.len().div_ceil(per_page.max(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(i: usize) -> BacklogEntry {
+        BacklogEntry {
+            speaker: None,
+            lines: Vec::new(),
+            voice: None,
+            position: Position {
+                index: i,
+                ..Position::default()
+            },
+        }
+    }
+
+    fn indices(entries: Vec<&BacklogEntry>) -> Vec<usize> {
+        entries.iter().map(|e| e.position.index).collect()
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut b = Backlog::with_capacity(3);
+        (0..5).for_each(|i| b.push(entry(i)));
+        assert_eq!(b.len(), 3);
+        assert_eq!(indices(b.page(0, 10)), vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn test_rewind() {
+        let mut b = Backlog::with_capacity(3);
+        (0..4).for_each(|i| b.push(entry(i)));
+        let mark = b.pushed();
+        (4..6).for_each(|i| b.push(entry(i)));
+        b.rewind(mark);
+        assert_eq!(indices(b.page(0, 10)), vec![3]);
+        b.push(entry(4));
+        assert_eq!(b.pushed(), 5);
+    }
+
+    #[test]
+    fn test_page() {
+        let mut b = Backlog::new();
+        (0..5).for_each(|i| b.push(entry(i)));
+        assert_eq!(b.pages(2), 3);
+        assert_eq!(indices(b.page(0, 2)), vec![3, 4]);
+        assert_eq!(indices(b.page(1, 2)), vec![1, 2]);
+        assert_eq!(indices(b.page(2, 2)), vec![0]);
+        assert!(b.page(3, 2).is_empty());
+    }
+}
@@ -0,0 +1,145 @@
+//! # Clock
+//!
+//! The interpreter never reads the wall clock. Front ends tick it with the
+//! elapsed milliseconds, real time in the browser and virtual time in tests,
+//! so a scene always plays the same way.
+
+/// milliseconds since the game started.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Clock {
+    now: u64,
+}
+
+impl Clock {
+    pub fn new() -> Clock {
+        Clock::default()
+    }
+
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    pub fn advance(&mut self, ms: u64) {
+        self.now += ms;
+    }
+}
+
+/// things on screen or in the speakers that `[wm]`-like tags wait for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EffectKind {
+    /// `[move]`, waited by `[wm]`
+    Move,
+    /// `[animstart]`, waited by `[wa]`
+    Animation,
+    /// `[trans]`, waited by `[wt]`
+    Transition,
+    /// `[quake]`, waited by `[wq]`
+    Quake,
+    /// `[playse]` and sound fades, waited by `[ws]`
+    Sound,
+    /// `[voice]`, waited by auto mode
+    Voice,
+    /// a game macro with a `time` attribute
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Effect {
+    kind: EffectKind,
+    /// `None` if only the front end knows, e.g. when a sound ends.
+    until: Option<u64>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Effects {
+    running: Vec<Effect>,
+}
+
+impl Effects {
+    pub fn new() -> Effects {
+        Effects::default()
+    }
+
+    pub fn start(&mut self, kind: EffectKind, until: Option<u64>) {
+        self.running.push(Effect { kind, until });
+    }
+
+    /// stops every effect of the kind.
+    pub fn finish(&mut self, kind: EffectKind) {
+        self.running.retain(|e| e.kind != kind);
+    }
+
+    /// drops the effects that are over by `now`.
+    pub fn update(&mut self, now: u64) {
+        self.running
+            .retain(|e| e.until.is_none_or(|until| until > now));
+    }
+
+    pub fn is_running(&self, kind: EffectKind) -> bool {
+        self.running.iter().any(|e| e.kind == kind)
+    }
+
+    /// when the last effect of the kind ends, `Some(0)` if none is running.
+    /// `None` if one of them has no known end.
+    pub fn end_of(&self, kind: EffectKind) -> Option<u64> {
+        self.running
+            .iter()
+            .filter(|e| e.kind == kind)
+            .try_fold(0, |end, e| e.until.map(|until| end.max(until)))
+    }
+}
+
+/// why the interpreter stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wait {
+    /// `[lr]`, `[pg]`
+    Click,
+    /// `[s]`, only a choice goes on from here.
+    Stop,
+    /// `[wait time=]`
+    Time { until: u64, canskip: bool },
+    /// `[wm]`, `[wa]`, `[wt]`, `[wq]`, `[ws]`
+    Effect { kind: EffectKind, canskip: bool },
+}
+
+impl Wait {
+    pub fn can_skip(&self) -> bool {
+        match self {
+            Wait::Click => true,
+            Wait::Stop => false,
+            Wait::Time { canskip, .. } | Wait::Effect { canskip, .. } => *canskip,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock() {
+        let mut c = Clock::new();
+        c.advance(16);
+        c.advance(17);
+        assert_eq!(c.now(), 33);
+    }
+
+    #[test]
+    fn test_effects() {
+        let mut e = Effects::new();
+        e.start(EffectKind::Move, Some(100));
+        e.start(EffectKind::Move, Some(300));
+        e.start(EffectKind::Sound, None);
+        assert_eq!(e.end_of(EffectKind::Move), Some(300));
+        assert_eq!(e.end_of(EffectKind::Sound), None);
+        assert_eq!(e.end_of(EffectKind::Quake), Some(0));
+
+        e.update(100);
+        assert_eq!(e.end_of(EffectKind::Move), Some(300));
+        e.update(300);
+        assert!(!e.is_running(EffectKind::Move));
+
+        e.finish(EffectKind::Sound);
+        assert!(!e.is_running(EffectKind::Sound));
+    }
+}
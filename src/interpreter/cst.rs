@@ -0,0 +1,364 @@
+//! # CST
+//!
+//! The concrete syntax tree of a `.ks` file. Unlike the tokens of the parser
+//! it keeps every byte: whitespace, line ends, comments, escapes, whether a
+//! tag is written `@tag` or `[tag]`, the order of attributes and how their
+//! values are quoted. Printing a tree gives back the source it is parsed
+//! from, so tools can edit a scenario and leave the rest of it alone.
+//!
+//! KAG reads a scenario line by line: a line is a comment after `;`, a label
+//! after `*`, a tag after `@`, or text with tags in `[` and `]`.
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cst {
+    pub nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Node {
+    /// spaces and tabs.
+    Whitespace(String),
+    /// `\n` or `\r\n`.
+    Newline(String),
+    /// after the `;`.
+    Comment(String),
+    /// `*name|heading`, `heading` is `None` without the `|`.
+    Label {
+        name: String,
+        heading: Option<String>,
+    },
+    Tag(CstTag),
+    /// as written, with `[[`, `]]` and `@@`.
+    Text(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CstTag {
+    /// `[tag]` rather than `@tag`.
+    pub inline: bool,
+    pub name: String,
+    pub attributes: Vec<CstAttribute>,
+    /// whitespace before the `]` or the end of the line.
+    pub trailing: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CstAttribute {
+    /// the whitespace before the key.
+    pub space: String,
+    pub key: String,
+    pub value: Option<CstValue>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CstValue {
+    /// whitespace before the `=`.
+    pub before: String,
+    /// whitespace after the `=`.
+    pub after: String,
+    /// the `&` of an expression or the `%` of a macro parameter written
+    /// before the quote, as in `&'f.a + 1'`.
+    pub prefix: Option<char>,
+    /// `"` or `'` if quoted.
+    pub quote: Option<char>,
+    /// between the quotes, as written.
+    pub raw: String,
+}
+
+impl fmt::Display for Cst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.nodes.iter().try_for_each(|node| write!(f, "{}", node))
+    }
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Node::Whitespace(s) | Node::Newline(s) | Node::Text(s) => write!(f, "{}", s),
+            Node::Comment(s) => write!(f, ";{}", s),
+            Node::Label { name, heading } => {
+                write!(f, "*{}", name)?;
+                match heading {
+                    Some(heading) => write!(f, "|{}", heading),
+                    None => Ok(()),
+                }
+            }
+            Node::Tag(tag) => write!(f, "{}", tag),
+        }
+    }
+}
+
+impl fmt::Display for CstTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", if self.inline { '[' } else { '@' }, self.name)?;
+        for attr in &self.attributes {
+            write!(f, "{}{}", attr.space, attr.key)?;
+            if let Some(v) = &attr.value {
+                write!(f, "{}={}", v.before, v.after)?;
+                if let Some(prefix) = v.prefix {
+                    write!(f, "{}", prefix)?;
+                }
+                match v.quote {
+                    Some(q) => write!(f, "{}{}{}", q, v.raw, q)?,
+                    None => write!(f, "{}", v.raw)?,
+                }
+            }
+        }
+        write!(f, "{}", self.trailing)?;
+        if self.inline {
+            write!(f, "]")?;
+        }
+        Ok(())
+    }
+}
+
+impl CstTag {
+    /// the value of `key` without its quotes, `None` if the attribute has
+    /// no value.
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|a| a.key == key)
+            .and_then(|a| a.value.as_ref())
+            .map(|v| v.raw.as_str())
+    }
+}
+
+fn is_space(c: char) -> bool {
+    c == ' ' || c == '\t'
+}
+
+/// splits `s` after its leading characters matching `p`.
+fn take_while(s: &str, p: impl Fn(char) -> bool) -> (&str, &str) {
+    s.split_at(s.find(|c| !p(c)).unwrap_or(s.len()))
+}
+
+/// parses a whole file. Anything that is not a well formed tag is kept as
+/// text, so every input has a tree.
+pub fn parse(source: &str) -> Cst {
+    let mut nodes = Vec::new();
+    for line in source.split_inclusive('\n') {
+        let (line, newline) = match line.strip_suffix('\n') {
+            Some(line) => match line.strip_suffix('\r') {
+                Some(line) => (line, "\r\n"),
+                None => (line, "\n"),
+            },
+            None => (line, ""),
+        };
+        parse_line(line, &mut nodes);
+        if !newline.is_empty() {
+            nodes.push(Node::Newline(newline.to_string()));
+        }
+    }
+    Cst { nodes }
+}
+
+fn parse_line(line: &str, nodes: &mut Vec<Node>) {
+    let (space, rest) = take_while(line, is_space);
+    if !space.is_empty() {
+        nodes.push(Node::Whitespace(space.to_string()));
+    }
+    if let Some(comment) = rest.strip_prefix(';') {
+        nodes.push(Node::Comment(comment.to_string()));
+    } else if let Some(label) = rest.strip_prefix('*') {
+        let (name, heading) = match label.split_once('|') {
+            Some((name, heading)) => (name, Some(heading.to_string())),
+            None => (label, None),
+        };
+        nodes.push(Node::Label {
+            name: name.to_string(),
+            heading,
+        });
+    } else if rest.starts_with('@') && !rest.starts_with("@@") {
+        match parse_tag(&rest[1..], false) {
+            Some((tag, "")) => nodes.push(Node::Tag(tag)),
+            _ => parse_text(rest, nodes),
+        }
+    } else {
+        parse_text(rest, nodes);
+    }
+}
+
+/// text with inlined tags.
+fn parse_text(mut rest: &str, nodes: &mut Vec<Node>) {
+    let mut text = String::new();
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with("[[") || rest.starts_with("]]") || rest.starts_with("@@") {
+            text.push_str(&rest[..2]);
+            rest = &rest[2..];
+            continue;
+        }
+        if c == '[' {
+            if let Some((tag, after)) = parse_tag(&rest[1..], true) {
+                if !text.is_empty() {
+                    nodes.push(Node::Text(std::mem::take(&mut text)));
+                }
+                nodes.push(Node::Tag(tag));
+                rest = after;
+                continue;
+            }
+        }
+        text.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    if !text.is_empty() {
+        nodes.push(Node::Text(text));
+    }
+}
+
+/// a tag after its `@` or `[`, and what follows it. An inlined tag ends at
+/// its `]`, a line tag at the end of the line.
+fn parse_tag(s: &str, inline: bool) -> Option<(CstTag, &str)> {
+    let ends = |c: char| is_space(c) || inline && c == ']';
+    let (name, mut rest) = take_while(s, |c| !ends(c));
+    let mut tag = CstTag {
+        inline,
+        name: name.to_string(),
+        attributes: Vec::new(),
+        trailing: String::new(),
+    };
+    loop {
+        let (space, after) = take_while(rest, is_space);
+        if after.is_empty() || inline && after.starts_with(']') {
+            tag.trailing = space.to_string();
+            rest = after;
+            break;
+        }
+        let (key, after) = take_while(after, |c| c != '=' && !ends(c));
+        let (before, eq) = take_while(after, is_space);
+        let value = match eq.strip_prefix('=') {
+            Some(eq) => {
+                let (after_eq, v) = take_while(eq, is_space);
+                let (value, after) = parse_value(v, inline)?;
+                rest = after;
+                Some(CstValue {
+                    before: before.to_string(),
+                    after: after_eq.to_string(),
+                    ..value
+                })
+            }
+            None => {
+                rest = after;
+                None
+            }
+        };
+        tag.attributes.push(CstAttribute {
+            space: space.to_string(),
+            key: key.to_string(),
+            value,
+        });
+    }
+    if inline {
+        rest = rest.strip_prefix(']')?;
+    }
+    Some((tag, rest))
+}
+
+/// a value quoted or not, `None` if its quote is not closed. A backslash
+/// keeps the character after it from closing the quote. The quote may
+/// follow a `&` or a `%`, as the parser allows.
+fn parse_value(s: &str, inline: bool) -> Option<(CstValue, &str)> {
+    let mut chars = s.chars();
+    let prefix = match (chars.next(), chars.next()) {
+        (Some(p @ ('&' | '%')), Some('"' | '\'')) => Some(p),
+        _ => None,
+    };
+    let value = |quote, raw: &str| CstValue {
+        before: String::new(),
+        after: String::new(),
+        prefix,
+        quote,
+        raw: raw.to_string(),
+    };
+    let quoted = &s[prefix.map_or(0, char::len_utf8)..];
+    match quoted.chars().next() {
+        Some(q @ ('"' | '\'')) => {
+            let body = &quoted[1..];
+            let mut escaped = false;
+            for (i, c) in body.char_indices() {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    c if c == q => return Some((value(Some(q), &body[..i]), &body[i + 1..])),
+                    _ => {}
+                }
+            }
+            None
+        }
+        _ => {
+            let (raw, rest) = take_while(s, |c| !(is_space(c) || inline && c == ']'));
+            Some((value(None, raw), rest))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let sources = [
+            "",
+            "\n\r\n",
+            "*page0|&f.scripttitle\r\n@eval exp=\"sf.name = '桜'\"\r\n",
+            "  ; a comment [lr]\n*start\n\t@bg  file = 'a b'  time=100 \nhi[[lr]] [r ][ l]@@x\n",
+            "[link target=*a]go[endlink][s]",
+            "broken [tag a=\"x] and [unclosed\n@line a=\"open\n[]@\n*|",
+            "[eval exp=\"f.a = \\\"]\\\"\"]",
+            "@eval exp=&'f.a + 1'\n[font size=%'s|2' ]",
+        ];
+        for source in sources {
+            assert_eq!(parse(source).to_string(), source);
+        }
+    }
+
+    #[test]
+    fn test_nodes() {
+        let cst = parse("*start|\n@bg  file = 'a b' nowait\nhi[[x[r a=1]");
+        assert_eq!(
+            cst.nodes[0],
+            Node::Label {
+                name: "start".to_string(),
+                heading: Some(String::new()),
+            }
+        );
+        let bg = match &cst.nodes[2] {
+            Node::Tag(tag) => tag,
+            node => panic!("{:?} is not a tag", node),
+        };
+        assert!(!bg.inline);
+        assert_eq!(bg.attribute("file"), Some("a b"));
+        assert_eq!(
+            bg.attributes[0].value,
+            Some(CstValue {
+                before: " ".to_string(),
+                after: " ".to_string(),
+                prefix: None,
+                quote: Some('\''),
+                raw: "a b".to_string(),
+            })
+        );
+        assert_eq!(bg.attributes[1].key, "nowait");
+        assert_eq!(bg.attributes[1].value, None);
+        assert_eq!(cst.nodes[4], Node::Text("hi[[x".to_string()));
+        match &cst.nodes[5] {
+            Node::Tag(tag) => {
+                assert!(tag.inline && tag.name == "r" && tag.attribute("a") == Some("1"))
+            }
+            node => panic!("{:?} is not a tag", node),
+        }
+
+        let font = match &parse("[font size=%'s|2']").nodes[0] {
+            Node::Tag(tag) => tag.attributes[0].value.clone().unwrap(),
+            node => panic!("{:?} is not a tag", node),
+        };
+        assert_eq!((font.prefix, font.quote), (Some('%'), Some('\'')));
+        assert_eq!(font.raw, "s|2");
+
+        let broken = parse("a [b c=\"d] e");
+        assert_eq!(broken.nodes, vec![Node::Text("a [b c=\"d] e".to_string())]);
+    }
+}
@@ -0,0 +1,210 @@
+//! # Format
+//!
+//! Writes scenarios one way: a tag alone on its line as `@tag`, attributes
+//! one space apart as `key=value`, values quoted only when they have to be
+//! and then with `"`, no indentation, no trailing whitespace after labels
+//! and tags, and no run of blank lines. Text and comments are left as they
+//! are otherwise, spaces in text show on screen and the parser reads a
+//! comment as text. The last line ends as it does in the source.
+//!
+//! The layout is worked out on the CST, which reads more than the parser
+//! does. A scenario is only formatted when the parser gets the same tokens
+//! from it before and after.
+
+use std::error::Error;
+
+use crate::interpreter::{
+    cst::{self, CstTag, Node},
+    parser::{parse_ks_lines, Token},
+};
+
+/// the source formatted. Formatting it again changes nothing. A source the
+/// parser rejects, or whose tokens formatting would change, is an error.
+pub fn format(source: &str) -> Result<String, Box<dyn Error>> {
+    let tokens = |s: &str| -> Result<Vec<Token>, Box<dyn Error>> {
+        Ok(parse_ks_lines(s)?.into_iter().map(|(_, t)| t).collect())
+    };
+    let before = tokens(source)?;
+    let formatted = layout(source);
+    match tokens(&formatted) {
+        Ok(after) if after == before => Ok(formatted),
+        _ => Err("formatting would change what the scenario does".into()),
+    }
+}
+
+fn layout(source: &str) -> String {
+    let nodes = cst::parse(source).nodes;
+    let mut out = String::with_capacity(source.len());
+    let mut blank = 0;
+    let mut first = true;
+    let lines = nodes.split(|n| matches!(n, Node::Newline(_)));
+    let mut newlines = nodes.iter().filter_map(|n| match n {
+        Node::Newline(s) => Some(s.as_str()),
+        _ => None,
+    });
+    let default_newline = nodes
+        .iter()
+        .find_map(|n| match n {
+            Node::Newline(s) => Some(s.clone()),
+            _ => None,
+        })
+        .unwrap_or_else(|| "\n".to_string());
+    for line in lines {
+        let newline = newlines.next();
+        let formatted = format_line(line);
+        if formatted.is_empty() {
+            blank += 1;
+            continue;
+        }
+        if blank > 0 && !first {
+            out.push_str(&default_newline);
+        }
+        blank = 0;
+        first = false;
+        out.push_str(&formatted);
+        out.push_str(newline.unwrap_or_default());
+    }
+    out
+}
+
+fn format_line(nodes: &[Node]) -> String {
+    let content: Vec<&Node> = nodes
+        .iter()
+        .skip_while(|n| matches!(n, Node::Whitespace(_)))
+        .collect();
+    match content.as_slice() {
+        [] => String::new(),
+        [Node::Comment(comment)] => format!(";{}", comment),
+        [Node::Label { name, heading }] => match heading {
+            Some(heading) => format!("*{}|{}", name.trim(), heading.trim()),
+            None => format!("*{}", name.trim()),
+        },
+        [Node::Tag(tag)] if !tag.name.is_empty() => format_tag(tag, false),
+        [Node::Tag(tag), Node::Text(t)] if !tag.name.is_empty() && t.trim().is_empty() => {
+            format_tag(tag, false)
+        }
+        _ => content
+            .iter()
+            .map(|n| match n {
+                Node::Tag(tag) => format_tag(tag, true),
+                n => n.to_string(),
+            })
+            .collect(),
+    }
+}
+
+fn format_tag(tag: &CstTag, inline: bool) -> String {
+    let mut out = String::from(if inline { "[" } else { "@" });
+    out.push_str(&tag.name);
+    for attr in &tag.attributes {
+        out.push(' ');
+        out.push_str(&attr.key);
+        if let Some(value) = &attr.value {
+            out.push('=');
+            if let Some(prefix) = value.prefix {
+                out.push(prefix);
+            }
+            out.push_str(&quote(&value.raw, value.quote, inline));
+        }
+    }
+    if inline {
+        out.push(']');
+    }
+    out
+}
+
+/// a value as written between quotes, quoted again if it has to be.
+fn quote(raw: &str, quote: Option<char>, inline: bool) -> String {
+    let special = |c: char| c.is_whitespace() || c == '"' || c == '\'' || inline && c == ']';
+    let escaped = quote.is_some() && raw.contains('\\');
+    if !raw.is_empty() && !escaped && !raw.contains(special) {
+        return raw.to_string();
+    }
+    // the quote the value is written for, unless `"` does as well.
+    let q = match quote {
+        Some('\'') if raw.contains('"') => '\'',
+        None if raw.contains('"') && !raw.contains('\'') => '\'',
+        _ => '"',
+    };
+    if quote.is_some() {
+        return format!("{}{}{}", q, raw, q);
+    }
+    // a backslash is itself outside quotes, escapes start inside.
+    let mut quoted = String::from(q);
+    for c in raw.chars() {
+        if c == '\\' || c == q {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push(q);
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(s: &str) -> Vec<Token> {
+        parse_ks_lines(s)
+            .unwrap()
+            .into_iter()
+            .map(|(_, token)| token)
+            .collect()
+    }
+
+    #[test]
+    fn test_format() {
+        let source =
+            "  ;comment  \r\n\r\n\r\n  *start| heading \r\n\t[bg  file='room'  time=\"100\"]\r\n\
+Hello,[r time=1 ] world. \r\n[eval exp='f.a = \"b\"'] \r\n\
+@title name='a b' storage=\"\" x='c\\'d'\r\n\r\n";
+        let formatted = format(source).unwrap();
+        assert_eq!(
+            formatted,
+            ";comment  \r\n\r\n*start|heading\r\n@bg file=room time=100\r\n\
+Hello,[r time=1] world. \r\n@eval exp='f.a = \"b\"'\r\n\
+@title name=\"a b\" storage=\"\" x=\"c\\'d\"\r\n"
+        );
+        assert_eq!(tokens(&formatted), tokens(source));
+
+        // unquoted values with quotes in them.
+        let source = "@font face=a\"b\n@font face=a\"b'c\\d\n[font face=it's]\n";
+        let formatted = format(source).unwrap();
+        assert_eq!(
+            formatted,
+            "@font face='a\"b'\n@font face=\"a\\\"b'c\\\\d\"\n@font face=\"it's\"\n"
+        );
+        assert_eq!(tokens(&formatted), tokens(source));
+
+        // a quote after the `&` of an expression or the `%` of a parameter.
+        let source = "@eval exp=&'f.a + 1'\n[font size=%'s|2']\n";
+        let formatted = format(source).unwrap();
+        assert_eq!(formatted, "@eval exp=&\"f.a + 1\"\n@font size=%s|2\n");
+        assert_eq!(tokens(&formatted), tokens(source));
+    }
+
+    #[test]
+    fn test_refused() {
+        // the parser takes no spaces around `=`, nor an unclosed tag.
+        assert!(format("[bg file = room]\n").is_err());
+        assert!(format("broken [tag a=\"x] and [unclosed").is_err());
+    }
+
+    #[test]
+    fn test_idempotent() {
+        let sources = [
+            "",
+            "\n\n",
+            "text",
+            "*a\n\n\n@s\n",
+            "[link target=*a]go[endlink]\n[p]  \n   say [[hi]]\n",
+        ];
+        for source in sources {
+            let once = format(source).unwrap();
+            assert_eq!(format(&once).unwrap(), once, "{:?}", source);
+        }
+        assert_eq!(format("\n\n").unwrap(), "");
+        assert_eq!(format("text").unwrap(), "text");
+    }
+}
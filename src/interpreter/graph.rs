@@ -0,0 +1,434 @@
+//! # Graph
+//!
+//! How labels lead to each other: a label falls through to the next one
+//! unless it stops or jumps away first, and `[jump]`, `[call]`, `[link]` and
+//! `[button]` go wherever they point. Edges taken only under a condition,
+//! `cond=` or an `[if]` block, carry the expression. Targets computed at run
+//! time are left out.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::Write,
+};
+
+use crate::interpreter::{
+    parser::{is_dynamic, Tag, Token},
+    scenario::Scenario,
+};
+
+/// a label, or the head of a scenario before its first label with an empty
+/// label.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Node {
+    pub storage: String,
+    pub label: String,
+}
+
+impl Node {
+    fn new(storage: &str, label: &str) -> Node {
+        Node {
+            storage: storage.to_string(),
+            label: label.to_string(),
+        }
+    }
+
+    fn name(&self) -> String {
+        if self.label.is_empty() {
+            self.storage.clone()
+        } else {
+            format!("*{}", self.label)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    Fallthrough,
+    Jump,
+    Call,
+    /// a choice, `[link]` or `[button]`.
+    Link,
+}
+
+impl EdgeKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EdgeKind::Fallthrough => "fallthrough",
+            EdgeKind::Jump => "jump",
+            EdgeKind::Call => "call",
+            EdgeKind::Link => "link",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edge {
+    pub from: Node,
+    pub to: Node,
+    pub kind: EdgeKind,
+    /// the condition the edge is taken under.
+    pub exp: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Graph {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+    /// the nodes no path from the start reaches.
+    pub unreachable: HashSet<Node>,
+}
+
+/// an `[if]` being walked through.
+#[derive(Debug, Default)]
+struct Branch {
+    /// the conditions of the branches before this one.
+    taken: Vec<String>,
+    /// the condition of this branch, `None` in `[else]`.
+    exp: Option<String>,
+}
+
+/// the condition all the branches around hold under, if any.
+fn condition(branches: &[Branch], cond: Option<&String>) -> Option<String> {
+    let mut parts = Vec::new();
+    for b in branches {
+        parts.extend(b.taken.iter().map(|e| format!("!({})", e)));
+        parts.extend(b.exp.clone());
+    }
+    parts.extend(cond.cloned());
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join(" && "))
+    }
+}
+
+/// where a scenario starts: its head, or its first label if nothing comes
+/// before it.
+fn entry(scenario: &Scenario) -> Node {
+    match scenario.get(0) {
+        Some(Token::Label(label)) => Node::new(&scenario.storage, &label.label),
+        _ => Node::new(&scenario.storage, ""),
+    }
+}
+
+impl Graph {
+    /// `resolve` turns a `storage=` value into the storage of one of the
+    /// scenarios; reachability starts from the entry of `start`.
+    pub fn build(
+        scenarios: &[Scenario],
+        resolve: impl Fn(&str) -> Option<String>,
+        start: &str,
+    ) -> Graph {
+        let by_storage: HashMap<&str, &Scenario> =
+            scenarios.iter().map(|s| (s.storage.as_str(), s)).collect();
+        let mut graph = Graph::default();
+        for scenario in scenarios {
+            graph.add_scenario(scenario, &by_storage, &resolve);
+        }
+
+        let mut reached = HashSet::new();
+        let mut queue: VecDeque<Node> = resolve(start)
+            .and_then(|s| by_storage.get(s.as_str()).map(|s| entry(s)))
+            .into_iter()
+            .collect();
+        while let Some(node) = queue.pop_front() {
+            if reached.insert(node.clone()) {
+                queue.extend(
+                    graph
+                        .edges
+                        .iter()
+                        .filter(|e| e.from == node)
+                        .map(|e| e.to.clone()),
+                );
+            }
+        }
+        graph.unreachable = graph
+            .nodes
+            .iter()
+            .filter(|n| !reached.contains(*n))
+            .cloned()
+            .collect();
+        graph
+    }
+
+    fn add_scenario(
+        &mut self,
+        scenario: &Scenario,
+        scenarios: &HashMap<&str, &Scenario>,
+        resolve: &impl Fn(&str) -> Option<String>,
+    ) {
+        let storage = &scenario.storage;
+        let mut current: Option<Node> = None;
+        // whether the script can get to the current token.
+        let mut live = true;
+        let mut branches: Vec<Branch> = Vec::new();
+        for (_, token) in scenario.tokens() {
+            if let Token::Label(label) = token {
+                let node = Node::new(storage, &label.label);
+                if let Some(from) = current.take().filter(|_| live) {
+                    self.edge(from, node.clone(), EdgeKind::Fallthrough, None);
+                }
+                self.nodes.push(node.clone());
+                current = Some(node);
+                live = true;
+                branches.clear();
+                continue;
+            }
+            let from = current.get_or_insert_with(|| {
+                let head = Node::new(storage, "");
+                self.nodes.push(head.clone());
+                head
+            });
+            let tag = match token {
+                Token::Tag(tag) => tag,
+                _ => continue,
+            };
+            match tag.name.as_str() {
+                "if" => branches.push(Branch {
+                    taken: Vec::new(),
+                    exp: tag.attributes.get("exp").cloned(),
+                }),
+                "elsif" | "else" => {
+                    if let Some(b) = branches.last_mut() {
+                        b.taken.extend(b.exp.take());
+                        b.exp = tag
+                            .attributes
+                            .get("exp")
+                            .cloned()
+                            .filter(|_| tag.name == "elsif");
+                    }
+                }
+                "endif" => {
+                    branches.pop();
+                }
+                _ => {}
+            }
+            if !live {
+                continue;
+            }
+            let exp = condition(&branches, tag.attributes.get("cond"));
+            let kind = match tag.name.as_str() {
+                "jump" => EdgeKind::Jump,
+                "call" => EdgeKind::Call,
+                "link" | "button" => EdgeKind::Link,
+                "s" | "return" => {
+                    live = exp.is_some();
+                    continue;
+                }
+                _ => continue,
+            };
+            let from = from.clone();
+            if kind == EdgeKind::Jump && exp.is_none() {
+                live = false;
+            }
+            if let Some(to) = target(tag, scenario, scenarios, resolve) {
+                self.edge(from, to, kind, exp);
+            }
+        }
+    }
+
+    fn edge(&mut self, from: Node, to: Node, kind: EdgeKind, exp: Option<String>) {
+        self.edges.push(Edge {
+            from,
+            to,
+            kind,
+            exp,
+        });
+    }
+
+    pub fn to_dot(&self) -> String {
+        let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+        let id = |n: &Node| quote(&format!("{}*{}", n.storage, n.label));
+        let mut dot = String::from("digraph flow {\n    node [shape=box];\n");
+        let mut storages: Vec<&str> = self.nodes.iter().map(|n| n.storage.as_str()).collect();
+        storages.dedup();
+        for (i, storage) in storages.iter().enumerate() {
+            writeln!(dot, "    subgraph cluster_{} {{", i).unwrap();
+            writeln!(dot, "        label={};", quote(storage)).unwrap();
+            for n in self.nodes.iter().filter(|n| n.storage == *storage) {
+                let style = if self.unreachable.contains(n) {
+                    ", color=red, style=filled, fillcolor=mistyrose"
+                } else {
+                    ""
+                };
+                writeln!(
+                    dot,
+                    "        {} [label={}{}];",
+                    id(n),
+                    quote(&n.name()),
+                    style
+                )
+                .unwrap();
+            }
+            dot.push_str("    }\n");
+        }
+        for e in &self.edges {
+            let mut attrs = vec![match e.kind {
+                EdgeKind::Fallthrough => "style=dotted",
+                EdgeKind::Jump => "style=solid",
+                EdgeKind::Call => "style=bold",
+                EdgeKind::Link => "color=blue",
+            }
+            .to_string()];
+            if let Some(exp) = &e.exp {
+                attrs.push(format!("label={}", quote(exp)));
+            }
+            writeln!(
+                dot,
+                "    {} -> {} [{}];",
+                id(&e.from),
+                id(&e.to),
+                attrs.join(", ")
+            )
+            .unwrap();
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// the graph as JSON, for tools. Only the command line writes it, the
+    /// web front end goes without `serde_json`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn to_json(&self) -> String {
+        use serde_json::json;
+
+        let node = |n: &Node| json!({ "storage": n.storage, "label": n.label });
+        let nodes: Vec<_> = self
+            .nodes
+            .iter()
+            .map(|n| {
+                json!({
+                    "storage": n.storage,
+                    "label": n.label,
+                    "reachable": !self.unreachable.contains(n),
+                })
+            })
+            .collect();
+        let edges: Vec<_> = self
+            .edges
+            .iter()
+            .map(|e| {
+                json!({
+                    "from": node(&e.from),
+                    "to": node(&e.to),
+                    "kind": e.kind.name(),
+                    "exp": e.exp,
+                })
+            })
+            .collect();
+        format!("{}\n", json!({ "nodes": nodes, "edges": edges }))
+    }
+}
+
+/// where a jump-like tag goes, `None` if it is computed or goes nowhere.
+fn target(
+    tag: &Tag,
+    from: &Scenario,
+    scenarios: &HashMap<&str, &Scenario>,
+    resolve: &impl Fn(&str) -> Option<String>,
+) -> Option<Node> {
+    let to = match tag.attributes.get("storage") {
+        Some(name) if is_dynamic(name) => return None,
+        Some(name) => *scenarios.get(resolve(name)?.as_str())?,
+        None => from,
+    };
+    match tag.attributes.get("target") {
+        Some(target) if is_dynamic(target) => None,
+        Some(target) => {
+            to.label(target)?;
+            Some(Node::new(&to.storage, target.trim_start_matches('*')))
+        }
+        None => Some(entry(to)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIRST: &str = "[cm]\n*start|\nhello\n[link target=*left]left[endlink]\
+[link storage=second.ks]right[endlink][s]\n\
+*left|\n[if exp=f.a]\n[jump target=*end]\n[elsif exp=f.b]\n[call storage=second.ks target=*sub]\n\
+[else]\n[jump target=*end cond=f.c]\n[endif]\n\
+*middle|\n[jump target=*end]\n*dead|\n*end|\n";
+    const SECOND: &str = "*top|\nright[s]\n*sub|\n[return]\n*lost|\n[jump storage=&f.x]";
+
+    fn graph() -> Graph {
+        let scenarios = vec![
+            Scenario::parse("first.ks", FIRST).unwrap(),
+            Scenario::parse("second.ks", SECOND).unwrap(),
+        ];
+        Graph::build(&scenarios, |s| Some(s.to_string()), "first.ks")
+    }
+
+    fn edges(g: &Graph) -> Vec<String> {
+        g.edges
+            .iter()
+            .map(|e| {
+                let exp = e
+                    .exp
+                    .as_ref()
+                    .map(|e| format!(" if {}", e))
+                    .unwrap_or_default();
+                format!(
+                    "{} -> {} {}{}",
+                    e.from.name(),
+                    e.to.name(),
+                    e.kind.name(),
+                    exp
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_edges() {
+        assert_eq!(
+            edges(&graph()),
+            vec![
+                "first.ks -> *start fallthrough",
+                "*start -> *left link",
+                "*start -> *top link",
+                "*left -> *end jump if f.a",
+                "*left -> *sub call if !(f.a) && f.b",
+                "*left -> *end jump if !(f.a) && !(f.b) && f.c",
+                "*left -> *middle fallthrough",
+                "*middle -> *end jump",
+                "*dead -> *end fallthrough",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unreachable() {
+        let g = graph();
+        let mut unreachable: Vec<String> = g.unreachable.iter().map(Node::name).collect();
+        unreachable.sort();
+        assert_eq!(unreachable, vec!["*dead", "*lost"]);
+    }
+
+    #[test]
+    fn test_export() {
+        let g = graph();
+        let dot = g.to_dot();
+        assert!(dot.starts_with("digraph flow {"));
+        assert!(dot.contains(
+            "\"first.ks*left\" -> \"second.ks*sub\" [style=bold, label=\"!(f.a) && f.b\"];"
+        ));
+        assert!(dot.contains("\"first.ks*dead\" [label=\"*dead\", color=red"));
+
+        let json: serde_json::Value = serde_json::from_str(&g.to_json()).unwrap();
+        assert!(json["nodes"].as_array().unwrap().contains(
+            &serde_json::json!({ "storage": "second.ks", "label": "lost", "reachable": false })
+        ));
+        assert!(json["edges"]
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!({
+                "from": { "storage": "first.ks", "label": "middle" },
+                "to": { "storage": "first.ks", "label": "end" },
+                "kind": "jump",
+                "exp": null,
+            })));
+    }
+}
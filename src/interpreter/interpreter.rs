@@ -2,55 +2,175 @@ use wasm_bindgen::JsCast;
 use wasm_bindgen_futures::JsFuture;
 use web_sys::{Request, RequestInit};
 
-use crate::interpreter::parser::*;
-use std::fmt::{self, Debug, Formatter};
+use crate::interpreter::{
+    backlog::{Backlog, BacklogEntry, Position},
+    clock::{Clock, EffectKind, Effects, Wait},
+    message::{Align, Cursor, Line, Message, Speed},
+    mode::{auto_delay, Mode},
+    parser::*,
+    persist::{FileStore, LocalStore, Store},
+    read::ReadRecord,
+    rollback::{Rollback, RollbackError},
+    save::SavePoint,
+    scenario::{Scenario, ScenarioCache},
+};
+use crate::vfs::{storage::Storage, text};
+use encoding_rs::Encoding;
+use std::{
+    convert::TryFrom,
+    error::Error,
+    fmt::{self, Debug, Formatter},
+    path::Path,
+    rc::Rc,
+};
+
+/// reads the source of a scenario by its storage name.
+pub type Loader = Box<dyn Fn(&str) -> Result<String, Box<dyn Error>>>;
 
 pub struct State {
+    /// the scenario being played.
+    scenario: Rc<Scenario>,
+    scenarios: ScenarioCache,
+    loader: Loader,
     label: Label,
+    /// the index of the current token after the label.
+    token_index: usize,
+    /// the last label with a `|` passed.
+    save_point: Option<SavePoint>,
     music: String,
+    /// storage names of the layers, the front end resolves them to images.
     scene: Vec<String>,
-    text: Vec<String>,
+    message: Message,
+    clock: Clock,
+    effects: Effects,
+    wait: Option<Wait>,
+    mode: Mode,
+    /// when auto mode clicks, once known.
+    auto_until: Option<u64>,
+    /// characters in the window when the script went on the last time.
+    chars_before: usize,
+    /// the voice of the current text.
+    voice: Option<String>,
+    /// who speaks the current text, set by `[name]`.
+    speaker: Option<String>,
+    backlog: Backlog,
+    /// where the current text starts in the message.
+    entry_cursor: Cursor,
+    /// where the current text starts in the script.
+    entry_position: Option<Position>,
+    read: ReadRecord,
+    /// the read record has changed since it was saved.
+    read_dirty: bool,
+    /// all text since the script went on the last time was read before.
+    text_read: bool,
+    store: Box<dyn Store>,
+    rollback: Rollback<Snapshot>,
     cur_token: Option<Token>,
-    tokens: Box<dyn Iterator<Item = Token>>,
+    /// the index of the next token in the scenario.
+    pc: usize,
+    /// problems in the script found since the front end showed them.
+    warnings: Vec<String>,
+}
+
+/// what rollback restores: where the script is, what is on screen and how
+/// far the backlog went.
+/// The clock goes on, a running effect is dropped and the wait is found
+/// again by `eval`. Variables are not kept, the interpreter has none yet.
+#[derive(Debug, Clone)]
+struct Snapshot {
+    scenario: Rc<Scenario>,
+    pc: usize,
+    label: Label,
+    token_index: usize,
+    save_point: Option<SavePoint>,
+    cur_token: Option<Token>,
+    scene: Vec<String>,
+    message: Message,
+    /// `Backlog::pushed` when the page started.
+    backlog: usize,
 }
 
 impl Debug for State {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.debug_struct("State")
+            .field("storage", &self.scenario.storage)
+            .field("pc", &self.pc)
             .field("label", &self.label)
+            .field("token_index", &self.token_index)
             .field("music", &self.music)
             .field("scene", &self.scene)
-            .field("text", &self.text)
+            .field("message", &self.message)
+            .field("clock", &self.clock)
+            .field("wait", &self.wait)
+            .field("mode", &self.mode)
+            .field("voice", &self.voice)
             .finish()
     }
 }
 
+/// the directory of system variables for the command line.
+const SAVE_DIR: &str = "savedata";
+/// the key of the read record among system variables.
+const READ_KEY: &str = "read";
+/// game macros that wait for their `time` unless told `nowait=true`. Other
+/// tags with a `time`, like `[fadeoutbgm]`, go on at once.
+const TIMED_MACROS: &[&str] = &["sestop"];
+
 #[derive(Debug, Clone)]
 pub struct RenderContext {
     pub scene: Vec<String>,
-    pub text: Vec<String>,
+    pub text: Vec<Line>,
+    pub mode: Mode,
+    /// problems in the script, `storage:line: what`, for the front end to show.
+    pub warnings: Vec<String>,
 }
 
 impl State {
-    pub fn new_from_ks(filename: &str) -> State {
-        let mut s = State {
-            label: Label {
-                label: String::new(),
-                heading: String::new(),
-            },
-            music: String::new(),
-            scene: Vec::new(),
-            text: Vec::new(),
-            cur_token: None,
-            tokens: Box::new(parse_ks(filename).unwrap()),
+    /// other scenarios are looked for in the directory of `filename`.
+    pub fn new_from_ks(filename: &str) -> Result<State, Box<dyn Error>> {
+        let path = Path::new(filename);
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let start = path
+            .file_name()
+            .ok_or_else(|| format!("{} is not a file", filename))?
+            .to_string_lossy();
+        let mut storage = Storage::new();
+        storage.mount_dir(if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        })?;
+        State::new_from_storage(storage, &start, None, None)
+    }
+
+    /// plays `start` from `label` on. Scenarios without a BOM are decoded
+    /// from `encoding`, or guessed if it is `None`.
+    pub fn new_from_storage(
+        storage: Storage,
+        start: &str,
+        label: Option<&str>,
+        encoding: Option<&'static Encoding>,
+    ) -> Result<State, Box<dyn Error>> {
+        let loader: Loader =
+            Box::new(move |name| Ok(text::decode(&storage.read(name, &["ks"])?, encoding)?));
+        let scenario = Scenario::parse(start, &loader(start)?)?;
+        let pc = match label {
+            Some(label) => scenario
+                .label(label)
+                .ok_or_else(|| format!("no label {} in {}", label, start))?,
+            None => 0,
         };
-        s.eval();
-        s
+        Ok(State::new_from_scenario(
+            scenario,
+            pc,
+            Box::new(FileStore::new(SAVE_DIR)),
+            loader,
+        ))
     }
 
     pub async fn new_from_web(url: &str) -> State {
-        let mut opts = RequestInit::new();
-        opts.method("GET");
+        let opts = RequestInit::new();
+        opts.set_method("GET");
         let url = format!("/{}", url);
         let request = Request::new_with_str_and_init(&url, &opts).unwrap();
 
@@ -64,23 +184,74 @@ impl State {
             .unwrap()
             .as_string()
             .unwrap();
+        State::new_from_scenario(
+            Scenario::parse(&url, &text).unwrap(),
+            0,
+            Box::new(LocalStore::default()),
+            // fetching is async, the browser only plays the scenario it started with.
+            Box::new(|storage| Err(format!("{} is not loaded", storage).into())),
+        )
+    }
+
+    fn new_from_scenario(
+        scenario: Scenario,
+        pc: usize,
+        store: Box<dyn Store>,
+        loader: Loader,
+    ) -> State {
+        let read = ReadRecord::from_text(&store.load(READ_KEY).unwrap_or_default());
+        let mut scenarios = ScenarioCache::new();
+        let scenario = scenarios.insert(scenario);
         let mut s = State {
+            scenario,
+            scenarios,
+            loader,
             label: Label {
                 label: String::new(),
-                heading: String::new(),
+                heading: None,
             },
+            token_index: 0,
+            save_point: None,
             music: String::new(),
             scene: Vec::new(),
-            text: Vec::new(),
+            message: Message::new(),
+            clock: Clock::new(),
+            effects: Effects::new(),
+            wait: None,
+            mode: Mode::Normal,
+            auto_until: None,
+            chars_before: 0,
+            voice: None,
+            speaker: None,
+            backlog: Backlog::new(),
+            entry_cursor: Cursor::default(),
+            entry_position: None,
+            read,
+            read_dirty: false,
+            text_read: true,
+            store,
+            rollback: Rollback::new(),
             cur_token: None,
-            tokens: Box::new(parse_ks_string(text.as_str()).unwrap()),
+            pc,
+            warnings: Vec::new(),
         };
+        s.rollback.push(s.snapshot());
+        s.open_entry();
         s.eval();
         s
     }
 
     pub fn eval(&mut self) {
-        while let Some(token) = self.tokens.next() {
+        self.wait = None;
+        self.auto_until = None;
+        self.chars_before = self.message.char_count();
+        self.text_read = true;
+        while let Some(token) = self.scenario.get(self.pc).cloned() {
+            self.pc += 1;
+            self.token_index = match token {
+                Token::Label(_) => 0,
+                _ => self.token_index + 1,
+            };
             self.cur_token = Some(token.clone());
             if self.eval_token(token) {
                 break;
@@ -91,12 +262,32 @@ impl State {
     fn eval_token(&mut self, token: Token) -> bool {
         match token {
             Token::Label(l) => {
+                if let Some(heading) = &l.heading {
+                    self.save_point = Some(SavePoint {
+                        position: Position {
+                            storage: self.scenario.storage.clone(),
+                            label: l.label.clone(),
+                            index: 0,
+                        },
+                        title: self.title(heading, &l.label),
+                    });
+                }
                 self.label = l;
                 false
             }
             Token::Tag(tag) => self.eval_tag(tag),
             Token::Text(text) => {
-                self.text.push(text);
+                let read =
+                    !self
+                        .read
+                        .mark(&self.scenario.storage, &self.label.label, self.token_index);
+                self.read_dirty |= !read;
+                self.text_read &= read;
+                self.message.set_read(read);
+                self.message.push_text(&text);
+                if self.entry_position.is_none() {
+                    self.entry_position = Some(self.position());
+                }
                 false
             }
         }
@@ -104,15 +295,280 @@ impl State {
 
     fn eval_tag(&mut self, tag: Tag) -> bool {
         match tag.name.as_str() {
-            "lr" | "pg" => true,
+            "r" => {
+                self.message.line_break();
+                false
+            }
+            "lr" => {
+                self.message.line_break();
+                self.wait = Some(Wait::Click);
+                true
+            }
+            "pg" => {
+                self.wait = Some(Wait::Click);
+                true
+            }
+            "s" => {
+                self.wait = Some(Wait::Stop);
+                true
+            }
+            "link" | "button" => {
+                // skip mode stops at choices.
+                if self.mode.is_skip() {
+                    self.mode = Mode::Normal;
+                }
+                false
+            }
+            "voice" | "say" => self.eval_voice(tag),
+            "jump" => self.eval_jump(tag),
+            "blockrollback" => {
+                self.rollback.block();
+                false
+            }
+            "name" => {
+                self.speaker = tag.attributes.get("text").cloned();
+                false
+            }
+            "wait" => self.eval_wait(tag),
+            "wm" => self.eval_wait_effect(tag, EffectKind::Move, false),
+            "wa" => self.eval_wait_effect(tag, EffectKind::Animation, false),
+            "wt" => self.eval_wait_effect(tag, EffectKind::Transition, true),
+            "wq" => self.eval_wait_effect(tag, EffectKind::Quake, false),
+            "ws" => self.eval_wait_effect(tag, EffectKind::Sound, false),
+            "move" => self.start_effect(tag, EffectKind::Move),
+            "trans" => self.start_effect(tag, EffectKind::Transition),
+            "quake" => self.start_effect(tag, EffectKind::Quake),
+            "fadeinse" | "fadeoutse" | "fadese" => self.start_effect(tag, EffectKind::Sound),
+            "playse" => {
+                // a looping sound never ends, `[ws]` does not wait for it.
+                if !self.attr_bool(&tag, "loop", false) {
+                    self.effects.start(EffectKind::Sound, None);
+                }
+                false
+            }
+            "animstart" => {
+                self.effects.start(EffectKind::Animation, None);
+                false
+            }
+            "stopmove" => self.finish_effect(EffectKind::Move),
+            "animstop" => self.finish_effect(EffectKind::Animation),
+            "stoptrans" => self.finish_effect(EffectKind::Transition),
+            "stopquake" => self.finish_effect(EffectKind::Quake),
+            "stopse" => self.finish_effect(EffectKind::Sound),
+            "ruby" => self.eval_ruby(tag),
+            "font" => {
+                self.message.set_font(&tag.attributes);
+                false
+            }
+            "deffont" => {
+                self.message.set_default_font(&tag.attributes);
+                false
+            }
+            "resetfont" => {
+                self.message.reset_font();
+                false
+            }
+            "style" => self.eval_style(tag),
+            "delay" => self.eval_delay(tag),
+            "nowait" => {
+                self.message.set_nowait(true);
+                false
+            }
+            "endnowait" => {
+                self.message.set_nowait(false);
+                false
+            }
+            "resetstyle" => {
+                self.message.reset_style();
+                false
+            }
             "bg" => self.eval_bg(tag),
+            name if TIMED_MACROS.contains(&name) => self.eval_timed_macro(tag),
             _ => false,
         }
     }
 
+    /// the value of an attribute, left out if it does not convert. That is
+    /// reported unless the value is computed, which the interpreter cannot do
+    /// yet.
+    fn attr<T>(
+        &mut self,
+        tag: &Tag,
+        key: &str,
+        value: Result<Option<T>, AttributeError>,
+    ) -> Option<T> {
+        value.unwrap_or_else(|e| {
+            if !tag.string(key).is_some_and(is_dynamic) {
+                self.warn(e);
+            }
+            None
+        })
+    }
+
+    /// reports a problem at the token being evaluated.
+    fn warn(&mut self, problem: impl fmt::Display) {
+        let warning = format!("{}: {}", self.location(), problem);
+        self.warnings.push(warning);
+    }
+
+    /// the title of a save point. An empty heading keeps the last title, like
+    /// KAG keeps the page name, and one that cannot be computed is the label.
+    fn title(&self, heading: &Heading, label: &str) -> String {
+        match heading {
+            Heading::Text(text) if text.is_empty() => self
+                .save_point
+                .as_ref()
+                .map_or_else(|| label.to_string(), |p| p.title.clone()),
+            Heading::Text(text) => text.clone(),
+            Heading::Expression(exp) => self.evaluate(exp).unwrap_or_else(|| label.to_string()),
+        }
+    }
+
+    /// the value of a TJS expression. The interpreter has no TJS yet, only
+    /// a quoted string has a value.
+    fn evaluate(&self, exp: &str) -> Option<String> {
+        let exp = exp.trim();
+        ['"', '\''].iter().find_map(|&q| {
+            let s = exp.strip_prefix(q)?.strip_suffix(q)?;
+            (!s.contains(q)).then(|| s.to_string())
+        })
+    }
+
+    /// the last save point passed, what a save slot keeps and titles itself by.
+    pub fn save_point(&self) -> Option<&SavePoint> {
+        self.save_point.as_ref()
+    }
+
+    fn attr_u64(&mut self, tag: &Tag, key: &str) -> Option<u64> {
+        self.attr(tag, key, tag.int(key))
+            .and_then(|v| u64::try_from(v).ok())
+    }
+
+    fn attr_bool(&mut self, tag: &Tag, key: &str, default: bool) -> bool {
+        self.attr(tag, key, tag.bool(key)).unwrap_or(default)
+    }
+
+    /// `[jump storage= target=]`, either may be left out.
+    fn eval_jump(&mut self, tag: Tag) -> bool {
+        if let Err(e) = self.jump(
+            tag.attributes.get("storage").map(String::as_str),
+            tag.attributes.get("target").map(String::as_str),
+        ) {
+            // a broken jump goes on with the next token, as if it was not there.
+            self.warn(format_args!("jump failed: {}", e));
+        }
+        false
+    }
+
+    /// `storage:line` of the token being evaluated.
+    pub fn location(&self) -> String {
+        let line = self.pc.checked_sub(1).and_then(|pc| self.scenario.line(pc));
+        match line {
+            Some(line) => format!("{}:{}", self.scenario.storage, line),
+            None => self.scenario.storage.clone(),
+        }
+    }
+
+    /// moves the program counter to the label in the storage, the current
+    /// scenario by default. Without a target it goes to the top of the storage,
+    /// before any label.
+    pub fn jump(
+        &mut self,
+        storage: Option<&str>,
+        target: Option<&str>,
+    ) -> Result<(), Box<dyn Error>> {
+        let scenario = match storage {
+            Some(storage) if storage != self.scenario.storage => {
+                let loader = &self.loader;
+                self.scenarios.get_or_load(storage, |s| loader(s))?
+            }
+            _ => self.scenario.clone(),
+        };
+        let pc = match target {
+            Some(target) => scenario
+                .label(target)
+                .ok_or_else(|| format!("no label {} in {}", target, scenario.storage))?,
+            None => {
+                self.label = Label {
+                    label: String::new(),
+                    heading: None,
+                };
+                self.token_index = 0;
+                0
+            }
+        };
+        self.scenario = scenario;
+        self.pc = pc;
+        Ok(())
+    }
+
+    fn eval_voice(&mut self, tag: Tag) -> bool {
+        if let Some(storage) = tag.attributes.get("storage") {
+            self.effects.finish(EffectKind::Voice);
+            self.effects.start(EffectKind::Voice, None);
+            self.voice = Some(storage.clone());
+        }
+        false
+    }
+
+    fn eval_wait(&mut self, tag: Tag) -> bool {
+        let time = self.attr_u64(&tag, "time").unwrap_or(0);
+        if time == 0 {
+            return false;
+        }
+        self.wait = Some(Wait::Time {
+            until: self.clock.now() + time,
+            canskip: self.attr_bool(&tag, "canskip", true),
+        });
+        true
+    }
+
+    fn eval_wait_effect(&mut self, tag: Tag, kind: EffectKind, canskip: bool) -> bool {
+        if !self.effects.is_running(kind) {
+            return false;
+        }
+        self.wait = Some(Wait::Effect {
+            kind,
+            canskip: self.attr_bool(&tag, "canskip", canskip),
+        });
+        true
+    }
+
+    fn start_effect(&mut self, tag: Tag, kind: EffectKind) -> bool {
+        if let Some(time) = self.attr_u64(&tag, "time") {
+            self.effects.start(kind, Some(self.clock.now() + time));
+        }
+        false
+    }
+
+    fn finish_effect(&mut self, kind: EffectKind) -> bool {
+        self.effects.finish(kind);
+        false
+    }
+
+    fn eval_timed_macro(&mut self, tag: Tag) -> bool {
+        let time = self.attr_u64(&tag, "time").unwrap_or(0);
+        if time == 0 || self.attr_bool(&tag, "nowait", false) {
+            return false;
+        }
+        self.effects
+            .start(EffectKind::Other, Some(self.clock.now() + time));
+        self.wait = Some(Wait::Effect {
+            kind: EffectKind::Other,
+            canskip: true,
+        });
+        true
+    }
+
     fn eval_bg(&mut self, tag: Tag) -> bool {
-        let image_src = format!("/bgimage/{}.png", tag.attributes["file"]);
-        if self.scene.len() < 1 {
+        let image_src = match tag.require("file") {
+            Ok(file) => file.to_string(),
+            Err(e) => {
+                self.warn(e);
+                return false;
+            }
+        };
+        if self.scene.is_empty() {
             self.scene.push(image_src);
         } else {
             self.scene[0] = image_src;
@@ -120,24 +576,272 @@ impl State {
         false
     }
 
+    fn eval_ruby(&mut self, tag: Tag) -> bool {
+        if let Some(ruby) = tag.attributes.get("text") {
+            self.message.set_ruby(ruby);
+        }
+        false
+    }
+
+    fn eval_style(&mut self, tag: Tag) -> bool {
+        if let Some(align) = tag.attributes.get("align").and_then(|a| Align::parse(a)) {
+            self.message.set_align(align);
+        }
+        false
+    }
+
+    fn eval_delay(&mut self, tag: Tag) -> bool {
+        let speed = match tag.attributes.get("speed").map(String::as_str) {
+            Some("user") => Some(Speed::User),
+            Some("nowait") => Some(Speed::Fixed(0)),
+            Some(ms) => ms.parse().ok().map(Speed::Fixed),
+            None => None,
+        };
+        if let Some(speed) = speed {
+            self.message.set_speed(speed);
+        }
+        false
+    }
+
+    /// the player's text speed, in milliseconds per character.
+    pub fn set_text_speed(&mut self, ms: u32) {
+        self.message.set_user_speed(ms);
+    }
+
     pub fn eval_cmd(&mut self, command: &str) {
         match command {
             "MouseClick" | "Enter" => {
-                if let Some(Token::Tag(tag)) = &self.cur_token {
-                    if tag.name == "pg" {
-                        self.text.clear();
+                // a click stops auto mode and skip mode first.
+                if self.mode != Mode::Normal {
+                    self.mode = Mode::Normal;
+                    return;
+                }
+                // the first click finishes the text being displayed.
+                if !self.message.is_revealed() {
+                    self.message.reveal_all();
+                    return;
+                }
+                match self.wait {
+                    Some(wait) if wait.can_skip() => {
+                        if let Wait::Effect { kind, .. } = wait {
+                            self.effects.finish(kind);
+                        }
+                        self.resume();
                     }
+                    None => self.resume(),
+                    Some(_) => {}
                 }
-                self.eval();
             }
+            // the front end has typed out all the text.
+            "TextRevealed" => self.message.reveal_all(),
+            "SoundEnded" => self.end_effect(EffectKind::Sound),
+            "AnimationEnded" => self.end_effect(EffectKind::Animation),
+            "VoiceEnded" => self.end_effect(EffectKind::Voice),
+            "Auto" => self.mode = self.mode.toggle(Mode::Auto),
+            "Skip" => self.mode = self.mode.toggle(Mode::SkipRead),
+            "SkipAll" => self.mode = self.mode.toggle(Mode::SkipAll),
             _ => {}
         }
     }
 
+    /// goes on from a wait, a new page starts after `[pg]`.
+    fn resume(&mut self) {
+        self.log_entry();
+        if let Some(Token::Tag(tag)) = &self.cur_token {
+            if tag.name == "pg" {
+                self.message.clear();
+                self.rollback.push(self.snapshot());
+            }
+        }
+        self.voice = None;
+        self.speaker = None;
+        self.save_read();
+        self.open_entry();
+        self.eval();
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            scenario: self.scenario.clone(),
+            pc: self.pc,
+            label: self.label.clone(),
+            token_index: self.token_index,
+            save_point: self.save_point.clone(),
+            cur_token: self.cur_token.clone(),
+            scene: self.scene.clone(),
+            message: self.message.clone(),
+            backlog: self.backlog.pushed(),
+        }
+    }
+
+    /// pages the player can step back now.
+    pub fn rollback_available(&self) -> usize {
+        self.rollback.available()
+    }
+
+    /// goes back to the start of the page `n` pages before the current one.
+    pub fn rollback(&mut self, n: usize) -> Result<(), RollbackError> {
+        let snapshot = self.rollback.rollback(n)?;
+        self.scenario = snapshot.scenario;
+        self.pc = snapshot.pc;
+        self.label = snapshot.label;
+        self.token_index = snapshot.token_index;
+        self.save_point = snapshot.save_point;
+        self.cur_token = snapshot.cur_token;
+        self.scene = snapshot.scene;
+        self.message = snapshot.message;
+        self.backlog.rewind(snapshot.backlog);
+        self.effects = Effects::new();
+        self.mode = Mode::Normal;
+        self.voice = None;
+        self.speaker = None;
+        self.open_entry();
+        self.eval();
+        Ok(())
+    }
+
+    /// puts the text since the script went on the last time into the backlog.
+    fn log_entry(&mut self) {
+        let lines = self.message.since(self.entry_cursor);
+        if let (false, Some(position)) = (lines.is_empty(), self.entry_position.take()) {
+            self.backlog.push(BacklogEntry {
+                speaker: self.speaker.clone(),
+                lines,
+                voice: self.voice.clone(),
+                position,
+            });
+        }
+    }
+
+    /// the next text starts a backlog entry. Waits the clock or an effect
+    /// ends keep the entry open, it is logged at the next click.
+    fn open_entry(&mut self) {
+        self.entry_cursor = self.message.cursor();
+        self.entry_position = None;
+    }
+
+    fn position(&self) -> Position {
+        Position {
+            storage: self.scenario.storage.clone(),
+            label: self.label.label.clone(),
+            index: self.token_index,
+        }
+    }
+
+    pub fn backlog(&self) -> &Backlog {
+        &self.backlog
+    }
+
+    fn save_read(&mut self) {
+        if self.read_dirty {
+            // losing the record only makes skip mode stop earlier next time.
+            let _ = self.store.save(READ_KEY, &self.read.to_text());
+            self.read_dirty = false;
+        }
+    }
+
+    /// whether the text at the label was read, in this play or before.
+    pub fn is_read_at(&self, storage: &str, label: &str, index: usize) -> bool {
+        self.read.is_read(storage, label, index)
+    }
+
+    /// an effect without a known duration is reported over by the front end.
+    fn end_effect(&mut self, kind: EffectKind) {
+        self.effects.finish(kind);
+        if matches!(self.wait, Some(Wait::Effect { kind: k, .. }) if k == kind) {
+            self.eval();
+        }
+    }
+
+    /// when auto mode clicks: after the text is out and the voice is over.
+    fn auto_end(&mut self) -> Option<u64> {
+        if self.auto_until.is_none() && !self.effects.is_running(EffectKind::Voice) {
+            let chars = self.message.char_count().saturating_sub(self.chars_before);
+            let delay = auto_delay(chars, self.voice.is_some());
+            self.auto_until = Some(self.clock.now() + self.message.remaining_time() + delay);
+        }
+        self.auto_until
+    }
+
+    /// the text since the script went on the last time was read before.
+    fn is_read(&self) -> bool {
+        self.text_read
+    }
+
+    /// skip mode passes one wait per tick, so the front end still sees the pages fly by.
+    fn skip_step(&mut self) -> bool {
+        match self.wait {
+            Some(wait) if wait.can_skip() => {
+                if self.mode == Mode::SkipRead && !self.is_read() {
+                    self.mode = Mode::Normal;
+                    return false;
+                }
+                self.message.reveal_all();
+                if let Wait::Effect { kind, .. } = wait {
+                    self.effects.finish(kind);
+                }
+                self.resume();
+                true
+            }
+            // the clock takes care of waits that can not be skipped.
+            Some(Wait::Time { .. }) | Some(Wait::Effect { .. }) => false,
+            // `[s]` and the end of the script
+            _ => {
+                self.mode = Mode::Normal;
+                false
+            }
+        }
+    }
+
+    /// moves the clock forward by `ms` milliseconds. Waits that end in
+    /// between resume the script at the exact time they end, so the result
+    /// does not depend on how the time is sliced.
+    /// Returns whether the script went on.
+    pub fn tick(&mut self, ms: u64) -> bool {
+        let mut resumed = self.mode.is_skip() && self.skip_step();
+        let target = self.clock.now() + ms;
+        loop {
+            let end = match self.wait {
+                Some(Wait::Time { until, .. }) => Some(until),
+                Some(Wait::Effect { kind, .. }) => self.effects.end_of(kind),
+                Some(Wait::Click) if self.mode == Mode::Auto => self.auto_end(),
+                _ => None,
+            };
+            match end {
+                Some(end) if end <= target => {
+                    self.advance_to(end);
+                    if self.wait == Some(Wait::Click) {
+                        self.resume();
+                    } else {
+                        self.eval();
+                    }
+                    resumed = true;
+                }
+                _ => break,
+            }
+        }
+        self.advance_to(target);
+        resumed
+    }
+
+    fn advance_to(&mut self, time: u64) {
+        let ms = time.saturating_sub(self.clock.now());
+        self.clock.advance(ms);
+        self.effects.update(self.clock.now());
+        self.message.advance(ms);
+    }
+
+    /// forgets the warnings once the front end has shown them.
+    pub fn clear_warnings(&mut self) {
+        self.warnings.clear();
+    }
+
     pub(crate) fn get_render_ctx(&self) -> RenderContext {
         RenderContext {
             scene: self.scene.clone(),
-            text: self.text.clone(),
+            text: self.message.lines().to_vec(),
+            mode: self.mode,
+            warnings: self.warnings.clone(),
         }
     }
 }
@@ -146,25 +850,450 @@ impl State {
 mod tests {
 
     use super::*;
+    use crate::interpreter::{message::Font, persist::MemoryStore};
+
+    fn text(s: &State) -> Vec<String> {
+        s.message
+            .lines()
+            .iter()
+            .map(|line| line.runs.iter().map(|run| run.text.as_str()).collect())
+            .collect()
+    }
 
     /// This test only runs in my local machine.
     #[ignore]
     #[test]
     fn test_state() {
-        let mut s = State::new_from_ks("public/lorerei.ks");
-        assert_eq!(s.text, vec!["I go outside with Illya."]);
-        assert_eq!(s.scene, vec!["/bgimage/o衛宮邸外観-(昼).png"]);
+        let mut s = State::new_from_ks("public/lorerei.ks").unwrap();
+        assert_eq!(text(&s), vec!["I go outside with Illya."]);
+        assert_eq!(s.scene, vec!["o衛宮邸外観-(昼)"]);
         s.eval_cmd("MouseClick");
-        assert_eq!(s.text, vec![
+        assert_eq!(text(&s), vec![
             "I go outside with Illya.",
             "We can’t spare the time to go shopping often, so we’ll have to push ourselves and buy about three days’ worth of groceries.\n"
          ]);
         s.eval_cmd("Enter");
         assert_eq!(
-            s.text,
+            text(&s),
             vec![
                "“Then let’s buy a lot. What do you want, Illya? Well, we have to start with today’s lunch.”\n"
             ]
          )
     }
+
+    fn state(ks: &str) -> State {
+        state_with(ks, &[])
+    }
+
+    /// `files` are the other scenarios `[jump]` can load.
+    fn state_with(ks: &str, files: &[(&str, &str)]) -> State {
+        let files = files
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<std::collections::HashMap<_, _>>();
+        State::new_from_scenario(
+            Scenario::parse("test.ks", ks).unwrap(),
+            0,
+            Box::new(MemoryStore::new()),
+            Box::new(move |storage| files.get(storage).cloned().ok_or("no such file".into())),
+        )
+    }
+
+    fn runs(s: &State) -> Vec<(String, Option<String>)> {
+        s.get_render_ctx().text[0]
+            .runs
+            .iter()
+            .map(|run| (run.text.clone(), run.ruby.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_ruby() {
+        let s = state("これは[ruby text=かん]漢字です[lr]");
+        assert_eq!(
+            runs(&s),
+            vec![
+                ("これは".to_string(), None),
+                ("漢".to_string(), Some("かん".to_string())),
+                ("字です".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_font() {
+        let s =
+            state("[style align=center][font color=0x00ff00 italic=true]士郎[resetfont]「hi」[lr]");
+        let line = &s.get_render_ctx().text[0];
+        assert_eq!(line.align, Align::Center);
+        assert_eq!(
+            line.runs.iter().map(|r| r.font.clone()).collect::<Vec<_>>(),
+            vec![
+                Font {
+                    color: Some(0x00ff00),
+                    italic: Some(true),
+                    ..Font::default()
+                },
+                Font::default(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_click_reveals_text_first() {
+        let mut s = state("[delay speed=50]first[nowait]line[endnowait][lr]second[lr]");
+        assert_eq!(
+            s.get_render_ctx().text[0]
+                .runs
+                .iter()
+                .map(|r| (r.text.as_str(), r.delay))
+                .collect::<Vec<_>>(),
+            vec![("first", 50), ("line", 0)]
+        );
+        s.eval_cmd("MouseClick");
+        assert!(s.message.is_revealed());
+        assert_eq!(s.get_render_ctx().text.len(), 1);
+        s.eval_cmd("MouseClick");
+        assert_eq!(s.get_render_ctx().text.len(), 2);
+
+        // text without a delay is on screen at once, the click goes on.
+        let mut s = state("[nowait]first[lr]second[lr]");
+        s.eval_cmd("MouseClick");
+        assert_eq!(s.get_render_ctx().text.len(), 2);
+    }
+
+    #[test]
+    fn test_wait() {
+        let mut s = state("a[wait time=100]b[wait time=100 canskip=false]c[lr]");
+        assert_eq!(text(&s), vec!["a"]);
+        assert!(!s.tick(99));
+        assert!(s.tick(1));
+        assert_eq!(text(&s), vec!["ab"]);
+        s.eval_cmd("TextRevealed");
+        s.eval_cmd("MouseClick");
+        assert_eq!(text(&s), vec!["ab"]);
+        s.tick(100);
+        assert_eq!(text(&s), vec!["abc"]);
+        assert_eq!(s.wait, Some(Wait::Click));
+    }
+
+    #[test]
+    fn test_tick_is_deterministic() {
+        let ks = "[wait time=30]a[wait time=30]b[wait time=30]c";
+        let mut once = state(ks);
+        once.tick(90);
+        let mut sliced = state(ks);
+        for _ in 0..9 {
+            sliced.tick(10);
+        }
+        assert_eq!(text(&once), vec!["abc"]);
+        assert_eq!(text(&once), text(&sliced));
+        assert_eq!(once.clock, sliced.clock);
+    }
+
+    #[test]
+    fn test_wait_effects() {
+        let mut s = state("[quake time=200][move time=100]a[wm]b[wq]c[playse storage=x][ws]d");
+        assert_eq!(text(&s), vec!["a"]);
+        s.tick(100);
+        assert_eq!(text(&s), vec!["ab"]);
+        s.eval_cmd("TextRevealed");
+        // quake can not be skipped by default
+        s.eval_cmd("MouseClick");
+        assert_eq!(text(&s), vec!["ab"]);
+        s.tick(100);
+        assert_eq!(text(&s), vec!["abc"]);
+        s.tick(10000);
+        assert_eq!(text(&s), vec!["abc"]);
+        s.eval_cmd("SoundEnded");
+        assert_eq!(text(&s), vec!["abcd"]);
+    }
+
+    #[test]
+    fn test_timed_macro() {
+        let s = state("@sestop file=se009 time=1500 nowait=true\na");
+        assert_eq!(text(&s), vec!["a"]);
+        let mut s = state("@sestop file=se009 time=1500\na");
+        assert!(text(&s).is_empty());
+        s.tick(1500);
+        assert_eq!(text(&s), vec!["a"]);
+        let s = state("[fadeoutbgm time=2000][myplugin time=100]a");
+        assert_eq!(text(&s), vec!["a"]);
+    }
+
+    #[test]
+    fn test_stop() {
+        let mut s = state("a[s]b");
+        s.eval_cmd("TextRevealed");
+        s.eval_cmd("MouseClick");
+        assert_eq!(text(&s), vec!["a"]);
+        assert_eq!(s.wait, Some(Wait::Stop));
+    }
+
+    #[test]
+    fn test_auto_mode() {
+        let mut s = state("[nowait]ab[lr]c[say storage=v1]d[lr]e");
+        s.eval_cmd("Auto");
+        s.tick(1000 + 2 * 50 - 1);
+        assert_eq!(text(&s), vec!["ab"]);
+        s.tick(1);
+        assert_eq!(text(&s), vec!["ab", "cd"]);
+        assert_eq!(s.voice, Some("v1".to_string()));
+        // waits for the voice
+        s.tick(60000);
+        assert_eq!(text(&s), vec!["ab", "cd"]);
+        s.eval_cmd("VoiceEnded");
+        s.tick(500);
+        assert_eq!(text(&s), vec!["ab", "cd", "e"]);
+        s.eval_cmd("MouseClick");
+        assert_eq!(s.get_render_ctx().mode, Mode::Normal);
+    }
+
+    #[test]
+    fn test_skip_mode() {
+        let mut s = state("a[lr]b[wait time=1000]c[pg]d[link target=*x]choice[endlink][s]");
+        s.eval_cmd("SkipAll");
+        s.tick(1);
+        assert_eq!(text(&s), vec!["a", "b"]);
+        s.tick(1);
+        assert_eq!(text(&s), vec!["a", "bc"]);
+        s.tick(1);
+        assert_eq!(text(&s), vec!["dchoice"]);
+        assert_eq!(s.mode, Mode::Normal);
+
+        let mut s = state("a[lr]b");
+        s.eval_cmd("Skip");
+        s.tick(1);
+        assert_eq!(text(&s), vec!["a"]);
+        assert_eq!(s.mode, Mode::Normal);
+    }
+
+    #[test]
+    fn test_read() {
+        const KS: &str = "*page1|\na[lr]b[lr]\n*page2|\nc[lr]";
+        let mut s = state(KS);
+        assert!(!s.get_render_ctx().text[0].runs[0].read);
+        s.eval_cmd("TextRevealed");
+        s.eval_cmd("MouseClick");
+        assert!(s.is_read_at("test.ks", "page1", 1));
+        assert!(!s.is_read_at("test.ks", "page2", 1));
+
+        // a second play with the same store
+        let mut store = MemoryStore::new();
+        store.save(READ_KEY, &s.read.to_text()).unwrap();
+        let mut s = State::new_from_scenario(
+            Scenario::parse("test.ks", KS).unwrap(),
+            0,
+            Box::new(store),
+            Box::new(|_| Err("no such file".into())),
+        );
+        assert!(s.get_render_ctx().text[0].runs[0].read);
+        s.eval_cmd("Skip");
+        s.tick(1);
+        assert_eq!(text(&s), vec!["a", "b"]);
+        s.tick(1);
+        assert_eq!(text(&s), vec!["a", "b", "c"]);
+        // page2 is new, skip stops there
+        s.tick(1);
+        assert_eq!(s.mode, Mode::Normal);
+        assert_eq!(text(&s), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_backlog() {
+        let mut s = state(
+            "*p1|\n[name text=士郎][say storage=v1]「hi」[lr][name text=凛]「yo」[pg]narration[pg]",
+        );
+        for _ in 0..3 {
+            s.eval_cmd("TextRevealed");
+            s.eval_cmd("MouseClick");
+        }
+        let entries = s.backlog().page(0, 10);
+        assert_eq!(
+            entries
+                .iter()
+                .map(|e| (
+                    e.speaker.as_deref(),
+                    e.lines[0].to_string(),
+                    e.voice.as_deref(),
+                    e.position.index
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                (Some("士郎"), "「hi」".to_string(), Some("v1"), 3),
+                (Some("凛"), "「yo」".to_string(), None, 6),
+                (None, "narration".to_string(), None, 8),
+            ]
+        );
+        assert_eq!(entries[0].position.label, "p1");
+    }
+
+    #[test]
+    fn test_backlog_across_waits() {
+        let mut s = state("*p1|\nfirst[wait time=100]second[quake time=50][wq]third[pg]");
+        s.tick(150);
+        next_page(&mut s);
+        let entries = s.backlog().page(0, 10);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].lines[0].to_string(), "firstsecondthird");
+        assert_eq!(entries[0].position.index, 1);
+    }
+
+    #[test]
+    fn test_save_point() {
+        let mut s = state(
+            "*start|Prologue\none[pg]\n*loop\ntwo[pg]\n*p2|\nthree[pg]\n*p3|&'Chapter 1'\nfour[pg]\n\
+             *p4|&f.title\nfive[pg]",
+        );
+        let title = |s: &State| {
+            s.save_point()
+                .map(|p| (p.position.label.clone(), p.title.clone()))
+        };
+        assert_eq!(
+            title(&s),
+            Some(("start".to_string(), "Prologue".to_string()))
+        );
+        next_page(&mut s);
+        assert_eq!(
+            title(&s),
+            Some(("start".to_string(), "Prologue".to_string()))
+        );
+        next_page(&mut s);
+        assert_eq!(title(&s), Some(("p2".to_string(), "Prologue".to_string())));
+        next_page(&mut s);
+        assert_eq!(title(&s), Some(("p3".to_string(), "Chapter 1".to_string())));
+        next_page(&mut s);
+        assert_eq!(title(&s), Some(("p4".to_string(), "p4".to_string())));
+        s.rollback(3).unwrap();
+        assert_eq!(
+            title(&s),
+            Some(("start".to_string(), "Prologue".to_string()))
+        );
+    }
+
+    fn next_page(s: &mut State) {
+        s.eval_cmd("TextRevealed");
+        s.eval_cmd("MouseClick");
+    }
+
+    #[test]
+    fn test_rollback() {
+        let mut s = state("[bg file=a]one[pg][bg file=b]two[lr]more[pg]three[pg]");
+        next_page(&mut s);
+        next_page(&mut s);
+        next_page(&mut s);
+        assert_eq!(text(&s), vec!["three"]);
+        s.rollback(1).unwrap();
+        assert_eq!(text(&s), vec!["two"]);
+        assert_eq!(s.scene, vec!["b"]);
+        s.rollback(1).unwrap();
+        assert_eq!(text(&s), vec!["one"]);
+        assert_eq!(s.scene, vec!["a"]);
+        assert_eq!(s.rollback(1), Err(RollbackError::TooFar));
+        next_page(&mut s);
+        assert_eq!(text(&s), vec!["two"]);
+    }
+
+    #[test]
+    fn test_rollback_backlog() {
+        let mut s = state("one[pg]two[pg]three[pg]four[pg]");
+        next_page(&mut s);
+        next_page(&mut s);
+        assert_eq!(s.backlog().len(), 2);
+        s.rollback(1).unwrap();
+        assert_eq!(s.backlog().len(), 1);
+        next_page(&mut s);
+        next_page(&mut s);
+        let entries = s.backlog().page(0, 10);
+        let lines: Vec<_> = entries.iter().map(|e| e.lines[0].to_string()).collect();
+        assert_eq!(lines, vec!["one", "two", "three"]);
+    }
+
+    #[test]
+    fn test_block_rollback() {
+        let mut s = state("one[pg]two[blockrollback][pg]three[pg]four[pg]");
+        next_page(&mut s);
+        next_page(&mut s);
+        next_page(&mut s);
+        assert_eq!(text(&s), vec!["four"]);
+        assert_eq!(s.rollback(2), Err(RollbackError::Blocked));
+        s.rollback(1).unwrap();
+        assert_eq!(text(&s), vec!["three"]);
+    }
+
+    #[test]
+    fn test_jump() {
+        let mut s = state_with(
+            "a[jump target=*next]skipped[lr]\n*next|\nb[lr][jump storage=other.ks target=*start]",
+            &[(
+                "other.ks",
+                "never[lr]\n*start|\nc[lr][jump storage=test.ks]",
+            )],
+        );
+        assert_eq!(text(&s), vec!["ab"]);
+        next_page(&mut s);
+        assert_eq!(text(&s), vec!["ab", "c"]);
+        assert_eq!(s.scenario.storage, "other.ks");
+        assert_eq!(s.location(), "other.ks:3");
+        next_page(&mut s);
+        assert_eq!(text(&s), vec!["ab", "c", "ab"]);
+        // other.ks is parsed once
+        assert!(Rc::ptr_eq(
+            &s.scenarios.get("other.ks").unwrap(),
+            &s.scenarios
+                .get_or_load("other.ks", |_| Err("reparsed".into()))
+                .unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_warnings() {
+        let mut s = state("[bg][wait time=x]\n[jump target=*nowhere]a[lr]b");
+        assert_eq!(
+            s.get_render_ctx().warnings,
+            vec![
+                "test.ks:1: [bg] needs file=".to_string(),
+                "test.ks:1: [wait] time=x is not an integer".to_string(),
+                "test.ks:2: jump failed: no label *nowhere in test.ks".to_string(),
+            ]
+        );
+        s.clear_warnings();
+        next_page(&mut s);
+        assert!(s.get_render_ctx().warnings.is_empty());
+    }
+
+    #[test]
+    fn test_warnings_kept_until_shown() {
+        let mut s = state(
+            "a[lr]
+[bg]b[lr]",
+        );
+        s.eval_cmd("TextRevealed");
+        s.eval_cmd("MouseClick");
+        s.tick(16);
+        assert_eq!(
+            s.get_render_ctx().warnings,
+            vec!["test.ks:2: [bg] needs file=".to_string()]
+        );
+        s.clear_warnings();
+        assert!(s.get_render_ctx().warnings.is_empty());
+    }
+
+    #[test]
+    fn test_jump_to_top() {
+        let mut s = state_with(
+            "*a|\none two[lr][jump storage=other.ks]",
+            &[("other.ks", "x[lr]y[lr]")],
+        );
+        next_page(&mut s);
+        assert_eq!(text(&s), vec!["one two", "x"]);
+        let position = s.position();
+        assert_eq!(
+            (position.storage.as_str(), position.label.as_str()),
+            ("other.ks", "")
+        );
+        // at the `[lr]` after the text
+        assert_eq!(position.index, 2);
+        assert!(s.is_read_at("other.ks", "", 1));
+    }
 }
@@ -0,0 +1,270 @@
+//! # Lint
+//!
+//! Finds the mistakes a game only trips over when the player gets there:
+//! tags nobody defines, jumps to labels or scenarios that do not exist and
+//! assets missing from the storage.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use encoding_rs::Encoding;
+
+use crate::{
+    interpreter::{
+        parser::{is_dynamic, Tag, Token},
+        scenario::Scenario,
+    },
+    vfs::{
+        storage::{Storage, IMAGE_EXTS},
+        text,
+    },
+};
+
+/// the tags of KAG3 and the ones this interpreter adds.
+#[rustfmt::skip]
+pub const KAG_TAGS: &[&str] = &[
+    "animstart", "animstop", "backlay", "bg", "bgmopt", "blockrollback", "button", "call",
+    "cancelautomode", "cancelskip", "cancelvideoevent", "cancelvideosegloop", "ch",
+    "clearbgmlabel", "clearbgmstop", "clearsysvar", "clearvar", "clickskip", "close", "cm",
+    "copybookmark", "current", "cursor", "deffont", "defstyle", "delay", "disablestore",
+    "else", "elsif", "emb", "endhact", "endif", "endignore", "endindent", "endlink",
+    "endmacro", "endnowait", "endscript", "erasebookmark", "erasemacro", "er", "eval",
+    "fadebgm", "fadeinbgm", "fadeinse", "fadeoutbgm", "fadeoutse", "fadepausebgm", "fadese",
+    "font", "glyph", "goback", "gotostart", "graph", "hact", "hch", "hidemessage",
+    "history", "if", "ignore", "image", "indent", "iscript", "jump", "l", "laycount",
+    "layopt", "link", "load", "loadplugin", "locate", "locklink", "locksnapshot", "lr",
+    "macro", "mapaction", "mapdisable", "mapimage", "move", "name", "nextskip", "nowait",
+    "openvideo", "p", "pausebgm", "pg", "pimage", "playbgm", "playse", "playvideo",
+    "position", "preparevideo", "ptext", "quake", "r", "record", "redraw", "resetfont",
+    "resetstyle", "resetwait", "resumebgm", "resumevideo", "return", "rewindvideo", "ruby",
+    "s", "save", "say", "seopt", "setbgmlabel", "setbgmstop", "showhistory", "startanchor",
+    "stopbgm", "stopmove", "stopquake", "stopse", "stoptrans", "stopvideo", "store",
+    "style", "tempload", "tempsave", "timeout", "title", "trace", "trans", "unlocklink",
+    "unlocksnapshot", "video", "videoevent", "videolayer", "videosegloop", "voice", "wa",
+    "wait", "waitclick", "wb", "wc", "wf", "wl", "wm", "wp", "wq", "ws", "wt", "wv",
+    "xchgbgm",
+];
+
+/// tags that go to another place of a scenario with `storage` and `target`.
+const JUMP_TAGS: &[&str] = &["jump", "call", "link", "button"];
+
+/// extensions tried for sounds.
+pub const SOUND_EXTS: &[&str] = &["ogg", "wav", "mp3"];
+
+/// the asset attributes of tags and the extensions tried for them.
+const ASSETS: &[(&str, &str, &[&str])] = &[
+    ("bg", "file", IMAGE_EXTS),
+    ("bg", "storage", IMAGE_EXTS),
+    ("image", "storage", IMAGE_EXTS),
+    ("pimage", "storage", IMAGE_EXTS),
+    ("button", "graphic", IMAGE_EXTS),
+    ("playbgm", "storage", SOUND_EXTS),
+    ("fadeinbgm", "storage", SOUND_EXTS),
+    ("xchgbgm", "storage", SOUND_EXTS),
+    ("playse", "storage", SOUND_EXTS),
+    ("fadeinse", "storage", SOUND_EXTS),
+    ("voice", "storage", SOUND_EXTS),
+    ("say", "storage", SOUND_EXTS),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Diagnostic {
+    pub storage: String,
+    /// counted from 1, 0 if the whole file is concerned.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.storage, self.line, self.message)
+    }
+}
+
+/// parses every scenario of the storage, reporting the ones that fail.
+pub fn load(
+    storage: &Storage,
+    encoding: Option<&'static Encoding>,
+) -> (Vec<Scenario>, Vec<Diagnostic>) {
+    let mut scenarios = Vec::new();
+    let mut diagnostics = Vec::new();
+    for file in storage.files() {
+        if !file.to_lowercase().ends_with(".ks") {
+            continue;
+        }
+        let parsed = storage
+            .read(&file, &[])
+            .and_then(|data| Ok(text::decode(&data, encoding)?))
+            .and_then(|source| Scenario::parse(&file, &source));
+        match parsed {
+            Ok(scenario) => scenarios.push(scenario),
+            Err(e) => diagnostics.push(Diagnostic {
+                storage: file,
+                line: 0,
+                message: e.to_string(),
+            }),
+        }
+    }
+    (scenarios, diagnostics)
+}
+
+/// lints every scenario of the storage. `tags` are known besides KAG's own
+/// and the macros the scenarios define, e.g. tags of plugins.
+pub fn lint(
+    storage: &Storage,
+    encoding: Option<&'static Encoding>,
+    tags: &[String],
+) -> Vec<Diagnostic> {
+    let (scenarios, mut diagnostics) = load(storage, encoding);
+    diagnostics.extend(check(storage, &scenarios, tags));
+    diagnostics.sort();
+    diagnostics
+}
+
+/// lints scenarios already parsed, which may differ from the ones in the
+/// storage, e.g. while they are edited. The storage has the assets.
+pub fn check(storage: &Storage, scenarios: &[Scenario], tags: &[String]) -> Vec<Diagnostic> {
+    let scenarios: Vec<&Scenario> = scenarios.iter().collect();
+    check_some(storage, &scenarios, &scenarios, tags)
+}
+
+/// checks only `linted`, against the labels and macros of all `scenarios`.
+pub fn check_some(
+    storage: &Storage,
+    scenarios: &[&Scenario],
+    linted: &[&Scenario],
+    tags: &[String],
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let scenarios: HashMap<String, &Scenario> = scenarios
+        .iter()
+        .map(|s| (s.storage.to_lowercase(), *s))
+        .collect();
+
+    let mut known: HashSet<&str> = KAG_TAGS.iter().copied().collect();
+    known.extend(tags.iter().map(String::as_str));
+    for scenario in scenarios.values() {
+        for (_, token) in scenario.tokens() {
+            if let Token::Tag(tag) = token {
+                if tag.name == "macro" {
+                    if let Some(name) = tag.attributes.get("name") {
+                        known.insert(name);
+                    }
+                }
+            }
+        }
+    }
+
+    for scenario in linted {
+        for (line, token) in scenario.tokens() {
+            let tag = match token {
+                Token::Tag(tag) => tag,
+                _ => continue,
+            };
+            let mut report = |message: String| {
+                diagnostics.push(Diagnostic {
+                    storage: scenario.storage.clone(),
+                    line,
+                    message,
+                })
+            };
+            if !known.contains(tag.name.as_str()) {
+                report(format!("unknown tag [{}]", tag.name));
+            }
+            if JUMP_TAGS.contains(&tag.name.as_str()) {
+                if let Some(message) = check_jump(tag, scenario, &scenarios, storage) {
+                    report(message);
+                }
+            }
+            for (_, attr, exts) in ASSETS.iter().filter(|(name, ..)| *name == tag.name) {
+                match tag.attributes.get(*attr) {
+                    // an empty value is being typed or left for the tag's default.
+                    Some(value) if value.is_empty() || is_dynamic(value) => {}
+                    Some(value) if !storage.exists(value, exts) => {
+                        report(format!("[{}] {}={} is not found", tag.name, attr, value))
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    diagnostics
+}
+
+/// what is wrong with where a jump-like tag goes, if anything.
+fn check_jump(
+    tag: &Tag,
+    from: &Scenario,
+    scenarios: &HashMap<String, &Scenario>,
+    storage: &Storage,
+) -> Option<String> {
+    let to = match tag.attributes.get("storage") {
+        Some(name) if is_dynamic(name) => return None,
+        Some(name) => {
+            // scenarios not saved yet are not in the storage.
+            let unsaved = vec![name.to_string(), format!("{}.ks", name)]
+                .into_iter()
+                .find(|n| scenarios.contains_key(&n.to_lowercase()));
+            let path = match storage.resolve(name, &["ks"]).or(unsaved) {
+                Some(path) => path,
+                None => return Some(format!("[{}] storage={} is not found", tag.name, name)),
+            };
+            scenarios.get(&path.to_lowercase())?
+        }
+        None => from,
+    };
+    let target = tag.attributes.get("target")?;
+    if is_dynamic(target) || to.label(target).is_some() {
+        return None;
+    }
+    Some(format!(
+        "[{}] target={} is not a label of {}",
+        tag.name, target, to.storage
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::xp3::{self, Xp3};
+
+    fn storage(files: &[(&str, &str)]) -> Storage {
+        let files: Vec<(&str, &[u8])> = files.iter().map(|(n, c)| (*n, c.as_bytes())).collect();
+        let mut s = Storage::new();
+        s.mount_archive(Xp3::new(xp3::pack(&files)).unwrap());
+        s
+    }
+
+    fn messages(storage: &Storage, tags: &[String]) -> Vec<String> {
+        lint(storage, None, tags)
+            .into_iter()
+            .map(|d| d.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_lint() {
+        let s = storage(&[
+            (
+                "scenario/first.ks",
+                "*start|\n[macro name=chara][endmacro]\n[chara][charas]\n\
+                 [jump target=*pge12]\n[jump storage=second.ks target=*end]\n\
+                 [bg file=room][bg file=ruins]\n[playse storage=&f.se][jump storage=third.ks]",
+            ),
+            ("scenario/second.ks", "*end|\n[s]"),
+            ("bgimage/room.tlg", ""),
+        ]);
+        assert_eq!(
+            messages(&s, &[]),
+            vec![
+                "scenario/first.ks:3: unknown tag [charas]",
+                "scenario/first.ks:4: [jump] target=*pge12 is not a label of scenario/first.ks",
+                "scenario/first.ks:6: [bg] file=ruins is not found",
+                "scenario/first.ks:7: [jump] storage=third.ks is not found",
+            ]
+        );
+        assert_eq!(messages(&s, &["charas".to_string()]).len(), 3);
+    }
+}
@@ -0,0 +1,679 @@
+//! # Message
+//!
+//! This module models the text inside the message window. Text is kept as
+//! lines of `TextRun`s, so annotations like ruby and the font can travel with
+//! the characters they belong to.
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+};
+
+/// font settings changed by `[font]` and `[deffont]`.
+/// `None` means the front end decides, usually by its stylesheet.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Font {
+    pub face: Option<String>,
+    pub size: Option<u32>,
+    /// `0xRRGGBB`
+    pub color: Option<u32>,
+    pub bold: Option<bool>,
+    pub italic: Option<bool>,
+    pub shadow: Option<bool>,
+    pub shadow_color: Option<u32>,
+    pub edge: Option<bool>,
+    pub edge_color: Option<u32>,
+}
+
+impl Font {
+    /// updates the font by the attributes of a `[font]`-like tag.
+    /// a value of `default` restores the setting from `base`.
+    pub fn update(&mut self, attributes: &HashMap<String, String>, base: &Font) {
+        for (key, value) in attributes {
+            match key.as_str() {
+                "face" => self.face = pick(value, &base.face, |v| Some(v.to_string())),
+                "size" => self.size = pick(value, &base.size, |v| v.parse().ok()),
+                "color" => self.color = pick(value, &base.color, parse_color),
+                "bold" => self.bold = pick(value, &base.bold, parse_bool),
+                "italic" => self.italic = pick(value, &base.italic, parse_bool),
+                "shadow" => self.shadow = pick(value, &base.shadow, parse_bool),
+                "shadowcolor" => self.shadow_color = pick(value, &base.shadow_color, parse_color),
+                "edge" => self.edge = pick(value, &base.edge, parse_bool),
+                "edgecolor" => self.edge_color = pick(value, &base.edge_color, parse_color),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn pick<T: Clone>(value: &str, base: &Option<T>, parse: fn(&str) -> Option<T>) -> Option<T> {
+    if value == "default" {
+        base.clone()
+    } else {
+        parse(value)
+    }
+}
+
+/// KAG writes colors as `0xRRGGBB`.
+fn parse_color(value: &str) -> Option<u32> {
+    let hex = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .or_else(|| value.strip_prefix('#'))?;
+    u32::from_str_radix(hex, 16).ok()
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+impl Align {
+    /// parses the `align` attribute of `[style]`.
+    pub fn parse(value: &str) -> Option<Align> {
+        match value {
+            "left" | "default" => Some(Align::Left),
+            "center" => Some(Align::Center),
+            "right" => Some(Align::Right),
+            _ => None,
+        }
+    }
+}
+
+/// how fast characters appear, changed by `[delay speed=]`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Speed {
+    /// follows the text speed chosen by the player.
+    #[default]
+    User,
+    /// milliseconds per character.
+    Fixed(u32),
+}
+
+/// milliseconds per character unless the player says otherwise.
+pub const DEFAULT_USER_SPEED: u32 = 30;
+
+/// a piece of text sharing the same annotation, font and display speed.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TextRun {
+    pub text: String,
+    pub ruby: Option<String>,
+    pub font: Font,
+    /// milliseconds between two characters, 0 shows the run at once.
+    pub delay: u32,
+    /// how many characters of the run are already on screen.
+    pub revealed: usize,
+    /// the player has seen the text in an earlier play.
+    pub read: bool,
+}
+
+impl TextRun {
+    /// a run that is shown at once.
+    pub fn plain(text: &str) -> TextRun {
+        TextRun {
+            text: text.to_string(),
+            ruby: None,
+            font: Font::default(),
+            delay: 0,
+            revealed: text.chars().count(),
+            read: false,
+        }
+    }
+
+    pub fn is_revealed(&self) -> bool {
+        self.revealed >= self.text.chars().count()
+    }
+}
+
+/// ruby is shown inline as `漢《かんじ》`, the notation used by plain text novels.
+impl Display for TextRun {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.ruby {
+            Some(ruby) => write!(f, "{}《{}》", self.text, ruby),
+            None => write!(f, "{}", self.text),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Line {
+    pub align: Align,
+    pub runs: Vec<TextRun>,
+}
+
+impl Display for Line {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.runs.iter().try_for_each(|run| write!(f, "{}", run))
+    }
+}
+
+/// a place in the message, to get the text written after it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    line: usize,
+    run: usize,
+    /// byte offset in the run
+    byte: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Message {
+    lines: Vec<Line>,
+    /// set by `[ruby]`, consumed by the next character.
+    pending_ruby: Option<String>,
+    /// the last line is ended by `[r]`/`[lr]`, next text starts a new line.
+    line_closed: bool,
+    font: Font,
+    default_font: Font,
+    align: Align,
+    speed: Speed,
+    /// between `[nowait]` and `[endnowait]`.
+    nowait: bool,
+    user_speed: u32,
+    /// milliseconds spent on the character being revealed.
+    reveal_carry: u64,
+    /// whether the text being pushed was read before.
+    read: bool,
+}
+
+impl Default for Message {
+    fn default() -> Self {
+        Message {
+            lines: Vec::new(),
+            pending_ruby: None,
+            line_closed: false,
+            font: Font::default(),
+            default_font: Font::default(),
+            align: Align::default(),
+            speed: Speed::default(),
+            nowait: false,
+            user_speed: DEFAULT_USER_SPEED,
+            reveal_carry: 0,
+            read: false,
+        }
+    }
+}
+
+impl Message {
+    pub fn new() -> Message {
+        Message::default()
+    }
+
+    pub fn set_ruby(&mut self, ruby: &str) {
+        self.pending_ruby = Some(ruby.to_string());
+    }
+
+    /// `[font]`
+    pub fn set_font(&mut self, attributes: &HashMap<String, String>) {
+        self.font.update(attributes, &self.default_font);
+    }
+
+    /// `[deffont]`, takes effect on the next `[resetfont]`.
+    pub fn set_default_font(&mut self, attributes: &HashMap<String, String>) {
+        self.default_font.update(attributes, &Font::default());
+    }
+
+    /// `[resetfont]`
+    pub fn reset_font(&mut self) {
+        self.font = self.default_font.clone();
+    }
+
+    /// `[style align=]`, applies to the current line if nothing is written on it yet.
+    pub fn set_align(&mut self, align: Align) {
+        self.align = align;
+        if let Some(line) = self.lines.last_mut() {
+            if !self.line_closed && line.runs.is_empty() {
+                line.align = align;
+            }
+        }
+    }
+
+    /// `[resetstyle]`
+    pub fn reset_style(&mut self) {
+        self.set_align(Align::default());
+    }
+
+    /// `[delay speed=]`
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.speed = speed;
+    }
+
+    /// `[nowait]` and `[endnowait]`
+    pub fn set_nowait(&mut self, nowait: bool) {
+        self.nowait = nowait;
+    }
+
+    /// the text speed from the player's settings, in milliseconds per character.
+    pub fn set_user_speed(&mut self, ms: u32) {
+        self.user_speed = ms;
+    }
+
+    /// marks the text pushed from now on as read or unread.
+    pub fn set_read(&mut self, read: bool) {
+        self.read = read;
+    }
+
+    fn delay(&self) -> u32 {
+        match (self.nowait, self.speed) {
+            (true, _) => 0,
+            (false, Speed::User) => self.user_speed,
+            (false, Speed::Fixed(ms)) => ms,
+        }
+    }
+
+    /// how much of a new run of `chars` characters is on screen at once: all
+    /// of it without a delay, the front end does not type it out.
+    fn revealed(&self, chars: usize) -> usize {
+        if self.delay() == 0 {
+            chars
+        } else {
+            0
+        }
+    }
+
+    /// a `\n` in the text breaks the line like `[r]`.
+    pub fn push_text(&mut self, text: &str) {
+        let mut lines = text.split('\n');
+        if let Some(line) = lines.next() {
+            self.push_line(line);
+        }
+        for line in lines {
+            self.line_break();
+            self.push_line(line);
+        }
+    }
+
+    fn push_line(&mut self, text: &str) {
+        let mut chars = text.chars();
+        if let Some(ruby) = self.pending_ruby.take() {
+            match chars.next() {
+                Some(c) => self.push_run(TextRun {
+                    text: c.to_string(),
+                    ruby: Some(ruby),
+                    font: self.font.clone(),
+                    delay: self.delay(),
+                    revealed: self.revealed(1),
+                    read: self.read,
+                }),
+                None => self.pending_ruby = Some(ruby),
+            }
+        }
+        if !chars.as_str().is_empty() {
+            self.push_run(TextRun {
+                text: chars.as_str().to_string(),
+                ruby: None,
+                font: self.font.clone(),
+                delay: self.delay(),
+                revealed: self.revealed(chars.clone().count()),
+                read: self.read,
+            });
+        }
+    }
+
+    fn push_run(&mut self, run: TextRun) {
+        if self.line_closed || self.lines.is_empty() {
+            self.lines.push(Line {
+                align: self.align,
+                runs: Vec::new(),
+            });
+            self.line_closed = false;
+        }
+        let line = self.lines.last_mut().unwrap();
+        match line.runs.last_mut() {
+            Some(last)
+                if last.ruby.is_none()
+                    && run.ruby.is_none()
+                    && last.font == run.font
+                    && last.delay == run.delay
+                    && last.read == run.read =>
+            {
+                last.text.push_str(&run.text);
+                last.revealed += run.revealed;
+            }
+            _ => line.runs.push(run),
+        }
+    }
+
+    pub fn line_break(&mut self) {
+        self.line_closed = true;
+    }
+
+    /// whether every character is on screen.
+    pub fn is_revealed(&self) -> bool {
+        self.lines
+            .iter()
+            .all(|line| line.runs.iter().all(TextRun::is_revealed))
+    }
+
+    /// shows the rest of the text at once.
+    pub fn reveal_all(&mut self) {
+        for run in self.lines.iter_mut().flat_map(|line| line.runs.iter_mut()) {
+            run.revealed = run.text.chars().count();
+        }
+        self.reveal_carry = 0;
+    }
+
+    /// the number of characters in the window.
+    pub fn char_count(&self) -> usize {
+        self.lines
+            .iter()
+            .flat_map(|line| line.runs.iter())
+            .map(|run| run.text.chars().count())
+            .sum()
+    }
+
+    /// milliseconds until every character is on screen.
+    pub fn remaining_time(&self) -> u64 {
+        let total: u64 = self
+            .lines
+            .iter()
+            .flat_map(|line| line.runs.iter())
+            .map(|run| {
+                let pending = run.text.chars().count().saturating_sub(run.revealed);
+                pending as u64 * run.delay as u64
+            })
+            .sum();
+        total.saturating_sub(self.reveal_carry)
+    }
+
+    /// reveals the characters that are due after `ms` milliseconds.
+    pub fn advance(&mut self, ms: u64) {
+        let mut budget = self.reveal_carry + ms;
+        for run in self.lines.iter_mut().flat_map(|line| line.runs.iter_mut()) {
+            let length = run.text.chars().count();
+            let pending = length.saturating_sub(run.revealed);
+            if pending == 0 {
+                continue;
+            }
+            if run.delay == 0 {
+                run.revealed = length;
+                continue;
+            }
+            let typed = pending.min((budget / run.delay as u64) as usize);
+            run.revealed += typed;
+            budget -= typed as u64 * run.delay as u64;
+            if run.revealed < length {
+                self.reveal_carry = budget;
+                return;
+            }
+        }
+        self.reveal_carry = 0;
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.line_closed = false;
+        self.reveal_carry = 0;
+    }
+
+    pub fn lines(&self) -> &[Line] {
+        &self.lines
+    }
+
+    /// the end of the text so far. It is no longer valid after `clear`.
+    pub fn cursor(&self) -> Cursor {
+        match self.lines.last() {
+            Some(_) if self.line_closed => Cursor {
+                line: self.lines.len(),
+                run: 0,
+                byte: 0,
+            },
+            Some(line) => Cursor {
+                line: self.lines.len() - 1,
+                run: line.runs.len().saturating_sub(1),
+                byte: line.runs.last().map_or(0, |run| run.text.len()),
+            },
+            None => Cursor::default(),
+        }
+    }
+
+    /// the text written after the cursor.
+    pub fn since(&self, cursor: Cursor) -> Vec<Line> {
+        let mut lines = self.lines.get(cursor.line..).unwrap_or_default().to_vec();
+        if let Some(first) = lines.first_mut() {
+            first.runs.drain(..cursor.run.min(first.runs.len()));
+            if let Some(run) = first.runs.first_mut() {
+                run.text = run.text[cursor.byte..].to_string();
+                run.revealed = run.text.chars().count();
+                if run.text.is_empty() {
+                    first.runs.remove(0);
+                }
+            }
+        }
+        lines.retain(|line| !line.runs.is_empty());
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attrs(kv: &[(&str, &str)]) -> HashMap<String, String> {
+        kv.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn runs(m: &Message) -> Vec<Vec<(&str, Option<&str>)>> {
+        m.lines()
+            .iter()
+            .map(|line| {
+                line.runs
+                    .iter()
+                    .map(|run| (run.text.as_str(), run.ruby.as_deref()))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_push_text() {
+        let mut m = Message::new();
+        m.push_text("hello ");
+        m.push_text("world");
+        m.line_break();
+        m.push_text("next");
+        assert_eq!(
+            runs(&m),
+            vec![vec![("hello world", None)], vec![("next", None)]]
+        );
+        m.push_text(" line\nand\n");
+        m.push_text("more");
+        assert_eq!(
+            runs(&m)[1..],
+            [
+                vec![("next line", None)],
+                vec![("and", None)],
+                vec![("more", None)]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ruby() {
+        let mut m = Message::new();
+        m.push_text("これは");
+        m.set_ruby("かん");
+        m.push_text("漢字");
+        assert_eq!(
+            runs(&m),
+            vec![vec![("これは", None), ("漢", Some("かん")), ("字", None)]]
+        );
+        assert_eq!(m.lines()[0].to_string(), "これは漢《かん》字");
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut m = Message::new();
+        m.push_text("a");
+        m.clear();
+        assert!(m.lines().is_empty());
+    }
+
+    #[test]
+    fn test_font() {
+        let mut m = Message::new();
+        m.set_default_font(&attrs(&[("size", "24")]));
+        m.reset_font();
+        m.push_text("a");
+        m.set_font(&attrs(&[("color", "0xff0000"), ("bold", "true")]));
+        m.push_text("b");
+        m.set_font(&attrs(&[("color", "default")]));
+        m.push_text("c");
+        m.reset_font();
+        m.push_text("d");
+
+        let fonts = m.lines()[0]
+            .runs
+            .iter()
+            .map(|run| (run.text.as_str(), run.font.clone()))
+            .collect::<Vec<_>>();
+        let default = Font {
+            size: Some(24),
+            ..Font::default()
+        };
+        assert_eq!(
+            fonts,
+            vec![
+                ("a", default.clone()),
+                (
+                    "b",
+                    Font {
+                        color: Some(0xff0000),
+                        bold: Some(true),
+                        ..default.clone()
+                    }
+                ),
+                (
+                    "c",
+                    Font {
+                        bold: Some(true),
+                        ..default.clone()
+                    }
+                ),
+                ("d", default),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_align() {
+        let mut m = Message::new();
+        m.push_text("left");
+        m.set_align(Align::Center);
+        m.push_text(" still left");
+        m.line_break();
+        m.push_text("center");
+        m.reset_style();
+        m.line_break();
+        m.push_text("left again");
+        assert_eq!(
+            m.lines().iter().map(|l| l.align).collect::<Vec<_>>(),
+            vec![Align::Left, Align::Center, Align::Left]
+        );
+    }
+
+    #[test]
+    fn test_delay() {
+        let mut m = Message::new();
+        m.push_text("a");
+        m.set_speed(Speed::Fixed(100));
+        m.push_text("b");
+        m.set_nowait(true);
+        m.push_text("c");
+        m.set_nowait(false);
+        m.set_speed(Speed::User);
+        m.set_user_speed(10);
+        m.push_text("d");
+        assert_eq!(
+            m.lines()[0]
+                .runs
+                .iter()
+                .map(|run| (run.text.as_str(), run.delay))
+                .collect::<Vec<_>>(),
+            vec![("a", DEFAULT_USER_SPEED), ("b", 100), ("c", 0), ("d", 10)]
+        );
+    }
+
+    #[test]
+    fn test_reveal_all() {
+        let mut m = Message::new();
+        m.push_text("你好");
+        assert!(!m.is_revealed());
+        m.reveal_all();
+        assert!(m.is_revealed());
+        assert_eq!(m.lines()[0].runs[0].revealed, 2);
+    }
+
+    #[test]
+    fn test_advance() {
+        let mut m = Message::new();
+        m.set_speed(Speed::Fixed(10));
+        m.push_text("abc");
+        m.set_nowait(true);
+        m.push_text("de");
+        m.set_nowait(false);
+        m.push_text("fg");
+        assert_eq!(m.lines()[0].runs[1].revealed, 2);
+        m.advance(15);
+        assert_eq!(m.lines()[0].runs[0].revealed, 1);
+        m.advance(15);
+        assert_eq!(m.lines()[0].runs[0].revealed, 3);
+        assert_eq!(m.lines()[0].runs[1].revealed, 2);
+        assert_eq!(m.lines()[0].runs[2].revealed, 0);
+        m.advance(20);
+        assert!(m.is_revealed());
+    }
+
+    #[test]
+    fn test_remaining_time() {
+        let mut m = Message::new();
+        m.set_speed(Speed::Fixed(10));
+        m.push_text("abc");
+        assert_eq!(m.char_count(), 3);
+        assert_eq!(m.remaining_time(), 30);
+        m.advance(15);
+        assert_eq!(m.remaining_time(), 15);
+    }
+
+    #[test]
+    fn test_since() {
+        let mut m = Message::new();
+        m.push_text("ab");
+        let c = m.cursor();
+        m.push_text("cd");
+        m.line_break();
+        m.push_text("ef");
+        assert_eq!(
+            m.since(c)
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec!["cd", "ef"]
+        );
+        let c = m.cursor();
+        assert!(m.since(c).is_empty());
+        m.line_break();
+        let c = m.cursor();
+        m.push_text("gh");
+        assert_eq!(
+            m.since(c)
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec!["gh"]
+        );
+    }
+}
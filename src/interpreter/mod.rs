@@ -4,6 +4,49 @@
 //! the state of the game.
 
 /// parser module parses the `.ks` file and returns an iterator.
-mod parser;
+pub(crate) mod parser;
 
+/// cst module keeps every byte of a `.ks` file, so it can be written back.
+pub mod cst;
+
+/// format module writes scenarios in one style.
+pub mod format;
+
+/// scenario module keeps parsed `.ks` files for random access.
+pub mod scenario;
+
+/// lint module finds broken tags, jumps and assets in scenarios.
+pub mod lint;
+
+/// graph module maps how the labels of scenarios lead to each other.
+pub mod graph;
+
+/// translate module takes the text of scenarios out for translators and puts it back.
+pub mod translate;
+
+/// clock module keeps the time of the game, driven by the front end.
+pub mod clock;
+
+/// mode module defines auto mode and skip mode.
+pub mod mode;
+
+/// persist module keeps system variables between plays.
+pub mod persist;
+
+/// read module records the text the player has seen.
+pub mod read;
+
+/// backlog module keeps the text the player has gone through.
+pub mod backlog;
+
+/// save module defines the save points a save slot keeps.
+pub mod save;
+
+/// rollback module keeps snapshots of the last pages.
+pub mod rollback;
+
+/// message module keeps the text shown in the message window.
+pub mod message;
+
+#[allow(clippy::module_inception)]
 pub mod interpreter;
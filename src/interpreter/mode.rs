@@ -0,0 +1,63 @@
+//! # Mode
+//!
+//! Auto mode and skip mode go through the script without clicks.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    #[default]
+    Normal,
+    /// clicks by itself once the text and the voice are over.
+    Auto,
+    /// passes every wait until it meets unread text.
+    SkipRead,
+    /// passes every wait.
+    SkipAll,
+}
+
+impl Mode {
+    pub fn is_skip(&self) -> bool {
+        matches!(self, Mode::SkipRead | Mode::SkipAll)
+    }
+
+    /// the name the front end sees.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Normal => "normal",
+            Mode::Auto => "auto",
+            Mode::SkipRead => "skipRead",
+            Mode::SkipAll => "skipAll",
+        }
+    }
+
+    /// turns `mode` on, or off if it is already on.
+    pub fn toggle(self, mode: Mode) -> Mode {
+        if self == mode {
+            Mode::Normal
+        } else {
+            mode
+        }
+    }
+}
+
+/// milliseconds auto mode waits after text without voice, plus `AUTO_PER_CHAR`
+/// for every character of it.
+pub const AUTO_BASE_DELAY: u64 = 1000;
+pub const AUTO_PER_CHAR: u64 = 50;
+/// milliseconds auto mode waits after the voice ends.
+pub const AUTO_VOICE_DELAY: u64 = 500;
+
+/// how long auto mode waits after `chars` characters of text.
+pub fn auto_delay(chars: usize, voiced: bool) -> u64 {
+    if voiced {
+        AUTO_VOICE_DELAY
+    } else {
+        AUTO_BASE_DELAY + AUTO_PER_CHAR * chars as u64
+    }
+}
+
+#[test]
+fn test_toggle() {
+    assert_eq!(Mode::Normal.toggle(Mode::Auto), Mode::Auto);
+    assert_eq!(Mode::Auto.toggle(Mode::Auto), Mode::Normal);
+    assert_eq!(Mode::Auto.toggle(Mode::SkipAll), Mode::SkipAll);
+}
@@ -1,5 +1,5 @@
 use crate::parsec::*;
-use std::{collections::HashMap, error::Error, fs, rc::Rc, str::Chars};
+use std::{collections::HashMap, error::Error, fmt, ops::Range, rc::Rc};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Tag {
@@ -7,62 +7,225 @@ pub struct Tag {
     pub(crate) attributes: HashMap<String, String>,
 }
 
+/// the value of an attribute given without one, like `canskip` in `[wait canskip]`.
+pub const FLAG: &str = "true";
+
+/// whether an attribute value is computed when the game runs, `&` for an
+/// expression and `%` for an argument of a macro.
+pub fn is_dynamic(value: &str) -> bool {
+    value.starts_with('&') || value.starts_with('%')
+}
+
+/// an attribute a tag lacks or cannot use.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttributeError {
+    Missing {
+        tag: String,
+        key: String,
+    },
+    Invalid {
+        tag: String,
+        key: String,
+        value: String,
+        expected: &'static str,
+    },
+}
+
+impl fmt::Display for AttributeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AttributeError::Missing { tag, key } => write!(f, "[{}] needs {}=", tag, key),
+            AttributeError::Invalid {
+                tag,
+                key,
+                value,
+                expected,
+            } => write!(f, "[{}] {}={} is not {}", tag, key, value, expected),
+        }
+    }
+}
+
+impl Error for AttributeError {}
+
+#[test]
+fn test_attributes() {
+    let tag = match parse_line_tag()(&mut Input::new(
+        "@wait time=1500 canskip volume=0.5 hex=0x1f bad=1e mode=until",
+    ))
+    .unwrap()
+    {
+        Token::Tag(tag) => tag,
+        token => panic!("{:?} is not a tag", token),
+    };
+    assert_eq!(tag.int("time"), Ok(Some(1500)));
+    assert_eq!(tag.int("hex"), Ok(Some(31)));
+    assert_eq!(tag.bool("canskip"), Ok(Some(true)));
+    assert_eq!(tag.real("volume"), Ok(Some(0.5)));
+    assert_eq!(tag.real("time"), Ok(Some(1500.0)));
+    assert_eq!(tag.bool("nowait"), Ok(None));
+    assert_eq!(tag.string("mode"), Some("until"));
+    assert_eq!(
+        tag.int("bad").unwrap_err().to_string(),
+        "[wait] bad=1e is not an integer"
+    );
+    assert_eq!(
+        tag.bool("mode").unwrap_err().to_string(),
+        "[wait] mode=until is not true or false"
+    );
+    assert_eq!(
+        tag.require("storage").unwrap_err().to_string(),
+        "[wait] needs storage="
+    );
+}
+
+impl Tag {
+    pub fn string(&self, key: &str) -> Option<&str> {
+        self.attributes.get(key).map(String::as_str)
+    }
+
+    /// the value of an attribute the tag cannot do without.
+    pub fn require(&self, key: &str) -> Result<&str, AttributeError> {
+        self.string(key).ok_or_else(|| AttributeError::Missing {
+            tag: self.name.clone(),
+            key: key.to_string(),
+        })
+    }
+
+    /// converts a value if given, telling what it should have been if it does not convert.
+    fn convert<T>(
+        &self,
+        key: &str,
+        expected: &'static str,
+        f: impl Fn(&str) -> Option<T>,
+    ) -> Result<Option<T>, AttributeError> {
+        self.string(key)
+            .map(|value| {
+                f(value.trim()).ok_or_else(|| AttributeError::Invalid {
+                    tag: self.name.clone(),
+                    key: key.to_string(),
+                    value: value.to_string(),
+                    expected,
+                })
+            })
+            .transpose()
+    }
+
+    /// `true` or `false` in any case, a flag is `true`.
+    pub fn bool(&self, key: &str) -> Result<Option<bool>, AttributeError> {
+        self.convert(key, "true or false", |v| match v.to_lowercase().as_str() {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => None,
+        })
+    }
+
+    /// decimal, or hexadecimal after `0x`.
+    pub fn int(&self, key: &str) -> Result<Option<i64>, AttributeError> {
+        self.convert(key, "an integer", |v| {
+            let (sign, digits) = match v.strip_prefix('-') {
+                Some(digits) => (-1, digits),
+                None => (1, v),
+            };
+            match digits
+                .strip_prefix("0x")
+                .or_else(|| digits.strip_prefix("0X"))
+            {
+                Some(hex) => i64::from_str_radix(hex, 16).ok(),
+                None => digits.parse().ok(),
+            }
+            .map(|n: i64| sign * n)
+        })
+    }
+
+    pub fn real(&self, key: &str) -> Result<Option<f64>, AttributeError> {
+        self.convert(key, "a number", |v| v.parse().ok())
+    }
+}
+
+/// a label is a save point if it is written with a `|`, `*name|heading`.
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Label {
     pub(crate) label: String,
-    pub(crate) heading: String,
+    pub(crate) heading: Option<Heading>,
+}
+
+impl Label {
+    pub fn is_save_point(&self) -> bool {
+        self.heading.is_some()
+    }
 }
 
+/// what is after the `|` of a label, the title of the save point.
 #[derive(Debug, PartialEq, Eq, Clone)]
-pub enum Token {
-    Label(Label),
-    Tag(Tag),
+pub enum Heading {
+    /// empty if nothing is after the `|`.
     Text(String),
+    /// after `&`, evaluated when the label is passed.
+    Expression(String),
 }
 
-fn lift_tag(v: Vec<String>) -> Token {
-    let mut attributes = HashMap::new();
-    let name = v[0].clone();
-    for attr in v[1..].iter() {
-        let attr: Vec<&str> = attr.split('=').collect();
-        attributes.insert(attr[0].to_string(), attr[1].to_string());
+impl fmt::Display for Heading {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Heading::Text(text) => write!(f, "{}", text),
+            Heading::Expression(exp) => write!(f, "&{}", exp),
+        }
     }
-    Token::Tag(Tag { name, attributes })
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Token {
+    Label(Label),
+    Tag(Tag),
+    Text(String),
 }
 
 #[test]
 fn test_parse_label() {
-    let mut input = "*page12|".chars();
-    let p = parse_label();
+    let label = |input: &str| match parse_label()(&mut Input::new(input)).unwrap() {
+        Token::Label(label) => label,
+        token => panic!("{:?} is not a label", token),
+    };
     assert_eq!(
-        p(&mut input).unwrap(),
-        Token::Label(Label {
+        label("*page12|wakeup \n"),
+        Label {
             label: "page12".to_string(),
-            heading: "page12".to_string(),
-        })
+            heading: Some(Heading::Text("wakeup".to_string())),
+        }
     );
-}
-
-#[test]
-fn test_parse_label_heading() {
-    let mut input = "*page12|wakeup".chars();
-    let p = parse_label();
     assert_eq!(
-        p(&mut input).unwrap(),
-        Token::Label(Label {
+        label("*page0|&f.scripttitle"),
+        Label {
+            label: "page0".to_string(),
+            heading: Some(Heading::Expression("f.scripttitle".to_string())),
+        }
+    );
+    assert_eq!(
+        label("*page12|"),
+        Label {
             label: "page12".to_string(),
-            heading: "wakeup".to_string(),
-        })
+            heading: Some(Heading::Text(String::new())),
+        }
     );
-}
-
-fn parse_label() -> Parsec<Token> {
-    Rc::new(|input: &mut Chars| {
-        let label = between(parse_char('*'), string_none_of("|"), parse_char('|'))(input)?;
-        let heading = if let Ok(Some(heading)) = optional(nonspace())(input) {
-            heading
-        } else {
-            label.clone()
+    let plain = label("*loop\r\n");
+    assert_eq!(plain.label, "loop");
+    assert!(!plain.is_save_point());
+}
+
+/// `*name`, a save point if a `|` and maybe a heading follow.
+fn parse_label<'a>() -> Parsec<'a, Token> {
+    Rc::new(|input: &mut Input<'a, str>| {
+        parse_char('*')(input)?;
+        let label = string_none_of("|\r\n")(input)?.trim_end().to_string();
+        let heading = match optional(parse_char('|'))(input)? {
+            Some(_) => {
+                let heading = recognize(many(none_of("\r\n")))(input)?.trim();
+                Some(match heading.strip_prefix('&') {
+                    Some(exp) => Heading::Expression(exp.to_string()),
+                    None => Heading::Text(heading.to_string()),
+                })
+            }
+            None => None,
         };
         Ok(Token::Label(Label { label, heading }))
     })
@@ -70,7 +233,7 @@ fn parse_label() -> Parsec<Token> {
 
 #[test]
 fn test_parse_line_tag() {
-    let mut input = "@a2aT a=2".chars();
+    let mut input = Input::new("@a2aT a=2");
     let p = parse_line_tag();
     assert_eq!(
         p(&mut input).unwrap(),
@@ -87,7 +250,7 @@ fn test_parse_line_tag() {
 
 #[test]
 fn test_parse_quoted_tag() {
-    let mut input = "@eval exp=\"sf.scriptresname = '桜ルート十二日目'\"".chars();
+    let mut input = Input::new("@eval exp=\"sf.scriptresname = '桜ルート十二日目'\"");
     let p = parse_line_tag();
     assert_eq!(
         p(&mut input).unwrap(),
@@ -105,29 +268,66 @@ fn test_parse_quoted_tag() {
     );
 }
 
-fn parse_line_tag() -> Parsec<Token> {
-    Rc::new(|input: &mut Chars| {
-        parse_char('@')(input)?;
-        let name = word()(input)?;
-        spaces()(input)?;
-        let attributes = sep_by(parse_key_value(), spaces())(input)?
+#[test]
+fn test_parse_line_tag_continued() {
+    let mut input = Input::new("@bg file=room \\\n    time=100\\\r\n  canskip\n@s");
+    let p = parse_line_tag();
+    assert_eq!(
+        p(&mut input).unwrap(),
+        Token::Tag(Tag {
+            name: "bg".to_string(),
+            attributes: {
+                let mut map = HashMap::new();
+                map.insert("file".to_string(), "room".to_string());
+                map.insert("time".to_string(), "100".to_string());
+                map.insert("canskip".to_string(), FLAG.to_string());
+                map
+            }
+        })
+    );
+    assert_eq!(input.as_str(), "\n@s");
+}
+
+/// `@` and a tag, `@@` is text.
+fn parse_line_tag<'a>() -> Parsec<'a, Token> {
+    Rc::new(|input: &mut Input<'a, str>| {
+        attempt(between(parse_char('@'), lookahead(none_of("@")), pure(())))(input)?;
+        parse_tag_body(false)(input)
+    })
+}
+
+/// the name and attributes of a tag after its `@` or `[`. Both kinds take
+/// the same attributes, an inlined tag ends before its `]`.
+fn parse_tag_body<'a>(inline: bool) -> Parsec<'a, Token> {
+    Rc::new(move |input: &mut Input<'a, str>| {
+        let name = label("tag name", string_none_of(" \t\r\n@[]=\\"))(input)?;
+        tag_spaces()(input)?;
+        let attribute = Rc::new(move |input: &mut Input<'a, str>| {
+            let key_value = parse_key_value(inline)(input)?;
+            tag_spaces()(input)?;
+            Ok(key_value)
+        });
+        let attributes = many(attribute)(input)?
             .into_iter()
             .collect::<HashMap<String, String>>();
-        Ok(Token::Tag(Tag { name, attributes }))
+        Ok(Token::Tag(Tag {
+            name: name.to_string(),
+            attributes,
+        }))
     })
 }
 
 #[test]
 fn test_parse_key_value() {
-    let mut input = "a=2".chars();
-    let p = parse_key_value();
+    let mut input = Input::new("a=2");
+    let p = parse_key_value(false);
     assert_eq!(p(&mut input).unwrap(), ("a".to_string(), "2".to_string()));
 }
 
 #[test]
 fn test_parse_quoted_key_value() {
-    let mut input = "exp=\"sf.scriptresname = '桜ルート十二日目'\"".chars();
-    let p = parse_key_value();
+    let mut input = Input::new("exp=\"sf.scriptresname = '桜ルート十二日目'\"");
+    let p = parse_key_value(false);
     assert_eq!(
         p(&mut input).unwrap(),
         (
@@ -137,37 +337,114 @@ fn test_parse_quoted_key_value() {
     );
 }
 
-fn parse_key_value() -> Parsec<(String, String)> {
-    Rc::new(|input: &mut Chars| {
-        let key = string_none_of("= \r\t\n")(input)?;
-        parse_char('=')(input)?;
-        let value = quoted_string()(input)?;
-        Ok((key, value))
+#[test]
+fn test_parse_flag() {
+    let mut input = Input::new("canskip time=1");
+    let p = parse_key_value(false);
+    assert_eq!(
+        p(&mut input).unwrap(),
+        ("canskip".to_string(), FLAG.to_string())
+    );
+}
+
+fn parse_key_value<'a>(inline: bool) -> Parsec<'a, (String, String)> {
+    Rc::new(move |input: &mut Input<'a, str>| {
+        let key = if inline {
+            string_none_of("= \r\t\n\\]")(input)?
+        } else {
+            string_none_of("= \r\t\n\\")(input)?
+        };
+        let value = match optional(parse_char('='))(input)? {
+            Some(_) => parse_value(inline)(input)?,
+            None => FLAG.to_string(),
+        };
+        Ok((key.to_string(), value))
     })
 }
 
 #[test]
-fn test_quoted_string() {
-    let mut input = "\"sf.scriptresname = '桜ルート十二日目'\"".chars();
-    let p = quoted_string();
+fn test_parse_value() {
+    let p = parse_value(false);
     assert_eq!(
-        p(&mut input).unwrap(),
-        "sf.scriptresname = '桜ルート十二日目'".to_string()
+        p(&mut Input::new("\"sf.scriptresname = '桜ルート十二日目'\"")).unwrap(),
+        "sf.scriptresname = '桜ルート十二日目'"
+    );
+    assert_eq!(p(&mut Input::new("'say \"hi\"'")).unwrap(), "say \"hi\"");
+    assert_eq!(p(&mut Input::new("2 a=1")).unwrap(), "2");
+    assert_eq!(p(&mut Input::new("\"\"")).unwrap(), "");
+    assert_eq!(
+        p(&mut Input::new(r#""a \"b\" \\ c\nd""#)).unwrap(),
+        "a \"b\" \\ c\nd"
+    );
+    assert_eq!(p(&mut Input::new("&f.a")).unwrap(), "&f.a");
+    assert_eq!(p(&mut Input::new("&'f.a + 1'")).unwrap(), "&f.a + 1");
+    assert_eq!(p(&mut Input::new("%time|100")).unwrap(), "%time|100");
+    assert_eq!(p(&mut Input::new("a]")).unwrap(), "a]");
+    assert_eq!(parse_value(true)(&mut Input::new("a]")).unwrap(), "a");
+    assert!(p(&mut Input::new("\"open")).is_err());
+    assert_eq!(
+        p(&mut Input::new("\"open\nline\""))
+            .unwrap_err()
+            .to_string(),
+        "1:6: Unexpected char \n"
     );
 }
 
-#[test]
-fn test_quoted_string_but_unquoted() {
-    let mut input = "2".chars();
-    let p = quoted_string();
-    assert_eq!(p(&mut input).unwrap(), "2".to_string());
+/// a value after `=`, quoted with `"` or `'` if it holds spaces or, inlined,
+/// a `]`. In quotes a backslash escapes the character after it, `\n` and
+/// `\t` being a newline and a tab. A quote may follow the `&` of an
+/// expression or the `%` of a macro parameter, which are kept for the
+/// interpreter to resolve.
+fn parse_value<'a>(inline: bool) -> Parsec<'a, String> {
+    Rc::new(move |input: &mut Input<'a, str>| {
+        let mut value = String::new();
+        if let Some(prefix @ ('&' | '%')) = input.clone().next() {
+            input.next();
+            value.push(prefix);
+        }
+        match input.clone().next() {
+            Some(quote @ ('"' | '\'')) => {
+                input.next();
+                loop {
+                    let location = input.location();
+                    let c = match input.next() {
+                        Some('\\') => {
+                            let location = input.location();
+                            match input.next() {
+                                Some('n') => '\n',
+                                Some('t') => '\t',
+                                Some(c) if c != '\n' => c,
+                                c => return Err(unexpected(c, location)),
+                            }
+                        }
+                        Some(c) if c == quote => return Ok(value),
+                        Some(c) if c != '\n' => c,
+                        c => return Err(unexpected(c, location)),
+                    };
+                    value.push(c);
+                }
+            }
+            _ => {
+                let ends = |c: char| matches!(c, ' ' | '\t' | '\r' | '\n') || inline && c == ']';
+                while let Some(c) = input.clone().next().filter(|&c| !ends(c)) {
+                    if lookahead(parse_continuation())(input).is_ok() {
+                        break;
+                    }
+                    input.next();
+                    value.push(c);
+                }
+                Ok(value)
+            }
+        }
+    })
 }
 
-fn quoted_string() -> Parsec<String> {
-    choice(vec![
-        between(parse_char('"'), string_none_of("\""), parse_char('"')),
-        string_none_of(" \r\t\n"),
-    ])
+fn unexpected(c: Option<char>, location: Location) -> ParsecError {
+    let msg = match c {
+        Some(c) => ParsecErrorKind::UnexpectedChar(c),
+        None => ParsecErrorKind::UnexpectedEOF,
+    };
+    ParsecError::new(msg, location)
 }
 
 #[test]
@@ -175,7 +452,7 @@ fn test_parse_inlined_tag() {
     let input = "[lr]";
     let p = parse_inlined_tag();
     assert_eq!(
-        p(&mut input.chars()).unwrap(),
+        p(&mut Input::new(input)).unwrap(),
         Token::Tag(Tag {
             name: "lr".to_string(),
             attributes: HashMap::new(),
@@ -183,50 +460,107 @@ fn test_parse_inlined_tag() {
     );
 }
 
-fn parse_inlined_tag() -> Parsec<Token> {
+#[test]
+fn test_parse_inlined_flags() {
+    let p = parse_inlined_tag();
+    assert_eq!(
+        p(&mut Input::new("[link  cond target=*a ]")).unwrap(),
+        Token::Tag(Tag {
+            name: "link".to_string(),
+            attributes: {
+                let mut map = HashMap::new();
+                map.insert("cond".to_string(), FLAG.to_string());
+                map.insert("target".to_string(), "*a".to_string());
+                map
+            },
+        })
+    );
+}
+
+#[test]
+fn test_parse_inlined_quotes() {
+    let p = parse_inlined_tag();
+    assert_eq!(
+        p(&mut Input::new(
+            "[eval exp=\"f.a = ']'\" name='x y' time=&f.t]"
+        ))
+        .unwrap(),
+        Token::Tag(Tag {
+            name: "eval".to_string(),
+            attributes: {
+                let mut map = HashMap::new();
+                map.insert("exp".to_string(), "f.a = ']'".to_string());
+                map.insert("name".to_string(), "x y".to_string());
+                map.insert("time".to_string(), "&f.t".to_string());
+                map
+            },
+        })
+    );
+    assert!(p(&mut Input::new("[eval exp=\"a]")).is_err());
+}
+
+/// `[`, a tag and a `]` left to `parse_delimiter`, `[[` is text.
+fn parse_inlined_tag<'a>() -> Parsec<'a, Token> {
     between(
-        parse_char('['),
-        fmap(many1(try_parse(none_of("]"))), |v| {
-            lift_tag(
-                v.iter()
-                    .collect::<String>()
-                    .split(" ")
-                    .map(|s| s.to_string())
-                    .collect::<Vec<String>>(),
-            )
-        }),
-        lookahead(parse_char(']')),
+        attempt(between(parse_char('['), lookahead(none_of("[")), pure(()))),
+        parse_tag_body(true),
+        label("]", lookahead(parse_char(']'))),
     )
 }
 
 #[test]
 fn test_parse_text_rune() {
-    let mut input = "[[".chars();
+    let mut input = Input::new("[[");
     let p = parse_text_rune();
     assert_eq!(p(&mut input).unwrap(), '[');
 
-    let mut input = "[lf]".chars();
+    let mut input = Input::new("[lf]");
     let p = parse_text_rune();
     assert_eq!(
-        p(&mut input),
-        Err(ParsecError {
-            msg: ParsecErrorKind::UnexpectedChar('['),
-        })
+        p(&mut input).unwrap_err().msg,
+        ParsecErrorKind::UnexpectedChar('[')
     );
 }
 
-fn parse_text_rune() -> Parsec<char> {
+fn parse_text_rune<'a>() -> Parsec<'a, char> {
     choice(vec![
-        fmap(string("[["), |_: String| '['),
-        none_of("@[]"),
-        fmap(string("]]"), |_| ']'),
-        fmap(string("@@"), |_| '@'),
+        fmap(attempt(string("[[")), |_| '['),
+        none_of("@[]\n\\"),
+        fmap(attempt(string("]]")), |_| ']'),
+        fmap(attempt(string("@@")), |_| '@'),
+        parse_char('\\'),
     ])
 }
 
+#[test]
+fn test_parse_continuation() {
+    let mut input = Input::new("\\\r\n\t b");
+    assert_eq!(parse_continuation()(&mut input), Ok(()));
+    assert_eq!(input.as_str(), "b");
+    assert!(parse_continuation()(&mut Input::new("\\n")).is_err());
+}
+
+/// a `\` ending a line joins the next one to it, without its indentation.
+fn parse_continuation<'a>() -> Parsec<'a, ()> {
+    Rc::new(|input: &mut Input<'a, str>| {
+        parse_char('\\')(input)?;
+        newline()(input)?;
+        spaces()(input)
+    })
+}
+
+/// spaces between the name and attributes of a tag, which may go on to the
+/// next line after a `\`.
+fn tag_spaces<'a>() -> Parsec<'a, ()> {
+    skip_many(choice(vec![
+        discard(space()),
+        attempt(parse_continuation()),
+    ]))
+}
+
 #[test]
 fn test_parse_text() {
-    let mut input = "hello world.[lr]".chars();
+    let mut input = Input::new("hello world.[lr]");
     let p = parse_text();
     assert_eq!(
         p(&mut input).unwrap(),
@@ -236,7 +570,7 @@ fn test_parse_text() {
 
 #[test]
 fn test_parse_text_easy() {
-    let mut input = "hello world.".chars();
+    let mut input = Input::new("hello world.");
     let p = parse_text();
     assert_eq!(
         p(&mut input).unwrap(),
@@ -244,12 +578,43 @@ fn test_parse_text_easy() {
     );
 }
 
-fn parse_text() -> Parsec<Token> {
-    Rc::new(|input: &mut Chars| {
-        let result = many1(parse_text_rune())(input)
-            .map(|v| v.into_iter().collect::<String>())
-            .map(Token::Text);
-        result
+#[test]
+fn test_parse_text_lines() {
+    let mut input = Input::new("one\r\ntwo");
+    let p = parse_text();
+    assert_eq!(p(&mut input).unwrap(), Token::Text("one\n".to_string()));
+    assert_eq!(input.as_str(), "\ntwo");
+    assert_eq!(
+        p(&mut Input::new("a \\\n  b\\c[r]")).unwrap(),
+        Token::Text("a b\\c".to_string())
+    );
+}
+
+/// text up to a tag or the end of the line. Text ending its line ends with
+/// `\n`, which breaks the line in the message window, unless the line ends
+/// with a `\` to go on with the next one. The line end itself is left to
+/// `parse_delimiter`.
+fn parse_text<'a>() -> Parsec<'a, Token> {
+    Rc::new(|input: &mut Input<'a, str>| {
+        let rest = input.as_str().len();
+        let mut text = String::new();
+        loop {
+            if attempt(parse_continuation())(input).is_ok() {
+                continue;
+            }
+            match parse_text_rune()(input) {
+                Ok(c) => text.push(c),
+                Err(e) if input.as_str().len() == rest => return Err(e),
+                Err(_) => break,
+            }
+        }
+        if lookahead(lf())(input).is_ok() {
+            if text.ends_with('\r') {
+                text.pop();
+            }
+            text.push('\n');
+        }
+        Ok(Token::Text(text))
     })
 }
 
@@ -260,16 +625,16 @@ fn test_parse_token() {
     let inlined_tag = "[lr]";
     let text = "Illya and I are alone in the small park a little way from the shopping district.";
 
-    let p = parse_token();
+    let p = parse_token(true);
     assert_eq!(
-        p(&mut page.chars()).unwrap(),
+        p(&mut Input::new(page)).unwrap(),
         Token::Label(Label {
             label: "page47".to_string(),
-            heading: "page47".to_string()
+            heading: Some(Heading::Text(String::new()))
         })
     );
     assert_eq!(
-        p(&mut line_tag.chars()).unwrap(),
+        p(&mut Input::new(line_tag)).unwrap(),
         Token::Tag(Tag {
             name: "a2aT".to_string(),
             attributes: {
@@ -281,7 +646,7 @@ fn test_parse_token() {
     );
 
     assert_eq!(
-        p(&mut inlined_tag.chars()).unwrap(),
+        p(&mut Input::new(inlined_tag)).unwrap(),
         Token::Tag(Tag {
             name: "lr".to_string(),
             attributes: HashMap::new(),
@@ -289,7 +654,7 @@ fn test_parse_token() {
     );
 
     assert_eq!(
-        p(&mut text.chars()).unwrap(),
+        p(&mut Input::new(text)).unwrap(),
         Token::Text(
             "Illya and I are alone in the small park a little way from the shopping district."
                 .to_string()
@@ -297,34 +662,39 @@ fn test_parse_token() {
     );
 }
 
-fn parse_token() -> Parsec<Token> {
-    Rc::new(|input: &mut Chars| {
-        spaces_and_newlines()(input)?;
-        choice(vec![
-            parse_label(),
-            parse_line_tag(),
-            parse_inlined_tag(),
-            parse_text(),
-        ])(input)
+/// a token after `parse_delimiter` and spaces. A label is only at the start
+/// of a line, elsewhere `*` is text, as is a `*` without a name.
+fn parse_token<'a>(line_start: bool) -> Parsec<'a, Token> {
+    Rc::new(move |input: &mut Input<'a, str>| {
+        let mut tokens = vec![
+            label("tag", parse_line_tag()),
+            label("tag", parse_inlined_tag()),
+            label("text", parse_text()),
+        ];
+        if line_start {
+            tokens.insert(0, label("label", attempt(parse_label())));
+        }
+        choice(tokens)(input)
     })
 }
 
 #[test]
 fn test_parse_tokens() {
-    let mut input = "
+    let mut input = Input::new(
+        "
     *page47|
     @sestop file=se009 time=1500 nowait=true
     Illya and I are alone in the small park a little way from the shopping district.[lr]
     @pg
-    "
-    .chars();
+    ",
+    );
     let p = parse_tokens();
     assert_eq!(
         p(&mut input).unwrap(),
         vec![
             Token::Label(Label {
                 label: "page47".to_string(),
-                heading: "page47".to_string()
+                heading: Some(Heading::Text(String::new()))
             }),
             Token::Tag(Tag {
                 name: "sestop".to_string(),
@@ -359,7 +729,7 @@ fn test_parse_tokens_hard() {
 @eval exp=\"sf.scriptresname = '桜ルート十二日目'\"";
     let p = parse_tokens();
     assert_eq!(
-        p(&mut input.chars()).unwrap(),
+        p(&mut Input::new(input)).unwrap(),
         vec![
             Token::Tag(Tag {
                 name: "download".to_string(),
@@ -371,7 +741,7 @@ fn test_parse_tokens_hard() {
             }),
             Token::Label(Label {
                 label: "page0".to_string(),
-                heading: "&f.scripttitle".to_string()
+                heading: Some(Heading::Expression("f.scripttitle".to_string()))
             }),
             Token::Tag(Tag {
                 name: "eval".to_string(),
@@ -388,49 +758,84 @@ fn test_parse_tokens_hard() {
     );
 }
 
-fn parse_delimiter() -> Parsec<()> {
+/// whether the next token starts a line.
+fn parse_delimiter<'a>() -> Parsec<'a, bool> {
     choice(vec![
-        newline(),
-        discard(lookahead(one_of("@["))),
-        discard(one_of("]")),
+        fmap(label("end of line", newline()), |_| true),
+        fmap(label("tag", lookahead(one_of("@["))), |_| false),
+        fmap(label("]", one_of("]")), |_| false),
     ])
 }
 
-fn parse_tokens() -> Parsec<Vec<Token>> {
-    Rc::new(|input: &mut Chars| {
-        let result = sep_by(parse_token(), parse_delimiter())(input);
-        result
-    })
+#[test]
+fn test_parse_star_after_tag() {
+    let tokens = parse_tokens()(&mut Input::new("[r]*sigh*\n*next\n[r]*wink|")).unwrap();
+    assert_eq!(tokens[1], Token::Text("*sigh*\n".to_string()));
+    assert!(matches!(&tokens[2], Token::Label(l) if l.label == "next"));
+    assert_eq!(tokens[4], Token::Text("*wink|".to_string()));
 }
 
 #[test]
-fn test_parse_ks() {
-    const KS: &str = "*page47|";
-    fs::write("test.ks", KS).unwrap();
-    let mut tokens = parse_ks("test.ks").unwrap();
-    fs::remove_file("test.ks").unwrap();
+fn test_parse_tokens_errors() {
+    let error = |input| {
+        parse_tokens()(&mut Input::new(input))
+            .unwrap_err()
+            .to_string()
+    };
+    assert_eq!(error("@bg\n[lr"), "2:4: Unexpected EOF, expected ]");
     assert_eq!(
-        tokens.next().unwrap(),
-        Token::Label(Label {
-            label: "page47".to_string(),
-            heading: "page47".to_string()
-        })
+        error("a\n] b"),
+        "2:1: Unexpected char ], expected one of label, tag, text"
+    );
+    assert_eq!(
+        error("@bg =x"),
+        "1:5: Unexpected char =, expected one of end of line, tag, ]"
     );
+    assert_eq!(error("[ lr]"), "1:2: Unexpected char  , expected tag name");
+    assert_eq!(
+        parse_tokens()(&mut Input::new("@@home\n[[x]]\n*\n")).unwrap(),
+        vec![
+            Token::Text("@home\n".to_string()),
+            Token::Text("[x]\n".to_string()),
+            Token::Text("*\n".to_string()),
+        ]
+    );
+}
+
+/// tokens by `token`, told whether they start a line, up to the end of the
+/// input. A token or delimiter that does not parse fails the whole sequence
+/// with where it went wrong.
+fn parse_sequence<'a, T: 'a>(token: fn(bool) -> Parsec<'a, T>) -> Parsec<'a, Vec<T>> {
+    Rc::new(move |input: &mut Input<'a, str>| {
+        let mut result = Vec::new();
+        let mut line_start = true;
+        loop {
+            let line = input.location().line;
+            spaces_and_newlines()(input)?;
+            if input.peek().is_none() {
+                return Ok(result);
+            }
+            result.push(token(line_start || input.location().line > line)(input)?);
+            if input.peek().is_none() {
+                return Ok(result);
+            }
+            line_start = parse_delimiter()(input)?;
+        }
+    })
 }
 
-pub fn parse_ks(path: &str) -> Result<impl Iterator<Item = Token>, Box<dyn Error>> {
-    let input = fs::read_to_string(path)?;
-    let result = run_parser_str(parse_tokens(), input.as_str())?;
-    Ok(result.into_iter())
+/// the tokens alone, the scenario parsers keep where they are.
+#[cfg(test)]
+fn parse_tokens<'a>() -> Parsec<'a, Vec<Token>> {
+    parse_sequence(parse_token)
 }
 
 #[test]
-fn test_parse_ks_string() {
+fn test_parse_tokens_string() {
     let input = "@say storage=sak1209_shi_0010
     “O[line3]Oh yeah. It’s good if it’s decided. Sakura makes white stew, so let’s go look at the chicken meat.”";
-    let tokens = parse_ks_string(input).unwrap();
     assert_eq!(
-        tokens.collect::<Vec<Token>>(),
+        run_parser_str(parse_tokens(), input).unwrap(),
         vec![
             Token::Tag(Tag {
                 name: "say".to_string(),
@@ -456,7 +861,57 @@ fn test_parse_ks_string() {
     );
 }
 
-pub fn parse_ks_string(input: &str) -> Result<impl Iterator<Item = Token>, Box<dyn Error>> {
-    let result = run_parser_str(parse_tokens(), input)?;
-    Ok(result.into_iter())
+#[test]
+fn test_parse_ks_lines() {
+    let input = "*page1|\n@bg file=a\n\ntext[lr]\n[pg]";
+    let tokens = parse_ks_lines(input).unwrap();
+    assert_eq!(
+        tokens.iter().map(|(line, _)| *line).collect::<Vec<_>>(),
+        vec![1, 2, 4, 4, 5]
+    );
+}
+
+/// the tokens of a scenario, each with the line it starts on, counted from 1.
+pub fn parse_ks_lines(input: &str) -> Result<Vec<(usize, Token)>, Box<dyn Error>> {
+    Ok(parse_located(input)?
+        .into_iter()
+        .map(|(start, _, token)| (start.line, token))
+        .collect())
+}
+
+#[test]
+fn test_parse_ks_spans() {
+    let input = "*page1|\n@bg file=a\n\n a [[b]][lr]\n";
+    let spans = parse_ks_spans(input).unwrap();
+    assert_eq!(
+        spans
+            .iter()
+            .map(|(span, _)| &input[span.clone()])
+            .collect::<Vec<_>>(),
+        vec!["*page1|", "@bg file=a", "a [[b]]", "[lr"]
+    );
+}
+
+/// a token with the bytes of the input it is parsed from.
+pub type Spanned = (Range<usize>, Token);
+
+/// the tokens of a scenario, each with the bytes of the input it is parsed from.
+/// The closing `]` of an inlined tag is left out.
+pub fn parse_ks_spans(input: &str) -> Result<Vec<Spanned>, Box<dyn Error>> {
+    Ok(parse_located(input)?
+        .into_iter()
+        .map(|(start, end, token)| (start.offset..end, token))
+        .collect())
+}
+
+/// every token with where it starts and the byte after it.
+fn parse_located(input: &str) -> Result<Vec<(Location, usize, Token)>, ParsecError> {
+    fn located_token<'a>(line_start: bool) -> Parsec<'a, (Location, usize, Token)> {
+        Rc::new(move |input: &mut Input<'a, str>| {
+            let start = input.location();
+            let token = parse_token(line_start)(input)?;
+            Ok((start, input.offset(), token))
+        })
+    }
+    run_parser_str(parse_sequence(located_token), input)
 }
@@ -0,0 +1,96 @@
+//! # Persist
+//!
+//! System variables outlive a single play, like the record of read text.
+//! They are kept as strings by key in a `Store`, which is a directory for the
+//! command line and `localStorage` for the browser.
+
+#[cfg(test)]
+use std::collections::HashMap;
+use std::{fs, path::PathBuf};
+
+pub trait Store {
+    fn load(&self, key: &str) -> Option<String>;
+    fn save(&mut self, key: &str, value: &str) -> Result<(), String>;
+}
+
+/// forgets everything when dropped, for tests.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    values: HashMap<String, String>,
+}
+
+#[cfg(test)]
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+}
+
+#[cfg(test)]
+impl Store for MemoryStore {
+    fn load(&self, key: &str) -> Option<String> {
+        self.values.get(key).cloned()
+    }
+
+    fn save(&mut self, key: &str, value: &str) -> Result<(), String> {
+        self.values.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+}
+
+/// one file per key in a directory.
+#[derive(Debug)]
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+impl FileStore {
+    pub fn new(dir: &str) -> FileStore {
+        FileStore { dir: dir.into() }
+    }
+}
+
+impl Store for FileStore {
+    fn load(&self, key: &str) -> Option<String> {
+        fs::read_to_string(self.dir.join(key)).ok()
+    }
+
+    fn save(&mut self, key: &str, value: &str) -> Result<(), String> {
+        fs::create_dir_all(&self.dir).map_err(|e| e.to_string())?;
+        fs::write(self.dir.join(key), value).map_err(|e| e.to_string())
+    }
+}
+
+/// the browser's `localStorage`, keys are prefixed by `krkrs.`.
+#[derive(Debug, Default)]
+pub struct LocalStore {}
+
+impl LocalStore {
+    fn storage() -> Option<web_sys::Storage> {
+        web_sys::window()?.local_storage().ok()?
+    }
+}
+
+impl Store for LocalStore {
+    fn load(&self, key: &str) -> Option<String> {
+        Self::storage()?.get_item(&format!("krkrs.{}", key)).ok()?
+    }
+
+    fn save(&mut self, key: &str, value: &str) -> Result<(), String> {
+        Self::storage()
+            .ok_or("localStorage is not available")?
+            .set_item(&format!("krkrs.{}", key), value)
+            .map_err(|e| e.as_string().unwrap_or_default())
+    }
+}
+
+#[test]
+fn test_file_store() {
+    let dir = std::env::temp_dir().join(format!("krkrs-store-{}", std::process::id()));
+    let mut store = FileStore::new(dir.to_str().unwrap());
+    assert_eq!(store.load("read"), None);
+    store.save("read", "a.ks\tpage1\t0-1\n").unwrap();
+    assert_eq!(store.load("read").unwrap(), "a.ks\tpage1\t0-1\n");
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,131 @@
+//! # Read
+//!
+//! This module remembers which text the player has seen. Text is identified
+//! by its storage, the label above it and the index of its token after that
+//! label, and kept as ranges of indices.
+
+use std::{collections::HashMap, ops::Range};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ReadRecord {
+    /// sorted, disjoint and never adjacent.
+    ranges: HashMap<(String, String), Vec<Range<usize>>>,
+}
+
+impl ReadRecord {
+    pub fn new() -> ReadRecord {
+        ReadRecord::default()
+    }
+
+    pub fn is_read(&self, storage: &str, label: &str, index: usize) -> bool {
+        self.ranges
+            .get(&(storage.to_string(), label.to_string()))
+            .is_some_and(|ranges| ranges.iter().any(|r| r.contains(&index)))
+    }
+
+    /// returns whether the text was new.
+    pub fn mark(&mut self, storage: &str, label: &str, index: usize) -> bool {
+        if self.is_read(storage, label, index) {
+            return false;
+        }
+        self.insert(storage, label, index..index + 1);
+        true
+    }
+
+    /// merges `range` with the ranges it overlaps or touches.
+    fn insert(&mut self, storage: &str, label: &str, range: Range<usize>) {
+        let ranges = self
+            .ranges
+            .entry((storage.to_string(), label.to_string()))
+            .or_default();
+        let at = ranges.partition_point(|r| r.end < range.start);
+        let mut merged = range;
+        while ranges.get(at).is_some_and(|r| r.start <= merged.end) {
+            let r = ranges.remove(at);
+            merged = merged.start.min(r.start)..merged.end.max(r.end);
+        }
+        ranges.insert(at, merged);
+    }
+
+    /// one line per label: `storage<TAB>label<TAB>0-3,5-6`
+    pub fn to_text(&self) -> String {
+        let mut keys = self.ranges.keys().collect::<Vec<_>>();
+        keys.sort();
+        keys.into_iter()
+            .map(|key| {
+                let ranges = self.ranges[key]
+                    .iter()
+                    .map(|r| format!("{}-{}", r.start, r.end))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                format!("{}\t{}\t{}\n", key.0, key.1, ranges)
+            })
+            .collect()
+    }
+
+    /// the inverse of `to_text`, broken lines and ranges are dropped.
+    pub fn from_text(text: &str) -> ReadRecord {
+        let mut record = ReadRecord::new();
+        for line in text.lines() {
+            let mut fields = line.split('\t');
+            let (Some(storage), Some(label), Some(ranges)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            for range in ranges.split(',') {
+                let Some((start, end)) = range.split_once('-') else {
+                    continue;
+                };
+                match (start.parse(), end.parse()) {
+                    (Ok(start), Ok(end)) if start < end => {
+                        record.insert(storage, label, start..end)
+                    }
+                    _ => {}
+                }
+            }
+        }
+        record
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mark() {
+        let mut r = ReadRecord::new();
+        assert!(r.mark("a.ks", "page1", 3));
+        assert!(r.mark("a.ks", "page1", 5));
+        assert!(r.mark("a.ks", "page1", 4));
+        assert!(!r.mark("a.ks", "page1", 4));
+        assert!(r.mark("a.ks", "page1", 2));
+        assert_eq!(
+            r.ranges[&("a.ks".to_string(), "page1".to_string())],
+            vec![2..6]
+        );
+        assert!(r.is_read("a.ks", "page1", 5));
+        assert!(!r.is_read("a.ks", "page1", 6));
+        assert!(!r.is_read("a.ks", "page2", 5));
+    }
+
+    #[test]
+    fn test_text() {
+        let mut r = ReadRecord::new();
+        r.mark("a.ks", "page1", 0);
+        r.mark("a.ks", "page1", 1);
+        r.mark("a.ks", "page1", 7);
+        r.mark("b.ks", "", 2);
+        let text = r.to_text();
+        assert_eq!(text, "a.ks\tpage1\t0-2,7-8\nb.ks\t\t2-3\n");
+        assert_eq!(ReadRecord::from_text(&text), r);
+
+        let r =
+            ReadRecord::from_text("a.ks\tp\t5-8,0-18446744073709551615,9-3\nb.ks\tp\t1-3,3-4\n");
+        assert_eq!(
+            r.to_text(),
+            "a.ks\tp\t0-18446744073709551615\nb.ks\tp\t1-4\n"
+        );
+    }
+}
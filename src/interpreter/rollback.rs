@@ -0,0 +1,127 @@
+//! # Rollback
+//!
+//! The interpreter takes a snapshot at the start of every page and keeps the
+//! latest ones in a ring buffer, so the player can step back a few pages.
+//! Some points, e.g. after a choice, block stepping back past them.
+
+use std::{collections::VecDeque, error::Error, fmt::Display};
+
+/// pages the player can step back.
+pub const ROLLBACK_CAPACITY: usize = 50;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RollbackError {
+    /// there are not so many pages before.
+    TooFar,
+    /// a point on the way blocks rollback.
+    Blocked,
+}
+
+impl Error for RollbackError {}
+
+impl Display for RollbackError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RollbackError::TooFar => write!(f, "no page to roll back to"),
+            RollbackError::Blocked => write!(f, "rollback is blocked here"),
+        }
+    }
+}
+
+/// the last snapshot is the start of the current page.
+#[derive(Debug, Clone)]
+pub struct Rollback<T> {
+    snapshots: VecDeque<T>,
+    /// snapshots before this index can not be restored.
+    barrier: usize,
+    capacity: usize,
+}
+
+impl<T: Clone> Default for Rollback<T> {
+    fn default() -> Self {
+        Rollback::with_capacity(ROLLBACK_CAPACITY)
+    }
+}
+
+impl<T: Clone> Rollback<T> {
+    pub fn new() -> Rollback<T> {
+        Rollback::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Rollback<T> {
+        Rollback {
+            snapshots: VecDeque::new(),
+            barrier: 0,
+            // the current page takes a place too.
+            capacity: capacity + 1,
+        }
+    }
+
+    pub fn push(&mut self, snapshot: T) {
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+            self.barrier = self.barrier.saturating_sub(1);
+        }
+        self.snapshots.push_back(snapshot);
+    }
+
+    /// the pages so far can not be restored any more.
+    pub fn block(&mut self) {
+        self.barrier = self.snapshots.len();
+    }
+
+    /// pages the player can step back now.
+    pub fn available(&self) -> usize {
+        self.snapshots.len().saturating_sub(self.barrier + 1)
+    }
+
+    /// the start of the page `n` pages before, which becomes the current page.
+    pub fn rollback(&mut self, n: usize) -> Result<T, RollbackError> {
+        let target = self
+            .snapshots
+            .len()
+            .checked_sub(n + 1)
+            .ok_or(RollbackError::TooFar)?;
+        if target < self.barrier {
+            return Err(RollbackError::Blocked);
+        }
+        self.snapshots.truncate(target + 1);
+        Ok(self.snapshots[target].clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rollback() {
+        let mut r = Rollback::new();
+        (0..4).for_each(|i| r.push(i));
+        assert_eq!(r.available(), 3);
+        assert_eq!(r.rollback(2), Ok(1));
+        assert_eq!(r.available(), 1);
+        assert_eq!(r.rollback(2), Err(RollbackError::TooFar));
+        assert_eq!(r.rollback(0), Ok(1));
+    }
+
+    #[test]
+    fn test_block() {
+        let mut r = Rollback::new();
+        (0..3).for_each(|i| r.push(i));
+        r.block();
+        r.push(3);
+        r.push(4);
+        assert_eq!(r.available(), 1);
+        assert_eq!(r.rollback(2), Err(RollbackError::Blocked));
+        assert_eq!(r.rollback(1), Ok(3));
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut r = Rollback::with_capacity(2);
+        (0..5).for_each(|i| r.push(i));
+        assert_eq!(r.available(), 2);
+        assert_eq!(r.rollback(2), Ok(2));
+    }
+}
@@ -0,0 +1,15 @@
+//! # Save
+//!
+//! KAG saves at labels written with a `|`, the save points. A save slot
+//! keeps where the last save point was passed, so loading plays on from
+//! there, and shows the heading of that label as its title.
+
+use crate::interpreter::backlog::Position;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SavePoint {
+    /// the label of the save point, index 0.
+    pub position: Position,
+    /// the heading of the label, evaluated when it was passed.
+    pub title: String,
+}
@@ -0,0 +1,132 @@
+//! # Scenario
+//!
+//! A scenario is a parsed `.ks` file. Tokens are kept in a vector so the
+//! interpreter can jump, save and roll back by index, with a table of labels
+//! and the source line of every token.
+
+use std::{collections::HashMap, error::Error, rc::Rc};
+
+use crate::interpreter::parser::{parse_ks_lines, Token};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scenario {
+    pub storage: String,
+    tokens: Vec<Token>,
+    /// label name without `*` to the index of the label token.
+    labels: HashMap<String, usize>,
+    /// the source line of every token, counted from 1.
+    lines: Vec<usize>,
+}
+
+impl Scenario {
+    pub fn parse(storage: &str, source: &str) -> Result<Scenario, Box<dyn Error>> {
+        let (lines, tokens): (Vec<usize>, Vec<Token>) = parse_ks_lines(source)?.into_iter().unzip();
+        Ok(Scenario::new(storage, tokens, lines))
+    }
+
+    pub fn new(storage: &str, tokens: Vec<Token>, lines: Vec<usize>) -> Scenario {
+        let mut labels = HashMap::new();
+        for (i, token) in tokens.iter().enumerate() {
+            if let Token::Label(label) = token {
+                // the first one wins, like KAG does.
+                labels.entry(label.label.clone()).or_insert(i);
+            }
+        }
+        Scenario {
+            storage: storage.to_string(),
+            tokens,
+            labels,
+            lines,
+        }
+    }
+
+    /// every token with its source line.
+    pub fn tokens(&self) -> impl Iterator<Item = (usize, &Token)> {
+        self.lines.iter().copied().zip(self.tokens.iter())
+    }
+
+    pub fn get(&self, pc: usize) -> Option<&Token> {
+        self.tokens.get(pc)
+    }
+
+    /// the index of the label token, `target` may start with `*`.
+    pub fn label(&self, target: &str) -> Option<usize> {
+        let name = target.strip_prefix('*').unwrap_or(target);
+        self.labels.get(name).copied()
+    }
+
+    pub fn line(&self, pc: usize) -> Option<usize> {
+        self.lines.get(pc).copied()
+    }
+}
+
+/// scenarios loaded so far by storage name, so entering a file again does not parse it again.
+#[derive(Debug, Default, Clone)]
+pub struct ScenarioCache {
+    scenarios: HashMap<String, Rc<Scenario>>,
+}
+
+impl ScenarioCache {
+    pub fn new() -> ScenarioCache {
+        ScenarioCache::default()
+    }
+
+    pub fn insert(&mut self, scenario: Scenario) -> Rc<Scenario> {
+        let scenario = Rc::new(scenario);
+        self.scenarios
+            .insert(scenario.storage.clone(), scenario.clone());
+        scenario
+    }
+
+    pub fn get(&self, storage: &str) -> Option<Rc<Scenario>> {
+        self.scenarios.get(storage).cloned()
+    }
+
+    /// the cached scenario, or parses the source `load` gives.
+    pub fn get_or_load(
+        &mut self,
+        storage: &str,
+        load: impl FnOnce(&str) -> Result<String, Box<dyn Error>>,
+    ) -> Result<Rc<Scenario>, Box<dyn Error>> {
+        if let Some(scenario) = self.get(storage) {
+            return Ok(scenario);
+        }
+        let source = load(storage)?;
+        Ok(self.insert(Scenario::parse(storage, &source)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scenario() {
+        let s = Scenario::parse("a.ks", "*start|\ntext[lr]\n*next|\nmore").unwrap();
+        assert!(s.get(4).is_some());
+        assert!(s.get(5).is_none());
+        assert_eq!(s.label("*next"), Some(3));
+        assert_eq!(s.label("start"), Some(0));
+        assert_eq!(s.label("*none"), None);
+        assert_eq!(s.line(4), Some(4));
+    }
+
+    #[test]
+    fn test_cache() {
+        let mut cache = ScenarioCache::new();
+        let mut loads = 0;
+        for _ in 0..2 {
+            let s = cache
+                .get_or_load("a.ks", |_| {
+                    loads += 1;
+                    Ok("text".to_string())
+                })
+                .unwrap();
+            assert_eq!(s.get(0), Some(&Token::Text("text".to_string())));
+        }
+        assert_eq!(loads, 1);
+        assert!(cache
+            .get_or_load("b.ks", |_| Err("missing".into()))
+            .is_err());
+    }
+}
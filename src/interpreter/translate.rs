@@ -0,0 +1,592 @@
+//! # Translate
+//!
+//! Takes the text of scenarios out for translators, as gettext PO or CSV,
+//! and puts the translated text back. Every line of text is one string,
+//! named by where it is, `storage:line:column`, and comes with the label and
+//! the voice it is under. Putting text back only touches the bytes of that
+//! text, tags, comments and line ends stay as they are.
+
+use std::{collections::HashMap, error::Error, fmt, ops::Range};
+
+use crate::interpreter::parser::{parse_ks_spans, Token};
+
+/// tags after which the next text is no longer spoken by the last voice.
+const CLICK_TAGS: &[&str] = &["l", "lr", "p", "pg", "s"];
+
+/// a line of text of a scenario.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub storage: String,
+    /// counted from 1.
+    pub line: usize,
+    /// in characters, counted from 1.
+    pub column: usize,
+    pub label: Option<String>,
+    pub voice: Option<String>,
+    /// the text as shown, without the `[[`, `]]` and `@@` escapes.
+    pub text: String,
+    /// the bytes of the source the text is written in.
+    pub span: Range<usize>,
+}
+
+impl Entry {
+    pub fn location(&self) -> String {
+        format!("{}:{}:{}", self.storage, self.line, self.column)
+    }
+}
+
+/// the translation of the entry at `location`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Translation {
+    pub location: String,
+    /// the text translated, to tell when the scenario has changed since.
+    pub source: String,
+    pub translation: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TranslationError {
+    /// the line of the PO or CSV file, counted from 1.
+    pub line: usize,
+    pub message: &'static str,
+}
+
+impl fmt::Display for TranslationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for TranslationError {}
+
+/// a scenario with translations put in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reinjection {
+    pub text: String,
+    pub applied: usize,
+    /// locations whose text is not what was translated any more.
+    pub stale: Vec<String>,
+    /// locations whose translation would be read as more than text: a `*`
+    /// or `;` starting a line, or a `\` going on to the next one.
+    pub refused: Vec<String>,
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("[[", "[")
+        .replace("]]", "]")
+        .replace("@@", "@")
+}
+
+/// the text as written in a scenario, line breaks as `[r]`.
+fn escape(text: &str) -> String {
+    text.replace('[', "[[")
+        .replace(']', "]]")
+        .replace('@', "@@")
+        .replace("\r\n", "[r]")
+        .replace('\n', "[r]")
+}
+
+/// whether a translation written at `at` would not be read as text alone.
+fn is_refused(source: &str, at: usize, translation: &str) -> bool {
+    let before = source[..at].trim_end_matches([' ', '\t']);
+    let line_start = before.is_empty() || before.ends_with('\n');
+    line_start && translation.starts_with(['*', ';']) || translation.ends_with('\\')
+}
+
+/// counts the lines of a source up to offsets given in order, so that the
+/// source is read once.
+struct Lines<'a> {
+    source: &'a str,
+    offset: usize,
+    /// counted from 1.
+    line: usize,
+    line_start: usize,
+}
+
+impl<'a> Lines<'a> {
+    fn new(source: &'a str) -> Self {
+        Lines {
+            source,
+            offset: 0,
+            line: 1,
+            line_start: 0,
+        }
+    }
+
+    /// the line `to` is on and what is before it on that line.
+    fn seek(&mut self, to: usize) -> (usize, &'a str) {
+        for (i, b) in self.source.as_bytes()[self.offset..to].iter().enumerate() {
+            if *b == b'\n' {
+                self.line += 1;
+                self.line_start = self.offset + i + 1;
+            }
+        }
+        self.offset = to;
+        (self.line, &self.source[self.line_start..to])
+    }
+}
+
+/// every line of text of the scenario. Lines of comments the parser takes
+/// for text are not text.
+pub fn extract(storage: &str, source: &str) -> Result<Vec<Entry>, Box<dyn Error>> {
+    let mut entries = Vec::new();
+    let mut label = None;
+    let mut voice = None;
+    let mut lines = Lines::new(source);
+    for (span, token) in parse_ks_spans(source)? {
+        match token {
+            Token::Label(l) => label = Some(l.label),
+            Token::Tag(tag) => match tag.name.as_str() {
+                "voice" | "say" => voice = tag.attributes.get("storage").cloned(),
+                name if CLICK_TAGS.contains(&name) => voice = None,
+                _ => {}
+            },
+            Token::Text(_) => {
+                let mut start = span.start;
+                for part in source[span.clone()].split_inclusive('\n') {
+                    let trimmed = part.trim_start();
+                    let from = start + part.len() - trimmed.len();
+                    let body = trimmed.trim_end();
+                    // the `\` going on to the next line is not part of the text.
+                    let body = match body.strip_suffix('\\') {
+                        Some(b) if part.ends_with('\n') => b.trim_end(),
+                        _ => body,
+                    };
+                    let to = from + body.len();
+                    start += part.len();
+                    let (line, before) = lines.seek(from);
+                    if from == to || before.trim().is_empty() && trimmed.starts_with(';') {
+                        continue;
+                    }
+                    entries.push(Entry {
+                        storage: storage.to_string(),
+                        line,
+                        column: before.chars().count() + 1,
+                        label: label.clone(),
+                        voice: voice.clone(),
+                        text: unescape(&source[from..to]),
+                        span: from..to,
+                    });
+                }
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// puts the translations of the scenario in, those of other scenarios and
+/// empty ones are left out.
+pub fn reinject(
+    storage: &str,
+    source: &str,
+    translations: &[Translation],
+) -> Result<Reinjection, Box<dyn Error>> {
+    let by_location: HashMap<&str, &Translation> = translations
+        .iter()
+        .filter(|t| !t.translation.is_empty())
+        .map(|t| (t.location.as_str(), t))
+        .collect();
+    let mut reinjection = Reinjection {
+        text: String::with_capacity(source.len()),
+        applied: 0,
+        stale: Vec::new(),
+        refused: Vec::new(),
+    };
+    let mut copied = 0;
+    for entry in extract(storage, source)? {
+        let location = entry.location();
+        let t = match by_location.get(location.as_str()) {
+            Some(t) => t,
+            None => continue,
+        };
+        if t.source != entry.text {
+            reinjection.stale.push(location);
+            continue;
+        }
+        if is_refused(source, entry.span.start, &t.translation) {
+            reinjection.refused.push(location);
+            continue;
+        }
+        reinjection.text.push_str(&source[copied..entry.span.start]);
+        reinjection.text.push_str(&escape(&t.translation));
+        copied = entry.span.end;
+        reinjection.applied += 1;
+    }
+    reinjection.text.push_str(&source[copied..]);
+    Ok(reinjection)
+}
+
+fn po_string(s: &str) -> String {
+    let escaped = s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n");
+    format!("\"{}\"", escaped)
+}
+
+pub fn to_po(entries: &[Entry]) -> String {
+    let mut po =
+        String::from("msgid \"\"\nmsgstr \"Content-Type: text/plain; charset=UTF-8\\n\"\n");
+    for e in entries {
+        po.push_str(&format!("\n#: {}\n", e.location()));
+        if let Some(label) = &e.label {
+            po.push_str(&format!("#. label: *{}\n", label));
+        }
+        if let Some(voice) = &e.voice {
+            po.push_str(&format!("#. voice: {}\n", voice));
+        }
+        po.push_str(&format!(
+            "msgctxt {}\nmsgid {}\nmsgstr \"\"\n",
+            po_string(&e.location()),
+            po_string(&e.text)
+        ));
+    }
+    po
+}
+
+/// reads the `msgctxt`, `msgid` and `msgstr` of every message, the header
+/// and messages without a context left out.
+pub fn read_po(po: &str) -> Result<Vec<Translation>, TranslationError> {
+    let mut translations = Vec::new();
+    // the fields of the message being read and the one being continued.
+    let mut fields: HashMap<&str, String> = HashMap::new();
+    let mut current: Option<&str> = None;
+    let mut finish = |fields: &mut HashMap<&str, String>| {
+        if let (Some(location), Some(source), Some(translation)) = (
+            fields.remove("msgctxt"),
+            fields.remove("msgid"),
+            fields.remove("msgstr"),
+        ) {
+            translations.push(Translation {
+                location,
+                source,
+                translation,
+            });
+        }
+        fields.clear();
+    };
+    for (i, line) in po.lines().enumerate() {
+        let error = |message| TranslationError {
+            line: i + 1,
+            message,
+        };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (keyword, rest) = match line.split_once(char::is_whitespace) {
+            Some((keyword, rest)) if !line.starts_with('"') => (Some(keyword), rest.trim()),
+            _ => (None, line),
+        };
+        let value = read_po_string(rest).ok_or_else(|| error("expected a quoted string"))?;
+        match keyword {
+            Some(keyword @ ("msgctxt" | "msgid" | "msgstr")) => {
+                if keyword == "msgctxt" || fields.contains_key(keyword) {
+                    finish(&mut fields);
+                }
+                fields.insert(keyword, value);
+                current = Some(keyword);
+            }
+            Some(_) => return Err(error("unknown keyword")),
+            None => {
+                let field = current
+                    .and_then(|c| fields.get_mut(c))
+                    .ok_or_else(|| error("string outside of a message"))?;
+                field.push_str(&value);
+            }
+        }
+    }
+    finish(&mut fields);
+    Ok(translations)
+}
+
+fn read_po_string(s: &str) -> Option<String> {
+    let inner = s.strip_prefix('"')?.strip_suffix('"')?;
+    let mut out = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        out.push(match chars.next()? {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            c => c,
+        });
+    }
+    Some(out)
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+pub fn to_csv(entries: &[Entry]) -> String {
+    let mut csv = String::from("location,label,voice,source,translation\n");
+    for e in entries {
+        let label = e.label.as_ref().map(|l| format!("*{}", l));
+        let fields = [
+            e.location(),
+            label.unwrap_or_default(),
+            e.voice.clone().unwrap_or_default(),
+            e.text.clone(),
+            String::new(),
+        ];
+        let fields: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// reads the `location`, `source` and `translation` columns, found by the
+/// header.
+pub fn read_csv(csv: &str) -> Result<Vec<Translation>, TranslationError> {
+    let mut records = read_csv_records(csv)?.into_iter();
+    let header = records.next().unwrap_or_default();
+    let column = |name| {
+        header
+            .iter()
+            .position(|h| h == name)
+            .ok_or(TranslationError {
+                line: 1,
+                message: "the header lacks location, source or translation",
+            })
+    };
+    let (location, source, translation) = (
+        column("location")?,
+        column("source")?,
+        column("translation")?,
+    );
+    Ok(records
+        .filter(|r| r.len() > 1 || r.first().is_some_and(|f| !f.is_empty()))
+        .map(|r| {
+            let field = |i: usize| r.get(i).cloned().unwrap_or_default();
+            Translation {
+                location: field(location),
+                source: field(source),
+                translation: field(translation),
+            }
+        })
+        .collect())
+}
+
+fn read_csv_records(csv: &str) -> Result<Vec<Vec<String>>, TranslationError> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut line = 1;
+    let mut chars = csv.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if field.is_empty() => {
+                let start = line;
+                loop {
+                    match chars.next() {
+                        Some('"') if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            field.push('"');
+                        }
+                        Some('"') => break,
+                        Some(c) => {
+                            line += usize::from(c == '\n');
+                            field.push(c);
+                        }
+                        None => {
+                            return Err(TranslationError {
+                                line: start,
+                                message: "unterminated quoted field",
+                            })
+                        }
+                    }
+                }
+            }
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                line += 1;
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KS: &str = "; opening\r\n*start|\r\n@say storage=v001\r\n\
+\"Hello, [[world]].\"[lr]\r\n  Who's there?[p]\r\nQuiet again.[r]\r\nA new line@@home\r\n";
+
+    fn translate(entries: &[Entry], f: impl Fn(&str) -> String) -> Vec<Translation> {
+        entries
+            .iter()
+            .map(|e| Translation {
+                location: e.location(),
+                source: e.text.clone(),
+                translation: f(&e.text),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_extract() {
+        let entries = extract("first.ks", KS).unwrap();
+        let summary: Vec<_> = entries
+            .iter()
+            .map(|e| {
+                (
+                    e.location(),
+                    e.label.as_deref(),
+                    e.voice.as_deref(),
+                    e.text.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    "first.ks:4:1".to_string(),
+                    Some("start"),
+                    Some("v001"),
+                    "\"Hello, [world].\""
+                ),
+                (
+                    "first.ks:5:3".to_string(),
+                    Some("start"),
+                    None,
+                    "Who's there?"
+                ),
+                (
+                    "first.ks:6:1".to_string(),
+                    Some("start"),
+                    None,
+                    "Quiet again."
+                ),
+                (
+                    "first.ks:7:1".to_string(),
+                    Some("start"),
+                    None,
+                    "A new line@home"
+                ),
+            ]
+        );
+        let continued = extract("a.ks", "one \\\n  two\n").unwrap();
+        assert_eq!(continued[0].text, "one");
+        assert_eq!(continued[1].text, "two");
+    }
+
+    #[test]
+    fn test_reinject() {
+        let entries = extract("first.ks", KS).unwrap();
+        let same = reinject("first.ks", KS, &translate(&entries, str::to_string)).unwrap();
+        assert_eq!(same.text, KS);
+        assert_eq!(same.applied, 4);
+
+        let mut translations = translate(&entries, |t| t.to_uppercase());
+        translations[1].translation.clear();
+        translations[2].source = "Loud again.".to_string();
+        let r = reinject("first.ks", KS, &translations).unwrap();
+        assert_eq!(
+            r.text,
+            "; opening\r\n*start|\r\n@say storage=v001\r\n\
+\"HELLO, [[WORLD]].\"[lr]\r\n  Who's there?[p]\r\nQuiet again.[r]\r\nA NEW LINE@@HOME\r\n"
+        );
+        assert_eq!(r.applied, 2);
+        assert_eq!(r.stale, vec!["first.ks:6:1"]);
+
+        let mut translations = translate(&entries, str::to_string);
+        translations[0].translation = "*sigh*".to_string();
+        translations[1].translation = "a\\".to_string();
+        translations[2].translation = ";hush".to_string();
+        translations[3].translation = "one\n*two".to_string();
+        let r = reinject("first.ks", KS, &translations).unwrap();
+        assert_eq!(
+            r.refused,
+            vec!["first.ks:4:1", "first.ks:5:3", "first.ks:6:1"]
+        );
+        assert_eq!(r.applied, 1);
+        assert!(r.text.ends_with("\r\none[r]*two\r\n"));
+        let tokens: Vec<Token> = parse_ks_spans(&r.text)
+            .unwrap()
+            .into_iter()
+            .map(|(_, t)| t)
+            .collect();
+        assert!(!tokens
+            .iter()
+            .any(|t| matches!(t, Token::Label(l) if l.label != "start")));
+        // mid-line a star is text.
+        let source = "a[r]b\n";
+        let mut translations = translate(&extract("a.ks", source).unwrap(), str::to_string);
+        translations[1].translation = "*sigh*".to_string();
+        let r = reinject("a.ks", source, &translations).unwrap();
+        assert_eq!(r.text, "a[r]*sigh*\n");
+        assert!(r.refused.is_empty());
+    }
+
+    #[test]
+    fn test_po() {
+        let entries = extract("first.ks", KS).unwrap();
+        let po = to_po(&entries);
+        assert!(po.contains(
+            "#: first.ks:4:1\n#. label: *start\n#. voice: v001\n\
+             msgctxt \"first.ks:4:1\"\nmsgid \"\\\"Hello, [world].\\\"\"\nmsgstr \"\"\n"
+        ));
+        assert_eq!(
+            read_po(&po).unwrap(),
+            translate(&entries, |_| String::new())
+        );
+
+        let po =
+            "msgctxt \"a.ks:1:1\"\nmsgid \"\"\n\"two\\n\"\n\"lines\"\nmsgstr \"deux\\nlignes\"\n";
+        assert_eq!(
+            read_po(po).unwrap(),
+            vec![Translation {
+                location: "a.ks:1:1".to_string(),
+                source: "two\nlines".to_string(),
+                translation: "deux\nlignes".to_string(),
+            }]
+        );
+        let po = format!("{}msgid \"no context\"\nmsgstr \"x\"\n", po);
+        assert_eq!(read_po(&po).unwrap().len(), 1);
+        assert_eq!(read_po("msgid \"a").unwrap_err().line, 1);
+    }
+
+    #[test]
+    fn test_csv() {
+        let entries = extract("first.ks", KS).unwrap();
+        let csv = to_csv(&entries);
+        assert!(csv.contains("first.ks:4:1,*start,v001,\"\"\"Hello, [world].\"\"\",\n"));
+        assert_eq!(
+            read_csv(&csv).unwrap(),
+            translate(&entries, |_| String::new())
+        );
+
+        let csv = "translation,location,source\r\n\"a\r\nb\",x.ks:1:1,c\r\n";
+        assert_eq!(
+            read_csv(csv).unwrap(),
+            vec![Translation {
+                location: "x.ks:1:1".to_string(),
+                source: "c".to_string(),
+                translation: "a\r\nb".to_string(),
+            }]
+        );
+        assert!(read_csv("location,source\n").is_err());
+        assert_eq!(read_csv("a\n\"b").unwrap_err().line, 2);
+    }
+}
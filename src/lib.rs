@@ -3,7 +3,7 @@ pub mod utils;
 mod interpreter;
 mod parsec;
 
-mod vfs;
+pub mod vfs;
 
 mod presentation;
 
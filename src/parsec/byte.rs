@@ -0,0 +1,110 @@
+//! # Parsers for bytes
+//!
+//! Binary formats like XP3 and TLG start with headers of magic bytes and
+//! little endian integers. These parsers read them from an `Input` of
+//! `[u8]`, the combinators work on it as they do on text.
+use std::{convert::TryInto, rc::Rc};
+
+use super::*;
+#[cfg(test)]
+use crate::mkpc;
+
+#[test]
+fn test_byte() {
+    let mut input = Input::new(&b"\x1a\x8b"[..]);
+    let p = byte(0x1a);
+    assert_eq!(p(&mut input).unwrap(), 0x1a);
+    assert_eq!(
+        p(&mut input).unwrap_err().msg,
+        ParsecErrorKind::UnexpectedByte(0x8b)
+    );
+}
+
+pub fn byte<'a>(b: u8) -> Parsec<'a, u8, [u8]> {
+    satisfy(Rc::new(move |x: u8| x == b))
+}
+
+#[test]
+fn test_take() {
+    let mut input = Input::new(&b"TLG5.0"[..]);
+    assert_eq!(take(3)(&mut input).unwrap(), b"TLG");
+    assert_eq!(input.location().column, 4);
+    assert_eq!(
+        take(4)(&mut input).unwrap_err().msg,
+        ParsecErrorKind::UnexpectedEOF
+    );
+}
+
+/// the next `n` bytes, borrowed from the source.
+pub fn take<'a>(n: usize) -> Parsec<'a, &'a [u8], [u8]> {
+    Rc::new(move |input: &mut Input<'a, [u8]>| {
+        let from = input.offset();
+        if input.as_bytes().len() < n {
+            return Err(ParsecError::new(
+                ParsecErrorKind::UnexpectedEOF,
+                input.location(),
+            ));
+        }
+        for _ in 0..n {
+            input.next();
+        }
+        Ok(input.since(from))
+    })
+}
+
+#[test]
+fn test_bytes() {
+    let mut input = Input::new(&b"XP3\r\n"[..]);
+    assert_eq!(bytes(b"XP3")(&mut input).unwrap(), b"XP3");
+    assert_eq!(
+        bytes(b"\n")(&mut input).unwrap_err().msg,
+        ParsecErrorKind::UnexpectedByte(b'\r')
+    );
+}
+
+/// the part of the input matching `s`, like magic bytes.
+pub fn bytes<'a>(s: &'static [u8]) -> Parsec<'a, &'a [u8], [u8]> {
+    recognize(Rc::new(move |input: &mut Input<'a, [u8]>| {
+        for &expected in s {
+            byte(expected)(input)?;
+        }
+        Ok(())
+    }))
+}
+
+#[test]
+fn test_le() {
+    let mut input = Input::new(&b"\x01\x00\x00\x00\x02\x00\x00\x00\x00\x00\x00\x01"[..]);
+    assert_eq!(le_u32()(&mut input).unwrap(), 1);
+    assert_eq!(le_u64()(&mut input).unwrap(), 0x0100_0000_0000_0002);
+}
+
+pub fn le_u32<'a>() -> Parsec<'a, u32, [u8]> {
+    fmap(take(4), |b| u32::from_le_bytes(b.try_into().unwrap()))
+}
+
+pub fn le_u64<'a>() -> Parsec<'a, u64, [u8]> {
+    fmap(take(8), |b| u64::from_le_bytes(b.try_into().unwrap()))
+}
+
+#[test]
+fn test_headers() {
+    // an XP3 archive starts with its magic and where the index is.
+    let xp3 = b"XP3\r\n \n\x1a\x8b\x67\x01\x20\x00\x00\x00\x00\x00\x00\x00";
+    let p = mkpc![
+        bytes(b"XP3\r\n \n\x1a\x8b\x67\x01");
+        offset <- le_u64();
+        return offset
+    ];
+    assert_eq!(run_parser_bytes(p, xp3).unwrap(), 0x20);
+
+    // a TLG0 container gives the length of the TLG inside.
+    let tlg = b"TLG0.0\x00sds\x1a\x0b\x00\x00\x00TLG5.0\x00raw\x1a";
+    let p = mkpc![
+        bytes(b"TLG0.0\x00sds\x1a");
+        len <- le_u32();
+        body <- take(len as usize);
+        return body
+    ];
+    assert_eq!(run_parser_bytes(p, tlg).unwrap(), b"TLG5.0\x00raw\x1a");
+}
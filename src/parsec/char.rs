@@ -8,272 +8,149 @@ use super::*;
 
 #[test]
 pub(crate) fn test_char() {
-    let mut input = "你好世界".chars();
+    let mut input = Input::new("你好世界");
     let p = parse_char('你');
     assert_eq!(p(&mut input).unwrap(), '你');
 }
 
-pub fn parse_char(c: char) -> Parsec<char> {
-    satisfy(Rc::new(move |x| x == c))
+pub fn parse_char<'a>(c: char) -> Parsec<'a, char> {
+    satisfy(Rc::new(move |x: char| x == c))
 }
 
 #[test]
 pub(crate) fn test_one_of() {
-    let mut input = "你好世界".chars();
+    let mut input = Input::new("你好世界");
     let p = one_of("你好");
     assert_eq!(p(&mut input).unwrap(), '你');
-    let mut input = "好世界".chars();
+    let mut input = Input::new("好世界");
     assert_eq!(p(&mut input).unwrap(), '好');
 }
 
-pub fn one_of(cs: &'static str) -> Parsec<char> {
-    satisfy(Rc::new(move |x| cs.contains(x)))
+pub fn one_of<'a>(cs: &'static str) -> Parsec<'a, char> {
+    satisfy(Rc::new(move |x: char| cs.contains(x)))
 }
 
 #[test]
 pub(crate) fn test_space() {
-    let mut input = "  你好世界".chars();
+    let mut input = Input::new("  你好世界");
     let p = space();
     assert_eq!(p(&mut input).unwrap(), ' ');
     assert_eq!(p(&mut input).unwrap(), ' ');
 }
 
 /// space but not newline
-pub fn space() -> Parsec<char> {
-    satisfy(Rc::new(move |x| x.is_whitespace() && x != '\n'))
+pub fn space<'a>() -> Parsec<'a, char> {
+    satisfy(Rc::new(move |x: char| x.is_whitespace() && x != '\n'))
 }
 
 #[test]
 pub(crate) fn test_spaces() {
-    let mut input = "  你好世界".chars();
+    let mut input = Input::new("  你好世界");
     let p = spaces();
     assert_eq!(p(&mut input).unwrap(), ());
 }
 
-pub fn spaces() -> Parsec<()> {
+pub fn spaces<'a>() -> Parsec<'a, ()> {
     skip_many(space())
 }
 
 #[test]
 fn test_spaces_and_newlines() {
-    let mut input = "  \n你好世界".chars();
+    let mut input = Input::new("  \n你好世界");
     let p = spaces_and_newlines();
     assert_eq!(p(&mut input).unwrap(), ());
 }
 
-pub fn spaces_and_newlines() -> Parsec<()> {
+pub fn spaces_and_newlines<'a>() -> Parsec<'a, ()> {
     skip_many(choice(vec![discard(space()), discard(newline())]))
 }
 
 #[test]
 fn test_none_of() {
-    let mut input = "j你好".chars();
+    let mut input = Input::new("j你好");
     let p = none_of("你好");
     assert_eq!(p(&mut input).unwrap(), 'j');
     assert_eq!(
-        p(&mut input),
-        Err(ParsecError {
-            msg: ParsecErrorKind::UnexpectedChar('你')
-        })
+        p(&mut input).unwrap_err().msg,
+        ParsecErrorKind::UnexpectedChar('你')
     );
 }
 
-pub fn none_of(s: &'static str) -> Parsec<char> {
+pub fn none_of<'a>(s: &'static str) -> Parsec<'a, char> {
     satisfy(Rc::new(move |c: char| !s.contains(c)))
 }
 
-#[test]
-fn test_digit() {
-    let mut input = "123".chars();
-    let p = digit();
-    assert_eq!(p(&mut input).unwrap(), '1');
-    assert_eq!(p(&mut input).unwrap(), '2');
-    assert_eq!(p(&mut input).unwrap(), '3');
-}
-
-pub fn digit() -> Parsec<char> {
-    satisfy(Rc::new(move |c: char| c.is_digit(10)))
-}
-
-#[test]
-fn test_dec_num() {
-    let mut input = "123".chars();
-    let p = dec_num();
-    assert_eq!(p(&mut input).unwrap(), 123);
-}
-
-pub fn dec_num() -> Parsec<i64> {
-    Rc::new(move |input: &mut Chars| {
-        let try_digit = try_parse(digit());
-        let digits = many1(try_digit)(input)?;
-        Ok(digits.iter().collect::<String>().parse::<i64>().unwrap())
-    })
-}
-
-#[test]
-fn test_letter() {
-    let mut input = "abc".chars();
-    let p = letter();
-    assert_eq!(p(&mut input).unwrap(), 'a');
-    assert_eq!(p(&mut input).unwrap(), 'b');
-    assert_eq!(p(&mut input).unwrap(), 'c');
-}
-
-pub fn letter() -> Parsec<char> {
-    satisfy(Rc::new(move |c: char| c.is_alphabetic()))
-}
-
-#[test]
-fn test_alpha_num() {
-    let mut input = "abc123".chars();
-    let p = alpha_num();
-    assert_eq!(p(&mut input).unwrap(), 'a');
-    assert_eq!(p(&mut input).unwrap(), 'b');
-    assert_eq!(p(&mut input).unwrap(), 'c');
-    assert_eq!(p(&mut input).unwrap(), '1');
-    assert_eq!(p(&mut input).unwrap(), '2');
-    assert_eq!(p(&mut input).unwrap(), '3');
-}
-
-pub fn alpha_num() -> Parsec<char> {
-    satisfy(Rc::new(move |c: char| c.is_alphanumeric()))
-}
-
 #[test]
 fn test_lf() {
-    let mut input = "\n".chars();
+    let mut input = Input::new("\n");
     let p = lf();
     assert_eq!(p(&mut input).unwrap(), '\n');
 }
 
-pub fn lf() -> Parsec<char> {
+pub fn lf<'a>() -> Parsec<'a, char> {
     satisfy(Rc::new(move |c: char| c == '\n'))
 }
 
 #[test]
 fn test_string() {
-    let mut input = "你好世界".chars();
+    let mut input = Input::new("你好世界");
     let p = string("你好");
-    assert_eq!(p(&mut input).unwrap(), "你好".to_string());
+    assert_eq!(p(&mut input).unwrap(), "你好");
 }
 
 #[test]
 fn test_string_failed() {
-    let mut input = "[".chars();
+    let mut input = Input::new("[");
     let p = string("[[");
     assert_eq!(
-        p(&mut input),
-        Err(ParsecError {
-            msg: ParsecErrorKind::UnexpectedEOF
-        })
+        p(&mut input).unwrap_err().msg,
+        ParsecErrorKind::UnexpectedEOF
     );
 }
 
-pub fn string(s: &'static str) -> Parsec<String> {
-    Rc::new(move |input: &mut Chars| {
-        if input.as_str().len() < s.len() {
-            return Err(ParsecError {
-                msg: ParsecErrorKind::UnexpectedEOF,
-            });
-        }
-        let z = input.take(s.len()).zip(s.chars());
-        for (a, b) in z {
-            if a != b {
-                return Err(ParsecError {
-                    msg: ParsecErrorKind::UnexpectedChar(a),
-                });
-            }
+/// the part of the input matching `s`. What matched before a mismatch is
+/// consumed, wrap it in `attempt` to try something else instead.
+pub fn string<'a>(s: &'static str) -> Parsec<'a, &'a str> {
+    recognize(Rc::new(move |input: &mut Input<'a, str>| {
+        for expected in s.chars() {
+            parse_char(expected)(input)?;
         }
-        Ok(s.to_string())
-    })
+        Ok(())
+    }))
 }
 
 #[test]
 fn test_crlf() {
-    let mut input = "\r\na".chars();
+    let mut input = Input::new("\r\na");
     let p = crlf();
-    assert_eq!(p(&mut input).unwrap(), "\r\n".to_string());
+    assert_eq!(p(&mut input).unwrap(), "\r\n");
 }
 
-pub fn crlf() -> Parsec<String> {
+pub fn crlf<'a>() -> Parsec<'a, &'a str> {
     string("\r\n")
 }
 
 #[test]
 fn test_newline() {
-    let mut input = "\n".chars();
+    let mut input = Input::new("\n");
     let p = newline();
     assert_eq!(p(&mut input).unwrap(), ());
 
-    let mut input = "\r\n".chars();
+    let mut input = Input::new("\r\n");
     assert_eq!(p(&mut input).unwrap(), ());
 }
 
-pub fn newline() -> Parsec<()> {
-    choice(vec![discard(crlf()), discard(lf())])
-}
-
-#[test]
-fn test_parse_word() {
-    let mut input = "abuhskh hjjh1hh".chars();
-    let p = word();
-    assert_eq!(p(&mut input).unwrap(), "abuhskh".to_string());
-    input.next();
-    assert_eq!(p(&mut input).unwrap(), "hjjh1hh".to_string());
-}
-
-pub fn word() -> Parsec<String> {
-    fmap(many1(alpha_num()), |v| v.into_iter().collect::<String>())
-}
-
-#[test]
-fn test_parse_nonspace() {
-    let mut input = "abuh#@#skh".chars();
-    let p = nonspace();
-    assert_eq!(p(&mut input).unwrap(), "abuh#@#skh".to_string());
-}
-
-pub fn nonspace() -> Parsec<String> {
-    fmap(many1(none_of(" \t\r\n")), |v| {
-        v.into_iter().collect::<String>()
-    })
-}
-
-#[test]
-fn test_parse_nonspaces() {
-    let mut input = "abuh#@#skh   $%%^&".chars();
-    let p = nonspaces();
-    assert_eq!(
-        p(&mut input).unwrap(),
-        vec!["abuh#@#skh".to_string(), "$%%^&".to_string()]
-    );
-}
-
-pub fn nonspaces() -> Parsec<Vec<String>> {
-    sep_by(nonspace(), spaces())
-}
-
-#[test]
-fn test_words() {
-    let mut input = "abuhskh hjjh1hh".chars();
-    let p = words();
-    assert_eq!(
-        p(&mut input).unwrap(),
-        vec!["abuhskh".to_string(), "hjjh1hh".to_string()]
-    );
-}
-
-pub fn words() -> Parsec<Vec<String>> {
-    sep_by(word(), space())
+pub fn newline<'a>() -> Parsec<'a, ()> {
+    choice(vec![discard(attempt(crlf())), discard(lf())])
 }
 
 #[test]
 fn test_string_none_of() {
-    let mut input = "abuhskh hjjh1hh".chars();
+    let mut input = Input::new("abuhskh hjjh1hh");
     let p = string_none_of(" ");
-    assert_eq!(p(&mut input).unwrap(), "abuhskh".to_string());
+    assert_eq!(p(&mut input).unwrap(), "abuhskh");
 }
 
-pub fn string_none_of(s: &'static str) -> Parsec<String> {
-    fmap(many1(none_of(s)), |v| v.into_iter().collect::<String>())
+pub fn string_none_of<'a>(s: &'static str) -> Parsec<'a, &'a str> {
+    recognize(skip_many1(none_of(s)))
 }
@@ -4,20 +4,45 @@
 //! for example, we use `between` to construct a parser that parses something between `left` and `right`.
 
 use crate::parsec::*;
-use std::{error::Error, fmt::Display, rc::Rc, str::Chars};
+use std::{error::Error, fmt::Display, rc::Rc};
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct ParsecError {
     pub(crate) msg: ParsecErrorKind,
+    /// where the unexpected item is.
+    pub(crate) location: Location,
+    /// the names `label` gave to what could have been there.
+    pub(crate) expected: Vec<&'static str>,
+}
+
+impl ParsecError {
+    pub fn new(msg: ParsecErrorKind, location: Location) -> ParsecError {
+        ParsecError {
+            msg,
+            location,
+            expected: vec![],
+        }
+    }
+
+    pub fn location(&self) -> Location {
+        self.location
+    }
 }
 
 impl Error for ParsecError {}
 
 impl Display for ParsecError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: ", self.location)?;
         match &self.msg {
             ParsecErrorKind::UnexpectedEOF => write!(f, "Unexpected EOF"),
             ParsecErrorKind::UnexpectedChar(c) => write!(f, "Unexpected char {}", c),
+            ParsecErrorKind::UnexpectedByte(b) => write!(f, "Unexpected byte {:#04x}", b),
+        }?;
+        match self.expected.as_slice() {
+            [] => Ok(()),
+            [name] => write!(f, ", expected {}", name),
+            names => write!(f, ", expected one of {}", names.join(", ")),
         }
     }
 }
@@ -26,39 +51,45 @@ impl Display for ParsecError {
 pub enum ParsecErrorKind {
     UnexpectedEOF,
     UnexpectedChar(char),
+    UnexpectedByte(u8),
 }
 
-/// A parser is a function that takes a `Chars` iterator and returns a `Result<T, ParsecError>`.
-/// the iterator is mutable because the parser may consume the input, but of course it will not
-/// modify the input.
-pub type Parsec<T> = Rc<dyn Fn(&mut Chars) -> Result<T, ParsecError>>;
+/// A parser is a function that takes an `Input` and returns a `Result<T, ParsecError>`.
+/// the input is mutable because the parser moves it forward, the source itself is never
+/// changed, so results may borrow from it for `'a`. Parsers read `str` unless told `S`.
+pub type Parsec<'a, T, S = str> = Rc<dyn Fn(&mut Input<'a, S>) -> Result<T, ParsecError> + 'a>;
 
 #[test]
 fn test_satisfy() {
-    let mut input = "你好世界".chars();
+    let mut input = Input::new("你好世界");
     let p = satisfy(Rc::new(move |x| x == '你'));
     assert_eq!(p(&mut input).unwrap(), '你');
-}
-
-/// the parser goes ahead regradless success or failure.
-pub fn satisfy(p: Rc<dyn Fn(char) -> bool>) -> Parsec<char> {
-    Rc::new(move |input: &mut Chars| {
-        let next = input.next().ok_or(ParsecError {
-            msg: ParsecErrorKind::UnexpectedEOF,
-        })?;
+    let mut input = Input::new(&b"\x8b"[..]);
+    let p = satisfy(Rc::new(move |x| x == 0x8b));
+    assert_eq!(p(&mut input).unwrap(), 0x8b);
+}
+
+/// the parser takes one item if `p` holds for it, and nothing if not.
+pub fn satisfy<'a, S: ?Sized + Stream + 'a>(
+    p: Rc<dyn Fn(S::Item) -> bool>,
+) -> Parsec<'a, S::Item, S> {
+    Rc::new(move |input: &mut Input<'a, S>| {
+        let location = input.location();
+        let next = input
+            .peek()
+            .ok_or(ParsecError::new(ParsecErrorKind::UnexpectedEOF, location))?;
         if p(next) {
+            input.next();
             Ok(next)
         } else {
-            Err(ParsecError {
-                msg: ParsecErrorKind::UnexpectedChar(next),
-            })
+            Err(ParsecError::new(S::unexpected(next), location))
         }
     })
 }
 
 #[test]
 fn test_lookahead() {
-    let mut input = "你好世界".chars();
+    let mut input = Input::new("你好世界");
     let p = lookahead(parse_char('你'));
     assert_eq!(p(&mut input).unwrap(), '你');
     assert_eq!(input.next().unwrap(), '你');
@@ -66,29 +97,29 @@ fn test_lookahead() {
 
 /// the parser peeks the parse result, but do not consume the input.
 /// thus named `lookahead`.
-pub fn lookahead<T: 'static>(p: Parsec<T>) -> Parsec<T> {
-    Rc::new(move |input: &mut Chars| {
-        let mut input_clone = input.clone();
-        let result = p(&mut input_clone);
-        result
+pub fn lookahead<'a, T: 'a, S: ?Sized + 'a>(p: Parsec<'a, T, S>) -> Parsec<'a, T, S> {
+    Rc::new(move |input: &mut Input<'a, S>| {
+        let mut input_clone = *input;
+        p(&mut input_clone)
     })
 }
 
 #[test]
-fn test_try_parse() {
-    let mut input = "你好世界".chars();
-    let p = try_parse(parse_char('您'));
+fn test_attempt() {
+    let mut input = Input::new("你好世界");
+    let p = attempt(string("你们"));
     assert_eq!(
         p(&mut input).unwrap_err().msg,
-        ParsecErrorKind::UnexpectedChar('你')
+        ParsecErrorKind::UnexpectedChar('好')
     );
     assert_eq!(input.next().unwrap(), '你');
 }
 
-/// the parser will not consume the input if it fails.
-pub fn try_parse<T: 'static>(p: Parsec<T>) -> Parsec<T> {
-    Rc::new(move |input: &mut Chars| {
-        let mut input_clone = input.clone();
+/// the parser will not consume the input if it fails, so `choice`, `many` and
+/// `optional` go on to the next alternative as if `p` had not started.
+pub fn attempt<'a, T: 'a, S: ?Sized + 'a>(p: Parsec<'a, T, S>) -> Parsec<'a, T, S> {
+    Rc::new(move |input: &mut Input<'a, S>| {
+        let mut input_clone = *input;
         match p(&mut input_clone) {
             Ok(x) => {
                 *input = input_clone;
@@ -99,39 +130,107 @@ pub fn try_parse<T: 'static>(p: Parsec<T>) -> Parsec<T> {
     })
 }
 
+#[test]
+fn test_label() {
+    let mut input = Input::new("[l]");
+    let p = label("tag name", parse_char('@'));
+    let e = p(&mut input).unwrap_err();
+    assert_eq!(e.expected, vec!["tag name"]);
+    assert_eq!(e.to_string(), "1:1: Unexpected char [, expected tag name");
+
+    // a parser that consumed the input keeps its own error.
+    let p = label(
+        "tag",
+        between(parse_char('['), parse_char('r'), parse_char(']')),
+    );
+    assert!(p(&mut input).unwrap_err().expected.is_empty());
+}
+
+/// names what `p` parses for the error when it fails without consuming the
+/// input, `choice` gathers the names of its alternatives.
+pub fn label<'a, T: 'a, S: ?Sized + Stream + 'a>(
+    name: &'static str,
+    p: Parsec<'a, T, S>,
+) -> Parsec<'a, T, S> {
+    Rc::new(move |input: &mut Input<'a, S>| {
+        let from = input.offset();
+        p(input).map_err(|mut e| {
+            if input.offset() == from {
+                e.expected = vec![name];
+            }
+            e
+        })
+    })
+}
+
 #[test]
 fn test_choice() {
-    let mut input = "你好世界".chars();
+    let mut input = Input::new("你好世界");
     let p = choice(vec![parse_char('你'), parse_char('您'), parse_char('好')]);
     assert_eq!(p(&mut input).unwrap(), '你');
     assert_eq!(p(&mut input).unwrap(), '好');
     assert_eq!(
-        p(&mut input),
-        Err(ParsecError {
-            msg: ParsecErrorKind::UnexpectedChar('世')
-        })
+        p(&mut input).unwrap_err().msg,
+        ParsecErrorKind::UnexpectedChar('世')
+    );
+
+    let p = choice(vec![
+        label("greeting", string("你")),
+        label("world", string("世间")),
+        label("end", string("界")),
+    ]);
+    let e = p(&mut Input::new("好")).unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        "1:1: Unexpected char 好, expected one of greeting, world, end"
+    );
+    // `string` consumed 世 before it failed, so `choice` does not try `end`.
+    let e = p(&mut Input::new("世界")).unwrap_err();
+    assert_eq!(e.location().column, 2);
+    assert!(e.expected.is_empty());
+    let p = choice(vec![attempt(string("世间")), string("世界")]);
+    assert_eq!(p(&mut Input::new("世界")).unwrap(), "世界");
+    let p: Parsec<char> = choice(vec![]);
+    assert_eq!(
+        p(&mut Input::new("")).unwrap_err().msg,
+        ParsecErrorKind::UnexpectedEOF
     );
 }
 
-/// the parser will try every parser in the vector, and return the first success.
-/// upon success, the input will be consumed.
-pub fn choice<T: 'static>(ps: Vec<Parsec<T>>) -> Parsec<T> {
-    Rc::new(move |input: &mut Chars| {
-        ps.clone().into_iter().map(try_parse).fold(
-            Err(ParsecError {
-                msg: ParsecErrorKind::UnexpectedEOF,
-            }),
-            |acc, p| match acc {
-                Ok(x) => Ok(x),
-                Err(_) => p(input),
-            },
-        )
+/// the parser will try the parsers in the vector in turn, and return the first success.
+/// it goes to the next one only if the last failed without consuming the input, the
+/// error is then the item here and everything the alternatives `label`ed.
+pub fn choice<'a, T: 'a, S: ?Sized + Stream + 'a>(ps: Vec<Parsec<'a, T, S>>) -> Parsec<'a, T, S> {
+    Rc::new(move |input: &mut Input<'a, S>| {
+        let location = input.location();
+        let mut expected = vec![];
+        for p in ps.iter() {
+            match p(input) {
+                Ok(x) => return Ok(x),
+                Err(e) if input.offset() != location.offset => return Err(e),
+                Err(e) => {
+                    for name in e.expected {
+                        if !expected.contains(&name) {
+                            expected.push(name);
+                        }
+                    }
+                }
+            }
+        }
+        let msg = input
+            .peek()
+            .map_or(ParsecErrorKind::UnexpectedEOF, S::unexpected);
+        Err(ParsecError {
+            msg,
+            location,
+            expected,
+        })
     })
 }
 
 #[test]
 fn test_many() {
-    let mut input = "你你你你你你".chars();
+    let mut input = Input::new("你你你你你你");
     let p = many(parse_char('你'));
     assert_eq!(
         p(&mut input).unwrap(),
@@ -140,16 +239,20 @@ fn test_many() {
 }
 
 /// the parser will try to parse the input as many times as possible.
-/// upon failure, the input will not be consumed.
-pub fn many<T: 'static>(p: Parsec<T>) -> Parsec<Vec<T>> {
-    Rc::new(move |input: &mut Chars| {
+/// it stops when `p` fails without consuming the input, and fails if `p` did consume.
+pub fn many<'a, T: 'a, S: ?Sized + Stream + 'a>(p: Parsec<'a, T, S>) -> Parsec<'a, Vec<T>, S> {
+    Rc::new(move |input: &mut Input<'a, S>| {
         let mut result = vec![];
-        let many_parser = try_parse(p.clone());
         loop {
-            match many_parser(input) {
+            let from = input.offset();
+            match p(input) {
                 Ok(x) => result.push(x),
+                Err(e) if input.offset() != from => return Err(e),
                 Err(_) => break,
             }
+            if input.offset() == from {
+                break;
+            }
         }
         Ok(result)
     })
@@ -157,38 +260,27 @@ pub fn many<T: 'static>(p: Parsec<T>) -> Parsec<Vec<T>> {
 
 #[test]
 fn test_many1() {
-    let mut input = "好".chars();
+    let mut input = Input::new("好");
     let p = many1(parse_char('你'));
     assert_eq!(
-        p(&mut input),
-        Err(ParsecError {
-            msg: ParsecErrorKind::UnexpectedChar('好')
-        })
+        p(&mut input).unwrap_err().msg,
+        ParsecErrorKind::UnexpectedChar('好')
     );
 }
 
 /// the parser will try to parse the input as many times as possible, but more than once
-pub fn many1(p: Parsec<char>) -> Parsec<Vec<char>> {
-    Rc::new(move |input: &mut Chars| {
-        let mut result = vec![];
-        let many1_parser = try_parse(p.clone());
-        match many1_parser(input) {
-            Ok(x) => result.push(x),
-            Err(e) => return Err(e),
-        }
-        loop {
-            match many1_parser(input) {
-                Ok(x) => result.push(x),
-                Err(_) => break,
-            }
-        }
+pub fn many1<'a, T: 'a, S: ?Sized + Stream + 'a>(p: Parsec<'a, T, S>) -> Parsec<'a, Vec<T>, S> {
+    let rest = many(p.clone());
+    Rc::new(move |input: &mut Input<'a, S>| {
+        let mut result = vec![p(input)?];
+        result.extend(rest(input)?);
         Ok(result)
     })
 }
 
 #[test]
 fn test_skip_many() {
-    let mut input = "你你你你你你好".chars();
+    let mut input = Input::new("你你你你你你好");
     let p = skip_many(parse_char('你'));
     assert_eq!(p(&mut input).unwrap(), ());
     let p2 = parse_char('好');
@@ -196,22 +288,13 @@ fn test_skip_many() {
 }
 
 /// the parser will skip all seq appearence of pattern p
-pub fn skip_many<T: 'static>(p: Parsec<T>) -> Parsec<()> {
-    Rc::new(move |input: &mut Chars| {
-        let many_parser = try_parse(p.clone());
-        loop {
-            match many_parser(input) {
-                Ok(_) => (),
-                Err(_) => break,
-            }
-        }
-        Ok(())
-    })
+pub fn skip_many<'a, T: 'a, S: ?Sized + Stream + 'a>(p: Parsec<'a, T, S>) -> Parsec<'a, (), S> {
+    discard(many(p))
 }
 
 #[test]
 fn test_skip_many1() {
-    let mut input = "你你你你你你好".chars();
+    let mut input = Input::new("你你你你你你好");
     let p = skip_many1(parse_char('你'));
     assert_eq!(p(&mut input).unwrap(), ());
     let p2 = parse_char('好');
@@ -219,79 +302,38 @@ fn test_skip_many1() {
 }
 
 /// the parser will skip all seq appearence of pattern p, but more than once
-pub fn skip_many1(p: Parsec<char>) -> Parsec<()> {
-    Rc::new(move |input: &mut Chars| {
-        let p_parser = try_parse(p.clone());
-        match p_parser(input) {
-            Ok(_) => (),
-            Err(e) => return Err(e),
-        }
-        loop {
-            match p_parser(input) {
-                Ok(_) => (),
-                Err(_) => break,
-            }
-        }
-        Ok(())
-    })
-}
-
-#[test]
-fn test_sep_by() {
-    let mut input = "你,你,你,你,你,你,你".chars();
-    let p = sep_by(parse_char('你'), parse_char(','));
-    assert_eq!(
-        p(&mut input).unwrap(),
-        vec!['你', '你', '你', '你', '你', '你', '你']
-    );
-}
-
-/// the parser will parse ps separated by seps
-pub fn sep_by<T: 'static, U: 'static>(p: Parsec<T>, sep: Parsec<U>) -> Parsec<Vec<T>> {
-    Rc::new(move |input: &mut Chars| {
-        let t_parser = try_parse(p.clone());
-        let sep_parser = try_parse(sep.clone());
-        let mut result = vec![];
-        match t_parser(input) {
-            Ok(x) => result.push(x),
-            Err(_) => return Ok(result),
-        }
-        loop {
-            match sep_parser(input) {
-                Ok(_) => (),
-                Err(_) => break,
-            }
-            match t_parser(input) {
-                Ok(x) => result.push(x),
-                Err(_) => break,
-            }
-        }
-        Ok(result)
-    })
+pub fn skip_many1<'a, T: 'a, S: ?Sized + Stream + 'a>(p: Parsec<'a, T, S>) -> Parsec<'a, (), S> {
+    discard(many1(p))
 }
 
 #[test]
 fn test_fmap() {
-    let mut input = "1".chars();
+    let mut input = Input::new("1");
     let p = fmap(parse_char('1'), |x| x.to_digit(10).unwrap());
     assert_eq!(p(&mut input).unwrap(), 1);
 }
 
 /// the function can convert the result of a parser from type T to type U
-pub fn fmap<T: 'static, U: 'static>(p: Parsec<T>, f: fn(T) -> U) -> Parsec<U> {
-    Rc::new(move |input: &mut Chars| p(input).map(f))
+pub fn fmap<'a, T: 'a, U: 'a, S: ?Sized + 'a>(
+    p: Parsec<'a, T, S>,
+    f: fn(T) -> U,
+) -> Parsec<'a, U, S> {
+    Rc::new(move |input: &mut Input<'a, S>| p(input).map(f))
 }
 
 #[test]
 fn test_bind() {
-    let mut input = "12".chars();
+    let mut input = Input::new("12");
     let p = bind(parse_char('1'), Rc::new(|_| parse_char('2')));
     assert_eq!(p(&mut input).unwrap(), '2');
 }
 
 /// Monad m => m a -> (a -> m b) -> m b
-pub fn bind<T: 'static, U: 'static>(p: Parsec<T>, f: Rc<dyn Fn(T) -> Parsec<U>>) -> Parsec<U> {
-    Rc::new(move |input: &mut Chars| {
+pub fn bind<'a, T: 'a, U: 'a, S: ?Sized + 'a>(
+    p: Parsec<'a, T, S>,
+    f: Rc<dyn Fn(T) -> Parsec<'a, U, S> + 'a>,
+) -> Parsec<'a, U, S> {
+    Rc::new(move |input: &mut Input<'a, S>| {
         let x = p(input)?;
         f(x)(input)
     })
@@ -299,42 +341,42 @@ pub fn bind<T: 'static, U: 'static>(p: Parsec<T>, f: Rc<dyn Fn(T) -> Parsec<U>>)
 
 #[test]
 fn test_pure() {
-    let mut input = "".chars();
+    let mut input = Input::new("");
     let p = pure(1);
     assert_eq!(p(&mut input).unwrap(), 1);
 }
 
 /// this function returns a parser that always returns x
-pub fn pure<T: 'static + Clone>(x: T) -> Parsec<T> {
-    Rc::new(move |_: &mut Chars| Ok(x.clone()))
+pub fn pure<'a, T: 'a + Clone, S: ?Sized + 'a>(x: T) -> Parsec<'a, T, S> {
+    Rc::new(move |_: &mut Input<'a, S>| Ok(x.clone()))
 }
 
 #[test]
 fn test_discard() {
-    let mut input = "1".chars();
+    let mut input = Input::new("1");
     let p = discard(parse_char('1'));
     assert_eq!(p(&mut input).unwrap(), ());
 }
 
 /// this function discards the result of a parser
-pub fn discard<T: 'static>(p: Parsec<T>) -> Parsec<()> {
+pub fn discard<'a, T: 'a, S: ?Sized + 'a>(p: Parsec<'a, T, S>) -> Parsec<'a, (), S> {
     fmap(p, |_| ())
 }
 
 #[test]
 fn test_between() {
-    let mut input = "*page34|".chars();
-    let p = between(string("*page"), dec_num(), parse_char('|'));
-    assert_eq!(p(&mut input).unwrap(), 34);
+    let mut input = Input::new("*page34|");
+    let p = between(string("*page"), string_none_of("|"), parse_char('|'));
+    assert_eq!(p(&mut input).unwrap(), "34");
 }
 
 /// this function parses something between left and right
-pub fn between<T: 'static, U: 'static, V: 'static>(
-    open: Parsec<T>,
-    p: Parsec<U>,
-    close: Parsec<V>,
-) -> Parsec<U> {
-    Rc::new(move |input: &mut Chars| {
+pub fn between<'a, T: 'a, U: 'a, V: 'a, S: ?Sized + 'a>(
+    open: Parsec<'a, T, S>,
+    p: Parsec<'a, U, S>,
+    close: Parsec<'a, V, S>,
+) -> Parsec<'a, U, S> {
+    Rc::new(move |input: &mut Input<'a, S>| {
         open(input)?;
         let result = p(input)?;
         close(input)?;
@@ -342,23 +384,46 @@ pub fn between<T: 'static, U: 'static, V: 'static>(
     })
 }
 
+#[test]
+fn test_recognize() {
+    let mut input = Input::new("你你好");
+    let p = recognize(skip_many(parse_char('你')));
+    assert_eq!(p(&mut input).unwrap(), "你你");
+    let mut input = Input::new(&b"XP3\r\n"[..]);
+    let p = recognize(many(satisfy(Rc::new(|b: u8| {
+        b.is_ascii_uppercase() || b.is_ascii_digit()
+    }))));
+    assert_eq!(p(&mut input).unwrap(), b"XP3");
+}
+
+/// the part of the input `p` parses, borrowed from the source.
+pub fn recognize<'a, T: 'a, S: ?Sized + Stream + 'a>(p: Parsec<'a, T, S>) -> Parsec<'a, &'a S, S> {
+    Rc::new(move |input: &mut Input<'a, S>| {
+        let from = input.offset();
+        p(input)?;
+        Ok(input.since(from))
+    })
+}
+
 #[test]
 fn test_optional() {
-    let mut input = "你好".chars();
+    let mut input = Input::new("你好");
     let p = optional(parse_char('你'));
     assert_eq!(p(&mut input).unwrap(), Some('你'));
     assert_eq!(p(&mut input).unwrap(), None);
-}
-
-/// does not consume the input if the parser fails
-pub fn optional<T: 'static>(p: Parsec<T>) -> Parsec<Option<T>> {
-    Rc::new(move |input: &mut Chars| {
-        let mut input_clone = input.clone();
-        match p(&mut input_clone) {
-            Ok(x) => {
-                *input = input_clone;
-                Ok(Some(x))
-            }
+    let p = optional(string("好的"));
+    assert!(p(&mut input).is_err());
+}
+
+/// `None` if the parser fails without consuming the input, an error if it did consume.
+pub fn optional<'a, T: 'a, S: ?Sized + Stream + 'a>(
+    p: Parsec<'a, T, S>,
+) -> Parsec<'a, Option<T>, S> {
+    Rc::new(move |input: &mut Input<'a, S>| {
+        let from = input.offset();
+        match p(input) {
+            Ok(x) => Ok(Some(x)),
+            Err(e) if input.offset() != from => Err(e),
             Err(_) => Ok(None),
         }
     })
@@ -392,7 +457,7 @@ macro_rules! mkpc {
 
 #[test]
 fn test_pc() {
-    let mut input = "12|3".chars();
+    let mut input = Input::new("12|3");
     let p: Parsec<u8> = mkpc![
         x <- parse_char('1');
         y <- parse_char('2');
@@ -0,0 +1,191 @@
+//! # Input
+//!
+//! Parsers read an `Input`, a `str` or a `[u8]` with how far they have got
+//! in it. It is a pair of a reference and a location, so parsers copy it to
+//! backtrack and slice the source to give results without allocating.
+
+use std::{fmt, ops::Range};
+
+use crate::parsec::ParsecErrorKind;
+
+/// what an `Input` can read: text by `char` or binary by `u8`.
+pub trait Stream {
+    type Item: Copy + fmt::Debug + PartialEq;
+
+    /// the item at the byte `offset` with its length in bytes.
+    fn item_at(&self, offset: usize) -> Option<(Self::Item, usize)>;
+
+    fn is_newline(item: Self::Item) -> bool;
+
+    /// the error for an item a parser does not take.
+    fn unexpected(item: Self::Item) -> ParsecErrorKind;
+
+    fn slice(&self, range: Range<usize>) -> &Self;
+}
+
+impl Stream for str {
+    type Item = char;
+
+    fn item_at(&self, offset: usize) -> Option<(char, usize)> {
+        self[offset..].chars().next().map(|c| (c, c.len_utf8()))
+    }
+
+    fn is_newline(item: char) -> bool {
+        item == '\n'
+    }
+
+    fn unexpected(item: char) -> ParsecErrorKind {
+        ParsecErrorKind::UnexpectedChar(item)
+    }
+
+    fn slice(&self, range: Range<usize>) -> &str {
+        &self[range]
+    }
+}
+
+impl Stream for [u8] {
+    type Item = u8;
+
+    fn item_at(&self, offset: usize) -> Option<(u8, usize)> {
+        self.get(offset).map(|&b| (b, 1))
+    }
+
+    fn is_newline(item: u8) -> bool {
+        item == b'\n'
+    }
+
+    fn unexpected(item: u8) -> ParsecErrorKind {
+        ParsecErrorKind::UnexpectedByte(item)
+    }
+
+    fn slice(&self, range: Range<usize>) -> &[u8] {
+        &self[range]
+    }
+}
+
+/// where a parser is in its input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub offset: usize,
+    /// counted from 1.
+    pub line: usize,
+    /// in items after the last `\n`, counted from 1.
+    pub column: usize,
+}
+
+impl Default for Location {
+    fn default() -> Self {
+        Location {
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Debug)]
+pub struct Input<'a, S: ?Sized> {
+    source: &'a S,
+    location: Location,
+}
+
+impl<'a, S: ?Sized> Clone for Input<'a, S> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, S: ?Sized> Copy for Input<'a, S> {}
+
+impl<'a, S: ?Sized + Stream> Input<'a, S> {
+    pub fn new(source: &'a S) -> Input<'a, S> {
+        Input {
+            source,
+            location: Location::default(),
+        }
+    }
+
+    pub fn location(&self) -> Location {
+        self.location
+    }
+
+    pub fn offset(&self) -> usize {
+        self.location.offset
+    }
+
+    pub fn peek(&self) -> Option<S::Item> {
+        self.source
+            .item_at(self.location.offset)
+            .map(|(item, _)| item)
+    }
+
+    /// the source from the byte `from` up to where the input is.
+    pub fn since(&self, from: usize) -> &'a S {
+        self.source.slice(from..self.location.offset)
+    }
+}
+
+impl<'a, S: ?Sized + Stream> Iterator for Input<'a, S> {
+    type Item = S::Item;
+
+    fn next(&mut self) -> Option<S::Item> {
+        let (item, len) = self.source.item_at(self.location.offset)?;
+        self.location.offset += len;
+        if S::is_newline(item) {
+            self.location.line += 1;
+            self.location.column = 1;
+        } else {
+            self.location.column += 1;
+        }
+        Some(item)
+    }
+}
+
+impl<'a> Input<'a, str> {
+    /// the rest of the source.
+    pub fn as_str(&self) -> &'a str {
+        &self.source[self.location.offset..]
+    }
+}
+
+impl<'a> Input<'a, [u8]> {
+    /// the rest of the source.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        &self.source[self.location.offset..]
+    }
+}
+
+#[test]
+fn test_location() {
+    let mut input = Input::new("桜\r\nab");
+    assert_eq!(input.next(), Some('桜'));
+    assert_eq!(input.location().column, 2);
+    input.next();
+    let before = input;
+    assert_eq!(input.next(), Some('\n'));
+    assert_eq!(
+        input.location(),
+        Location {
+            offset: 5,
+            line: 2,
+            column: 1,
+        }
+    );
+    assert_eq!(before.location().line, 1);
+    input.next();
+    assert_eq!(input.since(5), "a");
+    assert_eq!(input.as_str(), "b");
+    assert_eq!(input.location().to_string(), "2:2");
+
+    let mut bytes = Input::new(&b"\x00\n\xff"[..]);
+    bytes.nth(1);
+    assert_eq!(bytes.peek(), Some(0xff));
+    assert_eq!(bytes.location().line, 2);
+    assert_eq!(bytes.as_bytes(), b"\xff");
+}
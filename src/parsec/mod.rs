@@ -2,7 +2,10 @@
 //!
 //! It provides a set of functions to create parsers.
 //! these functions give you a `parsec`, which is a parsing function.
-//! You can use `parsec` to parse a `Chars` iterator. It will return a `Result<T, ParsecError>`.
+//! You can use `parsec` to parse an `Input` of text or bytes. It will return a `Result<T, ParsecError>`.
+
+pub mod input;
+pub use input::*;
 
 pub mod combinator;
 pub use combinator::*;
@@ -10,16 +13,19 @@ pub use combinator::*;
 pub mod char;
 pub use char::*;
 
-use std::str::Chars;
+pub mod byte;
 
 #[test]
 fn test_runparser() {
-    let mut input = "你".chars();
+    let mut input = Input::new("你");
     let p = parse_char('你');
     assert_eq!(run_parser(p, &mut input).unwrap(), '你');
 }
 
-pub fn run_parser<T>(parser: Parsec<T>, input: &mut Chars) -> Result<T, ParsecError> {
+pub fn run_parser<'a, T, S: ?Sized>(
+    parser: Parsec<'a, T, S>,
+    input: &mut Input<'a, S>,
+) -> Result<T, ParsecError> {
     parser(input)
 }
 
@@ -29,34 +35,19 @@ fn test_runparser_str() {
     assert_eq!(run_parser_str(p, "你").unwrap(), '你');
 }
 
-pub fn run_parser_str<T>(parser: Parsec<T>, input: &str) -> Result<T, ParsecError> {
-    run_parser(parser, &mut input.chars())
+pub fn run_parser_str<'a, T>(parser: Parsec<'a, T>, input: &'a str) -> Result<T, ParsecError> {
+    run_parser(parser, &mut Input::new(input))
+}
+
+#[test]
+fn test_runparser_bytes() {
+    let p = byte::byte(0x1a);
+    assert_eq!(run_parser_bytes(p, b"\x1a").unwrap(), 0x1a);
 }
 
-#[cfg(test)]
-mod tests {
-    const TEST_TEXT: &str = "
-    *page47|
-    @textoff
-    @sestop file=se009 time=1500 nowait=true
-    @a2aT file=o小さな公園-(曇)
-    @play file=bgm05 time=0
-    @texton
-    Illya and I are alone in the small park a little way from the shopping district.[lr]
-    Maybe all the children are at school or maybe such a small park isn’t popular anymore.[lr]
-    We start to talk in the empty winter park, bathed in a strangely tense atmosphere.
-    @pg
-    ";
-
-    use super::*;
-
-    #[test]
-    fn test_char() {
-        let linebreak_parser = sep_by(
-            fmap(many(none_of("\n")), |v| v.into_iter().collect::<String>()),
-            lf(),
-        );
-        let mut input = TEST_TEXT.chars();
-        println!("{:?}", linebreak_parser(&mut input));
-    }
+pub fn run_parser_bytes<'a, T>(
+    parser: Parsec<'a, T, [u8]>,
+    input: &'a [u8],
+) -> Result<T, ParsecError> {
+    run_parser(parser, &mut Input::new(input))
 }
@@ -1,4 +1,4 @@
-use crate::interpreter::interpreter::State;
+use crate::interpreter::{interpreter::State, mode::Mode};
 
 use super::UI;
 
@@ -7,7 +7,17 @@ pub struct KrkrsCli {}
 
 impl UI for KrkrsCli {
     fn render(&mut self, state: &State) -> Result<(), String> {
-        println!("game state: {:?}", state.get_render_ctx());
+        let ctx = state.get_render_ctx();
+        println!("scene: {:?}", ctx.scene);
+        if ctx.mode != Mode::Normal {
+            println!("mode: {}", ctx.mode.name());
+        }
+        for line in ctx.text {
+            println!("{}", line);
+        }
+        for warning in ctx.warnings {
+            eprintln!("warning: {}", warning);
+        }
         Ok(())
     }
 }
@@ -1,4 +1,9 @@
-use crate::interpreter::interpreter::{RenderContext, State};
+use crate::interpreter::{
+    backlog::{BacklogEntry, Position},
+    interpreter::{RenderContext, State},
+    message::{Align, Font, Line, TextRun},
+    save::SavePoint,
+};
 use crate::presentation::UI;
 use js_sys::global;
 use wasm_bindgen::JsValue;
@@ -12,6 +17,94 @@ pub struct WebUI {
     render_callback: js_sys::Function,
 }
 
+/// colors are handed to the front end as css colors.
+fn css_color(color: Option<u32>) -> JsValue {
+    JsValue::from(color.map(|c| format!("#{:06x}", c)))
+}
+
+impl From<Font> for JsValue {
+    fn from(font: Font) -> Self {
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"face".into(), &JsValue::from(font.face)).unwrap();
+        js_sys::Reflect::set(&obj, &"size".into(), &JsValue::from(font.size)).unwrap();
+        js_sys::Reflect::set(&obj, &"color".into(), &css_color(font.color)).unwrap();
+        js_sys::Reflect::set(&obj, &"bold".into(), &JsValue::from(font.bold)).unwrap();
+        js_sys::Reflect::set(&obj, &"italic".into(), &JsValue::from(font.italic)).unwrap();
+        js_sys::Reflect::set(&obj, &"shadow".into(), &JsValue::from(font.shadow)).unwrap();
+        js_sys::Reflect::set(&obj, &"shadowColor".into(), &css_color(font.shadow_color)).unwrap();
+        js_sys::Reflect::set(&obj, &"edge".into(), &JsValue::from(font.edge)).unwrap();
+        js_sys::Reflect::set(&obj, &"edgeColor".into(), &css_color(font.edge_color)).unwrap();
+        obj.into()
+    }
+}
+
+impl From<TextRun> for JsValue {
+    fn from(run: TextRun) -> Self {
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"text".into(), &JsValue::from(run.text)).unwrap();
+        js_sys::Reflect::set(&obj, &"ruby".into(), &JsValue::from(run.ruby)).unwrap();
+        js_sys::Reflect::set(&obj, &"font".into(), &JsValue::from(run.font)).unwrap();
+        js_sys::Reflect::set(&obj, &"delay".into(), &JsValue::from(run.delay)).unwrap();
+        js_sys::Reflect::set(&obj, &"revealed".into(), &JsValue::from(run.revealed)).unwrap();
+        js_sys::Reflect::set(&obj, &"read".into(), &JsValue::from(run.read)).unwrap();
+        obj.into()
+    }
+}
+
+impl From<Line> for JsValue {
+    fn from(line: Line) -> Self {
+        let align = match line.align {
+            Align::Left => "left",
+            Align::Center => "center",
+            Align::Right => "right",
+        };
+        let runs = line
+            .runs
+            .into_iter()
+            .map(JsValue::from)
+            .collect::<js_sys::Array>();
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"align".into(), &JsValue::from(align)).unwrap();
+        js_sys::Reflect::set(&obj, &"runs".into(), &JsValue::from(runs)).unwrap();
+        obj.into()
+    }
+}
+
+impl From<Position> for JsValue {
+    fn from(position: Position) -> Self {
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"storage".into(), &JsValue::from(position.storage)).unwrap();
+        js_sys::Reflect::set(&obj, &"label".into(), &JsValue::from(position.label)).unwrap();
+        js_sys::Reflect::set(&obj, &"index".into(), &JsValue::from(position.index)).unwrap();
+        obj.into()
+    }
+}
+
+impl From<SavePoint> for JsValue {
+    fn from(point: SavePoint) -> Self {
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"position".into(), &JsValue::from(point.position)).unwrap();
+        js_sys::Reflect::set(&obj, &"title".into(), &JsValue::from(point.title)).unwrap();
+        obj.into()
+    }
+}
+
+impl From<BacklogEntry> for JsValue {
+    fn from(entry: BacklogEntry) -> Self {
+        let lines = entry
+            .lines
+            .into_iter()
+            .map(JsValue::from)
+            .collect::<js_sys::Array>();
+        let obj = js_sys::Object::new();
+        js_sys::Reflect::set(&obj, &"speaker".into(), &JsValue::from(entry.speaker)).unwrap();
+        js_sys::Reflect::set(&obj, &"lines".into(), &JsValue::from(lines)).unwrap();
+        js_sys::Reflect::set(&obj, &"voice".into(), &JsValue::from(entry.voice)).unwrap();
+        js_sys::Reflect::set(&obj, &"position".into(), &JsValue::from(entry.position)).unwrap();
+        obj.into()
+    }
+}
+
 impl From<RenderContext> for JsValue {
     fn from(ctx: RenderContext) -> Self {
         let scene = ctx
@@ -24,9 +117,16 @@ impl From<RenderContext> for JsValue {
             .into_iter()
             .map(JsValue::from)
             .collect::<js_sys::Array>();
+        let warnings = ctx
+            .warnings
+            .into_iter()
+            .map(JsValue::from)
+            .collect::<js_sys::Array>();
         let obj = js_sys::Object::new();
         js_sys::Reflect::set(&obj, &"scene".into(), &JsValue::from(scene)).unwrap();
         js_sys::Reflect::set(&obj, &"text".into(), &JsValue::from(text)).unwrap();
+        js_sys::Reflect::set(&obj, &"mode".into(), &JsValue::from(ctx.mode.name())).unwrap();
+        js_sys::Reflect::set(&obj, &"warnings".into(), &JsValue::from(warnings)).unwrap();
         obj.into()
     }
 }
@@ -36,7 +136,7 @@ impl UI for WebUI {
         self.render_callback
             .call1(&global(), &JsValue::from(state.get_render_ctx()))
             .map_err(|e| e.as_string().unwrap_or("".to_string()))
-            .and_then(|_| Ok(()))
+            .map(|_| ())
     }
 }
 
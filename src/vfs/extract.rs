@@ -0,0 +1,132 @@
+//! # Extract
+//!
+//! Picks files out of an archive by glob and turns them into something
+//! ordinary tools open: TLG images into PNG, scrambled scripts into UTF-8.
+
+use std::{
+    error::Error,
+    path::{Component, Path, PathBuf},
+};
+
+use super::{text, tlg};
+
+/// extensions of the files `utf8` turns into UTF-8.
+const TEXT_EXTS: &[&str] = &["ks", "tjs", "txt", "csv", "ini", "asd"];
+
+#[derive(Debug, Clone, Default)]
+pub struct Extract {
+    /// patterns with `*` and `?`, matched against the whole path ignoring
+    /// case. A pattern without `/` is matched against the file name only.
+    /// No pattern matches everything.
+    pub globs: Vec<String>,
+    /// TLG images to PNG.
+    pub png: bool,
+    /// scripts to UTF-8, unscrambling them on the way.
+    pub utf8: bool,
+}
+
+impl Extract {
+    pub fn matches(&self, path: &str) -> bool {
+        let file = path.rsplit('/').next().unwrap_or(path);
+        self.globs.is_empty()
+            || self.globs.iter().any(|g| {
+                let target = if g.contains('/') { path } else { file };
+                glob_match(&g.to_lowercase(), &target.to_lowercase())
+            })
+    }
+
+    /// the path to write the entry to, relative to the output directory, and
+    /// its converted content. Paths that would climb out are flattened.
+    pub fn convert(&self, path: &str, data: Vec<u8>) -> Result<(PathBuf, Vec<u8>), Box<dyn Error>> {
+        let mut out: PathBuf = Path::new(path)
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(name) => Some(name),
+                _ => None,
+            })
+            .collect();
+        let ext = out
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if self.png && ext == "tlg" {
+            out.set_extension("png");
+            return Ok((out, tlg::decode(&data)?.to_png()));
+        }
+        if self.utf8 && TEXT_EXTS.contains(&ext.as_str()) {
+            return Ok((out, text::decode(&data, None)?.into_bytes()));
+        }
+        Ok((out, data))
+    }
+}
+
+/// `*` matches any run of characters, `?` any one character.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    // where the last `*` was and what it has swallowed up to.
+    let mut star: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        match p.get(pi) {
+            Some('*') => {
+                star = Some((pi, ti));
+                pi += 1;
+            }
+            Some(&c) if c == '?' || c == t[ti] => {
+                pi += 1;
+                ti += 1;
+            }
+            _ => match star {
+                Some((sp, st)) => {
+                    pi = sp + 1;
+                    ti = st + 1;
+                    star = Some((sp, st + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob() {
+        assert!(glob_match("*.tlg", "bg01.tlg"));
+        assert!(glob_match("bg??.*", "bg01.tlg"));
+        assert!(glob_match("*a*b*", "xaxxbx"));
+        assert!(!glob_match("*.tlg", "bg01.png"));
+        assert!(!glob_match("bg?.tlg", "bg01.tlg"));
+
+        let e = Extract {
+            globs: vec!["*.KS".to_string(), "image/*".to_string()],
+            ..Extract::default()
+        };
+        assert!(e.matches("scenario/first.ks"));
+        assert!(e.matches("image/bg.tlg"));
+        assert!(!e.matches("bgimage/bg.tlg"));
+    }
+
+    #[test]
+    fn test_convert() {
+        let e = Extract {
+            utf8: true,
+            png: true,
+            ..Extract::default()
+        };
+        let (path, data) = e
+            .convert("../scenario/first.ks", b"\xff\xfea\x00".to_vec())
+            .unwrap();
+        assert_eq!(path, Path::new("scenario/first.ks"));
+        assert_eq!(data, b"a");
+
+        let (path, data) = e.convert("voice/a.ogg", b"OggS".to_vec()).unwrap();
+        assert_eq!(path, Path::new("voice/a.ogg"));
+        assert_eq!(data, b"OggS");
+        assert!(e.convert("bg.tlg", b"broken".to_vec()).is_err());
+    }
+}
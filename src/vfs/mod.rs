@@ -1,2 +1,15 @@
 //! This module explores `xp3` files and extract assets that we need.
-//! 
\ No newline at end of file
+//!
+
+/// pulling files out of archives
+pub mod extract;
+/// PNG encoder for decoded pictures
+pub mod png;
+/// asset lookup across directories and archives
+pub mod storage;
+/// script decoding and unscrambling
+pub mod text;
+/// TLG5 and TLG6 image decoder
+pub mod tlg;
+/// XP3 archive reader
+pub mod xp3;
@@ -0,0 +1,73 @@
+//! # PNG
+//!
+//! Just enough of an encoder to hand decoded pictures to a browser: one
+//! truecolor-with-alpha IDAT, no filtering, deflated by `miniz_oxide`.
+
+const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// encodes `width * height` RGBA pixels.
+pub fn encode(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    let stride = width as usize * 4;
+    let mut raw = Vec::with_capacity((stride + 1) * height as usize);
+    for row in rgba.chunks(stride.max(1)).take(height as usize) {
+        // filter type none
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bits per channel, RGBA, deflate, adaptive filtering, no interlace
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    let mut png = SIGNATURE.to_vec();
+    chunk(&mut png, b"IHDR", &header);
+    chunk(
+        &mut png,
+        b"IDAT",
+        &miniz_oxide::deflate::compress_to_vec_zlib(&raw, 6),
+    );
+    chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let png = encode(1, 1, &[1, 2, 3, 4]);
+        assert!(png.starts_with(SIGNATURE));
+        assert!(png.ends_with(b"IEND\xae\x42\x60\x82"));
+
+        let idat = &png[8 + 25 + 8..png.len() - 12 - 4];
+        let raw = miniz_oxide::inflate::decompress_to_vec_zlib(idat).unwrap();
+        assert_eq!(raw, vec![0, 1, 2, 3, 4]);
+    }
+}
@@ -0,0 +1,264 @@
+//! # Storage
+//!
+//! Scripts name assets the way KiriKiri does: by file name alone, in any
+//! case, often without an extension. `Storage` looks such names up in the
+//! mounted directories and archives, the last mounted first so that patch
+//! archives win over the data they patch.
+
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use super::{tlg, xp3::Xp3};
+
+/// extensions tried for `[bg]`, `[image]` and friends, in krkr's order.
+pub const IMAGE_EXTS: &[&str] = &["png", "jpg", "jpeg", "tlg", "bmp"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotFound(pub String);
+
+impl fmt::Display for NotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "storage {} is not found", self.0)
+    }
+}
+
+impl Error for NotFound {}
+
+#[derive(Debug)]
+enum Source {
+    Dir(PathBuf),
+    Archive(Xp3),
+}
+
+/// a directory or an archive with its files by lowercased name.
+#[derive(Debug)]
+struct Mount {
+    source: Source,
+    paths: Vec<String>,
+    /// both the full path and the bare file name lead to the stored path.
+    names: HashMap<String, String>,
+}
+
+impl Mount {
+    fn new(source: Source, paths: Vec<String>) -> Mount {
+        let mut names = HashMap::new();
+        for path in &paths {
+            let file = path.rsplit('/').next().unwrap_or(path).to_lowercase();
+            names.entry(file).or_insert_with(|| path.clone());
+            names.insert(path.to_lowercase(), path.clone());
+        }
+        Mount {
+            source,
+            paths,
+            names,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Storage {
+    mounts: Vec<Mount>,
+}
+
+impl Storage {
+    pub fn new() -> Storage {
+        Storage::default()
+    }
+
+    pub fn mount_dir(&mut self, dir: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        let dir = dir.as_ref().to_path_buf();
+        let mut paths = Vec::new();
+        walk(&dir, "", &mut paths)?;
+        self.mounts.push(Mount::new(Source::Dir(dir), paths));
+        Ok(())
+    }
+
+    /// a single file, stored by its name alone.
+    pub fn mount_file(&mut self, file: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        let file = file.as_ref();
+        let name = file
+            .file_name()
+            .ok_or_else(|| format!("{} is not a file", file.display()))?
+            .to_string_lossy()
+            .into_owned();
+        let dir = file.parent().unwrap_or(Path::new("")).to_path_buf();
+        self.mounts.push(Mount::new(Source::Dir(dir), vec![name]));
+        Ok(())
+    }
+
+    pub fn mount_archive(&mut self, archive: Xp3) {
+        let paths = archive.entries().iter().map(|e| e.name.clone()).collect();
+        self.mounts
+            .push(Mount::new(Source::Archive(archive), paths));
+    }
+
+    /// the stored path of `name`, trying `exts` if it has no extension.
+    pub fn resolve(&self, name: &str, exts: &[&str]) -> Option<String> {
+        self.find(name, exts).map(|(_, path)| path.to_string())
+    }
+
+    /// every stored path, those shadowed by a later mount left out.
+    pub fn files(&self) -> Vec<String> {
+        let mut seen = HashSet::new();
+        let mut files = Vec::new();
+        for mount in self.mounts.iter().rev() {
+            for path in &mount.paths {
+                if seen.insert(path.to_lowercase()) {
+                    files.push(path.clone());
+                }
+            }
+        }
+        files.sort();
+        files
+    }
+
+    pub fn exists(&self, name: &str, exts: &[&str]) -> bool {
+        self.find(name, exts).is_some()
+    }
+
+    pub fn read(&self, name: &str, exts: &[&str]) -> Result<Vec<u8>, Box<dyn Error>> {
+        let (mount, path) = self
+            .find(name, exts)
+            .ok_or_else(|| NotFound(name.to_string()))?;
+        Ok(match &mount.source {
+            Source::Dir(dir) => fs::read(dir.join(path))?,
+            Source::Archive(xp3) => xp3.read(xp3.find(path).unwrap())?,
+        })
+    }
+
+    /// the asset in a form a browser understands, with its MIME type.
+    /// TLG images become PNG, everything else is passed through.
+    pub fn transcode(
+        &self,
+        name: &str,
+        exts: &[&str],
+    ) -> Result<(String, Vec<u8>), Box<dyn Error>> {
+        let path = self
+            .resolve(name, exts)
+            .ok_or_else(|| NotFound(name.to_string()))?;
+        let data = self.read(&path, &[])?;
+        let ext = extension(&path).unwrap_or_default().to_lowercase();
+        if ext == "tlg" {
+            return Ok(("image/png".to_string(), tlg::decode(&data)?.to_png()));
+        }
+        Ok((mime(&ext).to_string(), data))
+    }
+
+    fn find(&self, name: &str, exts: &[&str]) -> Option<(&Mount, &str)> {
+        let name = name
+            .trim_start_matches('/')
+            .replace('\\', "/")
+            .to_lowercase();
+        let candidates: Vec<String> = if extension(&name).is_some() || exts.is_empty() {
+            vec![name]
+        } else {
+            exts.iter().map(|ext| format!("{}.{}", name, ext)).collect()
+        };
+        self.mounts.iter().rev().find_map(|mount| {
+            candidates
+                .iter()
+                .find_map(|c| mount.names.get(c))
+                .map(|path| (mount, path.as_str()))
+        })
+    }
+}
+
+fn walk(dir: &Path, prefix: &str, paths: &mut Vec<String>) -> Result<(), Box<dyn Error>> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        if entry.file_type()?.is_dir() {
+            walk(&entry.path(), &format!("{}/", name), paths)?;
+        } else {
+            paths.push(name);
+        }
+    }
+    Ok(())
+}
+
+fn extension(path: &str) -> Option<&str> {
+    let file = path.rsplit('/').next().unwrap_or(path);
+    file.rsplit_once('.').map(|(_, ext)| ext)
+}
+
+fn mime(ext: &str) -> &'static str {
+    match ext {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "bmp" => "image/bmp",
+        "ogg" => "audio/ogg",
+        "wav" => "audio/wav",
+        "mp3" => "audio/mpeg",
+        "ks" | "tjs" | "txt" => "text/plain",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::xp3;
+
+    fn storage() -> Storage {
+        let mut s = Storage::new();
+        s.mount_archive(
+            Xp3::new(xp3::pack(&[
+                ("bgimage/Room.png", b"old room"),
+                ("bgimage/sky.jpg", b"sky"),
+            ]))
+            .unwrap(),
+        );
+        s.mount_archive(Xp3::new(xp3::pack(&[("patch/room.png", b"new room")])).unwrap());
+        s
+    }
+
+    #[test]
+    fn test_resolve() {
+        let s = storage();
+        assert_eq!(
+            s.resolve("sky", IMAGE_EXTS),
+            Some("bgimage/sky.jpg".to_string())
+        );
+        assert_eq!(
+            s.resolve("BGIMAGE/SKY.JPG", &[]),
+            Some("bgimage/sky.jpg".to_string())
+        );
+        assert!(!s.exists("sky.png", IMAGE_EXTS));
+        assert_eq!(
+            s.files(),
+            vec!["bgimage/Room.png", "bgimage/sky.jpg", "patch/room.png"]
+        );
+        // the patch mounted last shadows the original.
+        assert_eq!(s.read("room", IMAGE_EXTS).unwrap(), b"new room");
+        assert_eq!(s.read("bgimage/room.png", &[]).unwrap(), b"old room");
+        assert_eq!(
+            s.read("night", IMAGE_EXTS).unwrap_err().to_string(),
+            "storage night is not found"
+        );
+    }
+
+    #[test]
+    fn test_mount_file() {
+        let dir = std::env::temp_dir().join(format!("krkrs-mount-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("first.ks"), "a").unwrap();
+        fs::write(dir.join("sub/other.ks"), "b").unwrap();
+        let mut s = Storage::new();
+        s.mount_file(dir.join("first.ks")).unwrap();
+        assert_eq!(s.files(), vec!["first.ks"]);
+        assert_eq!(s.read("first", &["ks"]).unwrap(), b"a");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_transcode() {
+        let s = storage();
+        let (mime, data) = s.transcode("sky", IMAGE_EXTS).unwrap();
+        assert_eq!(mime, "image/jpeg");
+        assert_eq!(data, b"sky");
+    }
+}
@@ -0,0 +1,179 @@
+//! # Text
+//!
+//! Scripts come as Shift_JIS, UTF-8 or UTF-16, and games often scramble
+//! them with krkr's "simple crypt": a `FE FE` mark, a mode byte, a UTF-16
+//! BOM and the scrambled or deflated UTF-16 text.
+
+use std::{error::Error, fmt};
+
+use encoding_rs::{Encoding, SHIFT_JIS, UTF_16LE};
+
+const CRYPT_MARK: &[u8] = &[0xfe, 0xfe];
+const UTF16LE_BOM: &[u8] = &[0xff, 0xfe];
+const UTF8_BOM: &[u8] = &[0xef, 0xbb, 0xbf];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextError {
+    /// a simple crypt mode other than 0, 1 or 2.
+    Mode(u8),
+    /// the data ends before the header does.
+    Truncated,
+    /// a mode 2 text does not inflate.
+    Inflate,
+}
+
+impl fmt::Display for TextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextError::Mode(mode) => write!(f, "unknown text crypt mode {}", mode),
+            TextError::Truncated => write!(f, "text is truncated"),
+            TextError::Inflate => write!(f, "compressed text does not inflate"),
+        }
+    }
+}
+
+impl Error for TextError {}
+
+/// whether `data` is scrambled by simple crypt.
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(CRYPT_MARK)
+}
+
+/// decodes a script, unscrambling it first if needed. Text without a BOM is
+/// taken in `encoding` if given, else as UTF-8 if it is valid UTF-8 and as
+/// Shift_JIS otherwise.
+pub fn decode(data: &[u8], encoding: Option<&'static Encoding>) -> Result<String, TextError> {
+    if is_encrypted(data) {
+        return decrypt(data);
+    }
+    if let Some(utf16) = data.strip_prefix(UTF16LE_BOM) {
+        return Ok(UTF_16LE.decode_without_bom_handling(utf16).0.into_owned());
+    }
+    let data = data.strip_prefix(UTF8_BOM).unwrap_or(data);
+    if let Some(encoding) = encoding {
+        return Ok(encoding.decode_without_bom_handling(data).0.into_owned());
+    }
+    match std::str::from_utf8(data) {
+        Ok(text) => Ok(text.to_string()),
+        Err(_) => Ok(SHIFT_JIS.decode_without_bom_handling(data).0.into_owned()),
+    }
+}
+
+/// encodes a script the way `original` was, so what is not edited keeps its
+/// bytes. Scrambled scripts are written as plain UTF-16, which krkr reads as
+/// well.
+pub fn encode(text: &str, original: &[u8], encoding: Option<&'static Encoding>) -> Vec<u8> {
+    if is_encrypted(original) || original.starts_with(UTF16LE_BOM) {
+        let mut data = UTF16LE_BOM.to_vec();
+        data.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+        return data;
+    }
+    if original.starts_with(UTF8_BOM) {
+        return [UTF8_BOM, text.as_bytes()].concat();
+    }
+    match encoding {
+        Some(encoding) => encoding.encode(text).0.into_owned(),
+        None if std::str::from_utf8(original).is_ok() => text.as_bytes().to_vec(),
+        None => SHIFT_JIS.encode(text).0.into_owned(),
+    }
+}
+
+fn decrypt(data: &[u8]) -> Result<String, TextError> {
+    let header = data.get(..5).ok_or(TextError::Truncated)?;
+    let mode = header[2];
+    if &header[3..] != UTF16LE_BOM {
+        return Err(TextError::Truncated);
+    }
+    let body = &data[5..];
+    let units: Vec<u16> = match mode {
+        0 | 1 => body
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .map(|ch| match mode {
+                0 if ch >= 0x20 => ch ^ (((ch & 0xfe) << 8) ^ 1),
+                0 => ch,
+                _ => ((ch & 0xaaaa) >> 1) | ((ch & 0x5555) << 1),
+            })
+            .collect(),
+        2 => {
+            // compressed and uncompressed sizes, both u64.
+            let zlib = body.get(16..).ok_or(TextError::Truncated)?;
+            let utf16 = miniz_oxide::inflate::decompress_to_vec_zlib(zlib)
+                .map_err(|_| TextError::Inflate)?;
+            utf16
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .collect()
+        }
+        _ => return Err(TextError::Mode(mode)),
+    };
+    Ok(String::from_utf16_lossy(&units))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encrypt(mode: u8, text: &str) -> Vec<u8> {
+        let mut data = vec![0xfe, 0xfe, mode, 0xff, 0xfe];
+        let units: Vec<u16> = text.encode_utf16().collect();
+        let mut utf16 = Vec::new();
+        for ch in units {
+            let ch = match mode {
+                // the scramble of mode 0 keeps the bits it depends on.
+                0 if ch >= 0x20 => ch ^ (((ch & 0xfe) << 8) ^ 1),
+                0 => ch,
+                1 => ((ch & 0xaaaa) >> 1) | ((ch & 0x5555) << 1),
+                _ => ch,
+            };
+            utf16.extend_from_slice(&ch.to_le_bytes());
+        }
+        if mode == 2 {
+            let zlib = miniz_oxide::deflate::compress_to_vec_zlib(&utf16, 6);
+            data.extend_from_slice(&(zlib.len() as u64).to_le_bytes());
+            data.extend_from_slice(&(utf16.len() as u64).to_le_bytes());
+            data.extend_from_slice(&zlib);
+        } else {
+            data.extend_from_slice(&utf16);
+        }
+        data
+    }
+
+    #[test]
+    fn test_decrypt() {
+        let text = "*start\n[cm]始まり[p]\n";
+        for mode in 0..3 {
+            let data = encrypt(mode, text);
+            assert!(is_encrypted(&data));
+            assert_eq!(decode(&data, None).unwrap(), text);
+        }
+        assert_eq!(decode(&encrypt(3, text), None), Err(TextError::Mode(3)));
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode(b"\xef\xbb\xbf*start", None).unwrap(), "*start");
+        assert_eq!(decode(b"\xff\xfea\x00b\x00", None).unwrap(), "ab");
+        // 始まり in Shift_JIS
+        assert_eq!(decode(b"\x8en\x82\xdc\x82\xe8", None).unwrap(), "始まり");
+        assert_eq!(decode("始まり".as_bytes(), None).unwrap(), "始まり");
+        assert_eq!(decode(b"\x8en", Some(SHIFT_JIS)).unwrap(), "始");
+    }
+
+    #[test]
+    fn test_encode() {
+        for data in [
+            &b"\xef\xbb\xbf*start"[..],
+            b"\xff\xfea\x00b\x00",
+            b"\x8en\x82\xdc\x82\xe8",
+            "始まり".as_bytes(),
+        ] {
+            assert_eq!(encode(&decode(data, None).unwrap(), data, None), data);
+        }
+        let data = encrypt(1, "始まり");
+        assert_eq!(
+            encode("始まり", &data, None),
+            b"\xff\xfe\xcb\x59\x7e\x30\x8a\x30"
+        );
+    }
+}
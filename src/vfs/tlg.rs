@@ -0,0 +1,761 @@
+//! # TLG
+//!
+//! KiriKiri's own lossless image formats. TLG5 slides every color plane
+//! through LZSS, TLG6 splits the image into 8x8 blocks, predicts each pixel
+//! from its neighbours and stores the residuals with Golomb codes. Either one
+//! may be wrapped in a TLG0 "sds" container that carries a tag dictionary.
+
+use std::{collections::HashMap, error::Error, fmt, rc::Rc};
+
+use super::png;
+use crate::{
+    mkpc,
+    parsec::{
+        byte::{bytes, le_u32, take},
+        *,
+    },
+};
+
+const TLG0_MAGIC: &[u8] = b"TLG0.0\x00sds\x1a";
+const TLG5_MAGIC: &[u8] = b"TLG5.0\x00raw\x1a";
+const TLG6_MAGIC: &[u8] = b"TLG6.0\x00raw\x1a";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlgError {
+    /// not a TLG file, or a version we do not know.
+    Signature,
+    /// the data ends before the image does.
+    Truncated,
+    /// a field holds a value no encoder writes.
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for TlgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TlgError::Signature => write!(f, "not a TLG5 or TLG6 image"),
+            TlgError::Truncated => write!(f, "TLG data is truncated"),
+            TlgError::Corrupt(what) => write!(f, "TLG data is corrupt: {}", what),
+        }
+    }
+}
+
+impl Error for TlgError {}
+
+/// a decoded picture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    /// `width * height` pixels, 4 bytes each in RGBA order.
+    pub pixels: Vec<u8>,
+    /// the tag dictionary of a TLG0 container, e.g. `mode` or `offs_x`.
+    pub tags: HashMap<String, String>,
+}
+
+impl Image {
+    pub fn to_png(&self) -> Vec<u8> {
+        png::encode(self.width, self.height, &self.pixels)
+    }
+}
+
+/// decodes a `.tlg` file, wrapped or not.
+pub fn decode(data: &[u8]) -> Result<Image, TlgError> {
+    let mut input = Input::new(data);
+    if bytes(TLG0_MAGIC)(&mut input).is_err() {
+        return decode_raw(data);
+    }
+    let raw = mkpc![len <- le_u32(); take(len as usize)];
+    let mut image = decode_raw(raw(&mut input).map_err(|_| TlgError::Truncated)?)?;
+    let mut r = Reader::new(input.as_bytes());
+    while !r.is_empty() {
+        let name = r.bytes(4)?;
+        let len = r.u32()? as usize;
+        let chunk = r.bytes(len)?;
+        if name == b"tags" {
+            image.tags = parse_tags(chunk)?;
+        }
+    }
+    Ok(image)
+}
+
+fn decode_raw(data: &[u8]) -> Result<Image, TlgError> {
+    let (width, height, bgra) = if let Some(data) = data.strip_prefix(TLG5_MAGIC) {
+        decode_tlg5(Reader::new(data))?
+    } else if let Some(data) = data.strip_prefix(TLG6_MAGIC) {
+        decode_tlg6(Reader::new(data))?
+    } else {
+        return Err(TlgError::Signature);
+    };
+    let pixels = bgra
+        .iter()
+        .flat_map(|p| {
+            let [b, g, r, a] = p.to_le_bytes();
+            [r, g, b, a]
+        })
+        .collect();
+    Ok(Image {
+        width,
+        height,
+        pixels,
+        tags: HashMap::new(),
+    })
+}
+
+/// `4:mode=5:alpha,` and so on; both lengths count bytes.
+fn parse_tags(mut data: &[u8]) -> Result<HashMap<String, String>, TlgError> {
+    fn field<'a>(data: &mut &'a [u8], end: u8) -> Result<&'a str, TlgError> {
+        let colon = data
+            .iter()
+            .position(|&c| c == b':')
+            .ok_or(TlgError::Corrupt("tag length"))?;
+        let len: usize = std::str::from_utf8(&data[..colon])
+            .ok()
+            .and_then(|n| n.trim().parse().ok())
+            .ok_or(TlgError::Corrupt("tag length"))?;
+        let rest = &data[colon + 1..];
+        if rest.len() <= len || rest[len] != end {
+            return Err(TlgError::Corrupt("tag"));
+        }
+        let value = std::str::from_utf8(&rest[..len]).map_err(|_| TlgError::Corrupt("tag"))?;
+        *data = &rest[len + 1..];
+        Ok(value)
+    }
+
+    let mut tags = HashMap::new();
+    while data.iter().any(|c| !c.is_ascii_whitespace() && *c != 0) {
+        let key = field(&mut data, b'=')?;
+        let value = field(&mut data, b',')?;
+        tags.insert(key.to_string(), value.to_string());
+    }
+    Ok(tags)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], TlgError> {
+        if self.data.len() < n {
+            return Err(TlgError::Truncated);
+        }
+        let (head, tail) = self.data.split_at(n);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, TlgError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, TlgError> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+}
+
+/// the LZSS window both formats share.
+struct Slide {
+    text: [u8; 4096],
+    r: usize,
+}
+
+impl Slide {
+    fn new() -> Slide {
+        Slide {
+            text: [0; 4096],
+            r: 0,
+        }
+    }
+
+    /// the window TLG6 starts its filter types with.
+    fn for_filters() -> Slide {
+        let mut slide = Slide::new();
+        let mut p = 0;
+        for i in 0..32u8 {
+            for j in 0..16u8 {
+                slide.text[p..p + 4].fill(i);
+                slide.text[p + 4..p + 8].fill(j);
+                p += 8;
+            }
+        }
+        slide
+    }
+
+    /// inflates `input` into `out`; what does not fit is dropped.
+    fn decompress(&mut self, input: &[u8], out: &mut [u8]) -> Result<(), TlgError> {
+        let mut o = 0;
+        let mut put = |slide: &mut Slide, c: u8| {
+            if let Some(b) = out.get_mut(o) {
+                *b = c;
+            }
+            o += 1;
+            slide.text[slide.r] = c;
+            slide.r = (slide.r + 1) & 0xfff;
+        };
+        let mut i = 0;
+        let mut flags = 0u32;
+        while i < input.len() {
+            flags >>= 1;
+            if flags & 0x100 == 0 {
+                flags = input[i] as u32 | 0xff00;
+                i += 1;
+                if i == input.len() {
+                    break;
+                }
+            }
+            if flags & 1 == 0 {
+                put(self, input[i]);
+                i += 1;
+                continue;
+            }
+            let pair = input.get(i..i + 2).ok_or(TlgError::Truncated)?;
+            let mut pos = pair[0] as usize | ((pair[1] as usize & 0xf) << 8);
+            let mut len = (pair[1] as usize >> 4) + 3;
+            i += 2;
+            if len == 18 {
+                len += *input.get(i).ok_or(TlgError::Truncated)? as usize;
+                i += 1;
+            }
+            for _ in 0..len {
+                let c = self.text[pos];
+                put(self, c);
+                pos = (pos + 1) & 0xfff;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// the most pixels an image is decoded with, 8192 by 8192. A header may
+/// make up any size, and a few bytes must not allocate gigabytes.
+const MAX_PIXELS: usize = 1 << 26;
+
+/// the pixels of `a * b` for a buffer size from the header, which may be
+/// made up.
+fn size(a: usize, b: usize) -> Result<usize, TlgError> {
+    match a.checked_mul(b) {
+        Some(n) if n <= MAX_PIXELS => Ok(n),
+        _ => Err(TlgError::Corrupt("image size")),
+    }
+}
+
+fn decode_tlg5(mut r: Reader) -> Result<(u32, u32, Vec<u32>), TlgError> {
+    let colors = r.u8()? as usize;
+    if colors != 3 && colors != 4 {
+        return Err(TlgError::Corrupt("color count"));
+    }
+    let width = r.u32()?;
+    let height = r.u32()?;
+    let block_height = r.u32()? as usize;
+    if block_height == 0 {
+        return Err(TlgError::Corrupt("block height"));
+    }
+    let (w, h) = (width as usize, height as usize);
+    let pixels = size(w, h)?;
+    // the table of block sizes only helps to seek, we read blocks in order.
+    let blocks = h.div_ceil(block_height);
+    r.bytes(blocks * 4)?;
+
+    let mut slide = Slide::new();
+    let mut planes = vec![vec![0u8; size(block_height, w)?]; colors];
+    let mut out = vec![0u32; pixels];
+    for block in 0..blocks {
+        for plane in planes.iter_mut() {
+            let mark = r.u8()?;
+            let len = r.u32()? as usize;
+            let data = r.bytes(len)?;
+            if mark == 0 {
+                slide.decompress(data, plane)?;
+            } else {
+                let n = len.min(plane.len());
+                plane[..n].copy_from_slice(&data[..n]);
+            }
+        }
+        let top = block * block_height;
+        for y in top..h.min(top + block_height) {
+            let row = (y - top) * w;
+            let mut acc = [0u8; 4];
+            for x in 0..w {
+                let g = planes[1][row + x];
+                let b = planes[0][row + x].wrapping_add(g);
+                let r = planes[2][row + x].wrapping_add(g);
+                let a = if colors == 4 { planes[3][row + x] } else { 0 };
+                for (acc, d) in acc.iter_mut().zip([b, g, r, a]) {
+                    *acc = acc.wrapping_add(d);
+                }
+                let above = if y == 0 { 0 } else { out[(y - 1) * w + x] };
+                let mut p = add_bytes(above, u32::from_le_bytes(acc));
+                if colors == 3 {
+                    p |= 0xff00_0000;
+                }
+                out[y * w + x] = p;
+            }
+        }
+    }
+    Ok((width, height, out))
+}
+
+const GOLOMB_N_COUNT: usize = 4;
+const GOLOMB_COMPRESSED: [[usize; 9]; GOLOMB_N_COUNT] = [
+    [3, 7, 15, 27, 63, 108, 223, 448, 130],
+    [3, 5, 13, 24, 51, 95, 192, 384, 257],
+    [2, 5, 12, 21, 39, 86, 155, 320, 384],
+    [2, 3, 9, 18, 33, 61, 129, 258, 511],
+];
+
+/// the bit position of the lowest set bit counting from 1, 0 if `i` is 0.
+fn leading_zero(i: u32) -> u32 {
+    let i = i & 0xfff;
+    if i == 0 {
+        0
+    } else {
+        i.trailing_zeros() + 1
+    }
+}
+
+/// `k` of the Golomb code after the residuals summed up to `a`.
+fn golomb_bit_length() -> Vec<[u8; GOLOMB_N_COUNT]> {
+    let mut table = vec![[0; GOLOMB_N_COUNT]; GOLOMB_N_COUNT * 2 * 128];
+    for (n, counts) in GOLOMB_COMPRESSED.iter().enumerate() {
+        let mut a = 0;
+        for (i, &count) in counts.iter().enumerate() {
+            for _ in 0..count {
+                table[a][n] = i as u8;
+                a += 1;
+            }
+        }
+    }
+    table
+}
+
+/// reads a bit stream from least significant bit up, zeros past the end.
+struct Bits<'a> {
+    data: &'a [u8],
+    byte: usize,
+    bit: u32,
+}
+
+impl Bits<'_> {
+    fn peek(&self) -> u32 {
+        let b = |i: usize| *self.data.get(self.byte + i).unwrap_or(&0) as u32;
+        (b(0) | b(1) << 8 | b(2) << 16 | b(3) << 24) >> self.bit
+    }
+
+    fn skip(&mut self, bits: u32) -> Result<(), TlgError> {
+        self.bit += bits;
+        self.byte += (self.bit >> 3) as usize;
+        self.bit &= 7;
+        if self.byte > self.data.len() {
+            return Err(TlgError::Truncated);
+        }
+        Ok(())
+    }
+
+    /// the number of zeros before the next one bit, which is skipped too.
+    fn zeros(&mut self) -> Result<u32, TlgError> {
+        let mut count = 0;
+        loop {
+            let b = leading_zero(self.peek());
+            if b != 0 {
+                self.skip(b)?;
+                return Ok(count + b - 1);
+            }
+            count += 12;
+            self.skip(12)?;
+        }
+    }
+}
+
+/// fills byte `channel` of each pixel with the residuals of one color.
+fn decode_golomb(
+    pixels: &mut [u32],
+    channel: usize,
+    data: &[u8],
+    table: &[[u8; GOLOMB_N_COUNT]],
+) -> Result<(), TlgError> {
+    let shift = channel * 8;
+    let mut bits = Bits {
+        data,
+        byte: 0,
+        bit: 1,
+    };
+    let mut zero = data.first().is_some_and(|b| b & 1 == 0);
+    let mut n = GOLOMB_N_COUNT - 1;
+    let mut a = 0usize;
+    let mut i = 0;
+    while i < pixels.len() {
+        let width = bits.zeros()?;
+        if width >= usize::BITS {
+            return Err(TlgError::Corrupt("golomb run length"));
+        }
+        let mut count = (1usize << width) + (bits.peek() as usize & ((1 << width) - 1));
+        bits.skip(width)?;
+        count = count.min(pixels.len() - i);
+        if zero {
+            i += count;
+        } else {
+            for p in &mut pixels[i..i + count] {
+                let k = table.get(a).ok_or(TlgError::Corrupt("golomb code"))?[n] as u32;
+                let mut t = bits.peek();
+                let (ones, b) = if t != 0 {
+                    let mut ones = 0;
+                    let mut b = leading_zero(t);
+                    while b == 0 {
+                        ones += 12;
+                        bits.skip(12)?;
+                        t = bits.peek();
+                        b = leading_zero(t);
+                    }
+                    (ones + b - 1, b)
+                } else {
+                    // 32 zero bits escape a long count stored in the next byte.
+                    let ones = *data.get(bits.byte + 4).ok_or(TlgError::Truncated)? as u32;
+                    bits.byte += 5;
+                    bits.bit = 0;
+                    t = bits.peek();
+                    (ones, 0)
+                };
+                let v = ((ones as usize) << k) + ((t >> b) as usize & ((1 << k) - 1));
+                let value = if v & 1 == 1 {
+                    (v >> 1) as u8 + 1
+                } else {
+                    !((v >> 1) as u8)
+                };
+                a += v >> 1;
+                *p |= (value as u32) << shift;
+                bits.skip(b + k)?;
+                if n == 0 {
+                    a >>= 1;
+                    n = GOLOMB_N_COUNT - 1;
+                } else {
+                    n -= 1;
+                }
+            }
+            i += count;
+        }
+        zero = !zero;
+    }
+    Ok(())
+}
+
+/// byte-wise sum of two pixels.
+fn add_bytes(x: u32, y: u32) -> u32 {
+    let [a, b, c, d] = x.to_le_bytes();
+    let [e, f, g, h] = y.to_le_bytes();
+    u32::from_le_bytes([
+        a.wrapping_add(e),
+        b.wrapping_add(f),
+        c.wrapping_add(g),
+        d.wrapping_add(h),
+    ])
+}
+
+/// the median edge detector on each byte.
+fn med(left: u32, above: u32, corner: u32) -> u32 {
+    let (l, a, c) = (
+        left.to_le_bytes(),
+        above.to_le_bytes(),
+        corner.to_le_bytes(),
+    );
+    let mut p = [0u8; 4];
+    for i in 0..4 {
+        let (max, min) = (l[i].max(a[i]), l[i].min(a[i]));
+        p[i] = if c[i] >= max {
+            min
+        } else if c[i] < min {
+            max
+        } else {
+            l[i].wrapping_add(a[i]).wrapping_sub(c[i])
+        };
+    }
+    u32::from_le_bytes(p)
+}
+
+/// the rounded up mean on each byte.
+fn avg(left: u32, above: u32) -> u32 {
+    let (l, a) = (left.to_le_bytes(), above.to_le_bytes());
+    let mut p = [0u8; 4];
+    for i in 0..4 {
+        p[i] = ((l[i] as u16 + a[i] as u16 + 1) >> 1) as u8;
+    }
+    u32::from_le_bytes(p)
+}
+
+/// undoes one of the 16 color decorrelations of a residual.
+fn transform(method: u8, residual: u32) -> u32 {
+    let [mut b, mut g, mut r, a] = residual.to_le_bytes();
+    match method {
+        1 => {
+            r = r.wrapping_add(g);
+            b = b.wrapping_add(g);
+        }
+        2 => {
+            g = g.wrapping_add(b);
+            r = r.wrapping_add(g);
+        }
+        3 => {
+            g = g.wrapping_add(r);
+            b = b.wrapping_add(g);
+        }
+        4 => {
+            b = b.wrapping_add(r);
+            g = g.wrapping_add(b);
+            r = r.wrapping_add(g);
+        }
+        5 => {
+            b = b.wrapping_add(r);
+            g = g.wrapping_add(b);
+        }
+        6 => b = b.wrapping_add(g),
+        7 => g = g.wrapping_add(b),
+        8 => r = r.wrapping_add(g),
+        9 => {
+            r = r.wrapping_add(b);
+            g = g.wrapping_add(r);
+            b = b.wrapping_add(g);
+        }
+        10 => {
+            b = b.wrapping_add(r);
+            g = g.wrapping_add(r);
+        }
+        11 => {
+            r = r.wrapping_add(b);
+            g = g.wrapping_add(b);
+        }
+        12 => {
+            r = r.wrapping_add(b);
+            g = g.wrapping_add(r);
+        }
+        13 => {
+            b = b.wrapping_add(g);
+            r = r.wrapping_add(b);
+            g = g.wrapping_add(r);
+        }
+        14 => {
+            g = g.wrapping_add(r);
+            b = b.wrapping_add(g);
+            r = r.wrapping_add(b);
+        }
+        15 => {
+            g = g.wrapping_add(b << 1);
+            r = r.wrapping_add(b << 1);
+        }
+        _ => {}
+    }
+    u32::from_le_bytes([b, g, r, a])
+}
+
+const BLOCK: usize = 8;
+
+fn decode_tlg6(mut r: Reader) -> Result<(u32, u32, Vec<u32>), TlgError> {
+    let colors = r.u8()? as usize;
+    if colors != 1 && colors != 3 && colors != 4 {
+        return Err(TlgError::Corrupt("color count"));
+    }
+    let (data_flag, color_type, external_table) = (r.u8()?, r.u8()?, r.u8()?);
+    if data_flag != 0 || color_type != 0 || external_table != 0 {
+        return Err(TlgError::Corrupt("unsupported flags"));
+    }
+    let width = r.u32()?;
+    let height = r.u32()?;
+    let _max_bit_length = r.u32()?;
+    let (w, h) = (width as usize, height as usize);
+    let pixels = size(w, h)?;
+    let x_blocks = w.div_ceil(BLOCK);
+    let y_blocks = h.div_ceil(BLOCK);
+
+    let len = r.u32()? as usize;
+    let mut filters = vec![0u8; x_blocks * y_blocks];
+    Slide::for_filters().decompress(r.bytes(len)?, &mut filters)?;
+
+    let table = golomb_bit_length();
+    let initial = if colors == 3 { 0xff00_0000 } else { 0 };
+    let zero_line = vec![initial; w];
+    let mut out = vec![0u32; pixels];
+    let mut residuals = vec![0u32; size(BLOCK, w)?];
+    for top in (0..h).step_by(BLOCK) {
+        let bottom = h.min(top + BLOCK);
+        let rows = bottom - top;
+        let residuals = &mut residuals[..rows * w];
+        residuals.fill(0);
+        for channel in 0..colors {
+            let bit_length = r.u32()?;
+            if (bit_length >> 30) & 3 != 0 {
+                return Err(TlgError::Corrupt("entropy coding method"));
+            }
+            let bit_length = (bit_length & 0x3fff_ffff) as usize;
+            let data = r.bytes(bit_length.div_ceil(8))?;
+            decode_golomb(residuals, channel, data, &table)?;
+        }
+
+        let filters = &filters[top / BLOCK * x_blocks..];
+        let block_size = rows * BLOCK;
+        for y in top..bottom {
+            let (done, rest) = out.split_at_mut(y * w);
+            let above = if y == 0 {
+                &zero_line[..]
+            } else {
+                &done[(y - 1) * w..]
+            };
+            let line = &mut rest[..w];
+            let forward = y & 1 == 0;
+            let odd_skip = (bottom - y - 1) as isize - (y - top) as isize;
+
+            let (mut left, mut corner) = (initial, initial);
+            for (block, &filter) in filters.iter().enumerate().take(x_blocks) {
+                let x0 = block * BLOCK;
+                let bw = (w - x0).min(BLOCK);
+                // blocks are laid out one after another, odd ones upside down.
+                let row = if block & 1 == 1 {
+                    (y - top) as isize + odd_skip
+                } else {
+                    (y - top) as isize
+                } as usize;
+                let start = block * block_size + row * bw;
+                for i in 0..bw {
+                    let x = x0 + i;
+                    let index = if forward {
+                        start + i
+                    } else {
+                        start + bw - 1 - i
+                    };
+                    let residual = transform(filter >> 1, residuals[index]);
+                    let up = above[x];
+                    let predicted = if filter & 1 == 0 {
+                        med(left, up, corner)
+                    } else {
+                        avg(left, up)
+                    };
+                    left = add_bytes(predicted, residual);
+                    corner = up;
+                    line[x] = left;
+                }
+            }
+            if colors == 1 {
+                for p in line.iter_mut() {
+                    let v = *p & 0xff;
+                    *p = 0xff00_0000 | v << 16 | v << 8 | v;
+                }
+            }
+        }
+    }
+    Ok((width, height, out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(magic: &[u8], fields: &[u8], width: u32, height: u32, extra: u32) -> Vec<u8> {
+        let mut v = magic.to_vec();
+        v.extend_from_slice(fields);
+        for n in [width, height, extra] {
+            v.extend_from_slice(&n.to_le_bytes());
+        }
+        v
+    }
+
+    #[test]
+    fn test_slide() {
+        let mut slide = Slide::new();
+        let mut out = [0u8; 8];
+        // "ab" as literals, then copy 6 bytes from the start of the window.
+        slide
+            .decompress(&[0b100, b'a', b'b', 0x00, 0x30], &mut out)
+            .unwrap();
+        assert_eq!(&out, b"abababab");
+    }
+
+    #[test]
+    fn test_tlg5() {
+        // 2x2 with raw planes; green is added to blue and red, then each
+        // row sums up left to right and onto the row above.
+        let mut data = header(TLG5_MAGIC, &[3], 2, 2, 2);
+        data.extend_from_slice(&[0; 4]);
+        for plane in [[1u8, 1, 0, 0], [10, 0, 0, 1], [2, 0, 0, 0]] {
+            data.push(1);
+            data.extend_from_slice(&4u32.to_le_bytes());
+            data.extend_from_slice(&plane);
+        }
+        let image = decode(&data).unwrap();
+        assert_eq!((image.width, image.height), (2, 2));
+        assert_eq!(
+            image.pixels,
+            vec![
+                12, 10, 11, 255, 12, 10, 12, 255, //
+                12, 10, 11, 255, 13, 11, 13, 255,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tlg6() {
+        let mut data = header(TLG6_MAGIC, &[3, 0, 0, 0], 1, 1, 12);
+        // one filter type: MED prediction, no color transform.
+        data.extend_from_slice(&2u32.to_le_bytes());
+        data.extend_from_slice(&[0, 0]);
+        // residuals 5, 1 and -1 for blue, green and red.
+        for (bits, code) in [(12u32, &[0x03u8, 0x08][..]), (4, &[0x0b]), (3, &[0x07])] {
+            data.extend_from_slice(&bits.to_le_bytes());
+            data.extend_from_slice(code);
+        }
+        let image = decode(&data).unwrap();
+        assert_eq!(image.pixels, vec![255, 1, 5, 255]);
+    }
+
+    #[test]
+    fn test_tlg0() {
+        let mut raw = header(TLG5_MAGIC, &[3], 1, 1, 1);
+        raw.extend_from_slice(&[0; 4]);
+        for _ in 0..3 {
+            raw.extend_from_slice(&[1, 1, 0, 0, 0, 0]);
+        }
+        let tags = b"4:mode=5:alpha,6:offs_x=2:10,";
+        let mut data = TLG0_MAGIC.to_vec();
+        data.extend_from_slice(&(raw.len() as u32).to_le_bytes());
+        data.extend_from_slice(&raw);
+        data.extend_from_slice(b"tags");
+        data.extend_from_slice(&(tags.len() as u32).to_le_bytes());
+        data.extend_from_slice(tags);
+
+        let image = decode(&data).unwrap();
+        assert_eq!(image.pixels, vec![0, 0, 0, 255]);
+        assert_eq!(image.tags["mode"], "alpha");
+        assert_eq!(image.tags["offs_x"], "10");
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(decode(b"PNG"), Err(TlgError::Signature));
+        let data = header(TLG5_MAGIC, &[3], 4, 4, 4);
+        assert_eq!(decode(&data), Err(TlgError::Truncated));
+        let data = header(TLG5_MAGIC, &[3], u32::MAX, u32::MAX, 1);
+        assert_eq!(decode(&data), Err(TlgError::Corrupt("image size")));
+        // a few bytes that claim 65535 by 65535 pixels.
+        let data = header(TLG5_MAGIC, &[3], 65535, 65535, 4);
+        assert_eq!(decode(&data), Err(TlgError::Corrupt("image size")));
+        let data = header(TLG6_MAGIC, &[3, 0, 0, 0], 65535, 65535, 12);
+        assert_eq!(decode(&data), Err(TlgError::Corrupt("image size")));
+        let mut data = header(TLG5_MAGIC, &[3], 1, 1, u32::MAX);
+        data.extend_from_slice(&[0; 4]);
+        assert_eq!(decode(&data), Err(TlgError::Corrupt("image size")));
+
+        // a run length of more than 64 bits.
+        let mut data = header(TLG6_MAGIC, &[3, 0, 0, 0], 1, 1, 12);
+        data.extend_from_slice(&2u32.to_le_bytes());
+        data.extend_from_slice(&[0, 0]);
+        data.extend_from_slice(&80u32.to_le_bytes());
+        data.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(decode(&data), Err(TlgError::Corrupt("golomb run length")));
+    }
+}
@@ -0,0 +1,358 @@
+//! # XP3
+//!
+//! KiriKiri's archive format. A header points at an index, usually zlib
+//! compressed, that lists every file with its name and segments; segments
+//! are runs of the archive that are stored raw or zlib compressed.
+
+use std::{collections::HashSet, convert::TryFrom, error::Error, fmt, fs, path::Path, rc::Rc};
+
+use crate::{
+    mkpc,
+    parsec::{
+        byte::{bytes, le_u64},
+        *,
+    },
+};
+
+const MAGIC: &[u8] = b"XP3\r\n \n\x1a\x8b\x67\x01";
+
+/// the index is zlib compressed.
+const INDEX_ZLIB: u8 = 1;
+/// the index only points at the real one, written by krkr 2.29 and later.
+const INDEX_CONTINUE: u8 = 0x80;
+/// the segment is zlib compressed.
+const SEGMENT_ZLIB: u32 = 1;
+/// the author asked not to extract the file.
+const FILE_PROTECTED: u32 = 1 << 31;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Xp3Error {
+    /// not an XP3 archive.
+    Signature,
+    /// the data ends before the archive does.
+    Truncated,
+    /// a zlib stream does not inflate.
+    Inflate,
+    /// a field holds a value no packer writes.
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for Xp3Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Xp3Error::Signature => write!(f, "not an XP3 archive"),
+            Xp3Error::Truncated => write!(f, "XP3 archive is truncated"),
+            Xp3Error::Inflate => write!(f, "XP3 archive holds a broken zlib stream"),
+            Xp3Error::Corrupt(what) => write!(f, "XP3 archive is corrupt: {}", what),
+        }
+    }
+}
+
+impl Error for Xp3Error {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Segment {
+    compressed: bool,
+    offset: u64,
+    size: u64,
+    packed: u64,
+}
+
+/// a file in an archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// the path inside the archive, with `/` between directories.
+    pub name: String,
+    pub size: u64,
+    /// bytes taken in the archive.
+    pub packed: u64,
+    pub protected: bool,
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug)]
+pub struct Xp3 {
+    data: Vec<u8>,
+    entries: Vec<Entry>,
+}
+
+impl Xp3 {
+    pub fn open(path: impl AsRef<Path>) -> Result<Xp3, Box<dyn Error>> {
+        Ok(Xp3::new(fs::read(path)?)?)
+    }
+
+    pub fn new(data: Vec<u8>) -> Result<Xp3, Xp3Error> {
+        let header = mkpc![bytes(MAGIC); offset <- le_u64(); return offset];
+        let mut offset = run_parser_bytes(header, &data).map_err(|e| {
+            if e.location().offset < MAGIC.len() {
+                Xp3Error::Signature
+            } else {
+                Xp3Error::Truncated
+            }
+        })?;
+        let mut visited = HashSet::new();
+        let index = loop {
+            let at = usize::try_from(offset).map_err(|_| Xp3Error::Truncated)?;
+            // an index continued at itself or an earlier one never ends.
+            if !visited.insert(at) {
+                return Err(Xp3Error::Corrupt("index loop"));
+            }
+            let flag = *data.get(at).ok_or(Xp3Error::Truncated)?;
+            if flag & INDEX_CONTINUE != 0 {
+                offset = u64_at(&data, at + 9)?;
+                continue;
+            }
+            break if flag & INDEX_ZLIB != 0 {
+                let packed = usize_at(&data, at + 1)?;
+                let zlib = slice(&data, at + 17, packed)?;
+                inflate(zlib)?
+            } else {
+                let size = usize_at(&data, at + 1)?;
+                slice(&data, at + 9, size)?.to_vec()
+            };
+        };
+        let entries = chunks(&index)?
+            .filter(|(name, _)| name == b"File")
+            .map(|(_, file)| parse_entry(file))
+            .collect::<Result<_, _>>()?;
+        Ok(Xp3 { data, entries })
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// the entry called `name`, ignoring case.
+    pub fn find(&self, name: &str) -> Option<&Entry> {
+        self.entries
+            .iter()
+            .find(|e| e.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn read(&self, entry: &Entry) -> Result<Vec<u8>, Xp3Error> {
+        // the size in the entry is not checked, it may be made up.
+        let mut out = Vec::new();
+        for s in &entry.segments {
+            let offset = usize::try_from(s.offset).map_err(|_| Xp3Error::Truncated)?;
+            let packed = usize::try_from(s.packed).map_err(|_| Xp3Error::Truncated)?;
+            let data = slice(&self.data, offset, packed)?;
+            if s.compressed {
+                out.extend_from_slice(&inflate(data)?);
+            } else {
+                out.extend_from_slice(data);
+            }
+        }
+        Ok(out)
+    }
+}
+
+fn slice(data: &[u8], at: usize, len: usize) -> Result<&[u8], Xp3Error> {
+    data.get(at..at.checked_add(len).ok_or(Xp3Error::Truncated)?)
+        .ok_or(Xp3Error::Truncated)
+}
+
+fn u64_at(data: &[u8], at: usize) -> Result<u64, Xp3Error> {
+    let b = slice(data, at, 8)?;
+    Ok(u64::from_le_bytes([
+        b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7],
+    ]))
+}
+
+/// a length or offset, which does not fit a 32-bit `usize` in a made-up
+/// archive.
+fn usize_at(data: &[u8], at: usize) -> Result<usize, Xp3Error> {
+    usize::try_from(u64_at(data, at)?).map_err(|_| Xp3Error::Truncated)
+}
+
+fn u32_at(data: &[u8], at: usize) -> Result<u32, Xp3Error> {
+    let b = slice(data, at, 4)?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn inflate(data: &[u8]) -> Result<Vec<u8>, Xp3Error> {
+    miniz_oxide::inflate::decompress_to_vec_zlib(data).map_err(|_| Xp3Error::Inflate)
+}
+
+/// the `name, u64 size, data` chunks the index is made of.
+fn chunks(mut data: &[u8]) -> Result<impl Iterator<Item = (&[u8], &[u8])>, Xp3Error> {
+    let mut found = Vec::new();
+    while !data.is_empty() {
+        let name = slice(data, 0, 4)?;
+        let size = usize_at(data, 4)?;
+        found.push((name, slice(data, 12, size)?));
+        data = &data[12 + size..];
+    }
+    Ok(found.into_iter())
+}
+
+fn parse_entry(file: &[u8]) -> Result<Entry, Xp3Error> {
+    let mut entry = None;
+    let mut segments = Vec::new();
+    for (name, chunk) in chunks(file)? {
+        match name {
+            b"info" => {
+                let flags = u32_at(chunk, 0)?;
+                let len = slice(chunk, 20, 2).map(|b| u16::from_le_bytes([b[0], b[1]]))? as usize;
+                let utf16: Vec<u16> = slice(chunk, 22, len * 2)?
+                    .chunks(2)
+                    .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                    .collect();
+                let name = String::from_utf16(&utf16)
+                    .map_err(|_| Xp3Error::Corrupt("file name"))?
+                    .replace('\\', "/");
+                entry = Some(Entry {
+                    name,
+                    size: u64_at(chunk, 4)?,
+                    packed: u64_at(chunk, 12)?,
+                    protected: flags & FILE_PROTECTED != 0,
+                    segments: Vec::new(),
+                });
+            }
+            b"segm" => {
+                for s in chunk.chunks(28) {
+                    segments.push(Segment {
+                        compressed: u32_at(s, 0)? & 7 == SEGMENT_ZLIB,
+                        offset: u64_at(s, 4)?,
+                        size: u64_at(s, 12)?,
+                        packed: u64_at(s, 20)?,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    let mut entry = entry.ok_or(Xp3Error::Corrupt("file without info"))?;
+    let size = segments
+        .iter()
+        .try_fold(0u64, |sum, s| sum.checked_add(s.size));
+    if size != Some(entry.size) {
+        return Err(Xp3Error::Corrupt("segment sizes"));
+    }
+    entry.segments = segments;
+    Ok(entry)
+}
+
+/// packs `files` the way krkr 2.29 does, compressing every other one.
+#[cfg(test)]
+pub fn pack(files: &[(&str, &[u8])]) -> Vec<u8> {
+    fn chunk(out: &mut Vec<u8>, name: &[u8], data: &[u8]) {
+        out.extend_from_slice(name);
+        out.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        out.extend_from_slice(data);
+    }
+
+    let mut data = MAGIC.to_vec();
+    data.extend_from_slice(&0x17u64.to_le_bytes());
+    data.extend_from_slice(&1u32.to_le_bytes());
+    data.push(INDEX_CONTINUE);
+    data.extend_from_slice(&0u64.to_le_bytes());
+    let pointer = data.len();
+    data.extend_from_slice(&0u64.to_le_bytes());
+
+    let mut index = Vec::new();
+    for (i, (name, content)) in files.iter().enumerate() {
+        let compressed = i % 2 == 1;
+        let stored = if compressed {
+            miniz_oxide::deflate::compress_to_vec_zlib(content, 6)
+        } else {
+            content.to_vec()
+        };
+        let offset = data.len() as u64;
+        data.extend_from_slice(&stored);
+
+        let utf16: Vec<u16> = name.encode_utf16().collect();
+        let mut info = Vec::new();
+        info.extend_from_slice(&0u32.to_le_bytes());
+        info.extend_from_slice(&(content.len() as u64).to_le_bytes());
+        info.extend_from_slice(&(stored.len() as u64).to_le_bytes());
+        info.extend_from_slice(&(utf16.len() as u16).to_le_bytes());
+        for c in utf16 {
+            info.extend_from_slice(&c.to_le_bytes());
+        }
+        let mut segm = Vec::new();
+        segm.extend_from_slice(&(compressed as u32).to_le_bytes());
+        segm.extend_from_slice(&offset.to_le_bytes());
+        segm.extend_from_slice(&(content.len() as u64).to_le_bytes());
+        segm.extend_from_slice(&(stored.len() as u64).to_le_bytes());
+
+        let mut file = Vec::new();
+        chunk(&mut file, b"info", &info);
+        chunk(&mut file, b"segm", &segm);
+        chunk(&mut file, b"adlr", &[0; 4]);
+        chunk(&mut index, b"File", &file);
+    }
+
+    let at = data.len() as u64;
+    data[pointer..pointer + 8].copy_from_slice(&at.to_le_bytes());
+    let packed = miniz_oxide::deflate::compress_to_vec_zlib(&index, 6);
+    data.push(INDEX_ZLIB);
+    data.extend_from_slice(&(packed.len() as u64).to_le_bytes());
+    data.extend_from_slice(&(index.len() as u64).to_le_bytes());
+    data.extend_from_slice(&packed);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xp3() {
+        let xp3 = Xp3::new(pack(&[
+            ("startup.tjs", b"Scripts.execStorage(\"first.ks\");"),
+            ("scenario/first.ks", "*start\n最初の一文[p]\n".as_bytes()),
+        ]))
+        .unwrap();
+        let names: Vec<_> = xp3.entries().iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["startup.tjs", "scenario/first.ks"]);
+
+        let ks = xp3.find("Scenario/First.ks").unwrap();
+        assert_eq!(ks.size, "*start\n最初の一文[p]\n".len() as u64);
+        assert_eq!(xp3.read(ks).unwrap(), "*start\n最初の一文[p]\n".as_bytes());
+        assert!(xp3.find("first.ks").is_none());
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            Xp3::new(b"PK\x03\x04".to_vec()).unwrap_err(),
+            Xp3Error::Signature
+        );
+        let mut data = pack(&[("a.txt", b"a")]);
+        data.truncate(data.len() - 4);
+        assert_eq!(Xp3::new(data).unwrap_err(), Xp3Error::Truncated);
+
+        let at = (MAGIC.len() + 8) as u64;
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&at.to_le_bytes());
+        data.push(INDEX_CONTINUE);
+        data.extend_from_slice(&[0; 8]);
+        data.extend_from_slice(&at.to_le_bytes());
+        assert_eq!(Xp3::new(data).unwrap_err(), Xp3Error::Corrupt("index loop"));
+
+        // segments whose sizes add up past u64::MAX.
+        let chunk = |name: &[u8], body: &[u8]| {
+            let mut c = name.to_vec();
+            c.extend_from_slice(&(body.len() as u64).to_le_bytes());
+            c.extend_from_slice(body);
+            c
+        };
+        let mut info = vec![0; 20];
+        info.extend_from_slice(&[1, 0, b'a', 0]);
+        let mut segment = vec![0; 12];
+        segment.extend_from_slice(&u64::MAX.to_le_bytes());
+        segment.extend_from_slice(&[0; 8]);
+        let mut file = chunk(b"info", &info);
+        file.extend(chunk(b"segm", &segment.repeat(2)));
+        let index = chunk(b"File", &file);
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&at.to_le_bytes());
+        data.push(0);
+        data.extend_from_slice(&(index.len() as u64).to_le_bytes());
+        data.extend(index);
+        assert_eq!(
+            Xp3::new(data).unwrap_err(),
+            Xp3Error::Corrupt("segment sizes")
+        );
+    }
+}